@@ -28,8 +28,8 @@ pub enum QemuExitCode {
     Err = 0x11,
 }
 
-pub fn poweroff() {
-    exit(QemuExitCode::Ok);
+pub fn poweroff() -> ! {
+    std::os::shutdown()
 }
 
 pub fn exit(code: QemuExitCode) {
@@ -38,7 +38,7 @@ fn main(boot_info: &'static BootInfo) -> ! {
 
     // runs the 'mainloop' of the OS;
     let mut executor = Executor::new();
-    executor.spawn(Task::new(shell::command_handler()));
+    executor.spawn(Task::new("shell", shell::command_handler()));
     loop {
         executor.try_run();
     }
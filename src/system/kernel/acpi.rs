@@ -0,0 +1,350 @@
+use alloc::vec::Vec;
+use conquer_once::spin::OnceCell;
+use core::fmt;
+use x86_64::PhysAddr;
+
+use super::memory::phys_to_virt;
+
+static TABLES: OnceCell<Vec<Table>> = OnceCell::uninit();
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+const HEADER_SIZE: usize = 36;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AcpiError {
+    /// no root pointer in the BIOS areas, e.g. when booted without ACPI
+    NoRsdp,
+    BadChecksum([u8; 4]),
+    AlreadyInitialised,
+}
+
+impl fmt::Display for AcpiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AcpiError::NoRsdp => write!(f, "no ACPI root table found"),
+            AcpiError::BadChecksum(sig) => write!(
+                f,
+                "ACPI table {} has a bad checksum",
+                core::str::from_utf8(sig).unwrap_or("????")
+            ),
+            AcpiError::AlreadyInitialised => write!(f, "ACPI has already been initialised"),
+        }
+    }
+}
+
+/// a system description table found through the RSDT or XSDT
+#[derive(Debug, Clone, Copy)]
+pub struct Table {
+    pub signature: [u8; 4],
+    pub address: PhysAddr,
+    pub length: u32,
+}
+
+impl Table {
+    fn at(address: PhysAddr) -> Table {
+        Table {
+            signature: read(address),
+            address,
+            length: read(address + 4u64),
+        }
+    }
+
+    /// the whole table, header included
+    pub fn data(&self) -> &'static [u8] {
+        unsafe {
+            core::slice::from_raw_parts(
+                phys_to_virt(self.address).as_ptr::<u8>(),
+                self.length as usize,
+            )
+        }
+    }
+}
+
+fn read<T: Copy>(addr: PhysAddr) -> T {
+    unsafe { phys_to_virt(addr).as_ptr::<T>().read_unaligned() }
+}
+
+fn checksum_ok(addr: PhysAddr, len: usize) -> bool {
+    (0..len as u64).fold(0u8, |sum, i| sum.wrapping_add(read::<u8>(addr + i))) == 0
+}
+
+/// the root pointer sits on a 16 byte boundary in the first KiB of the EBDA or in the BIOS
+/// area between 0xE0000 and 0x100000
+fn find_rsdp() -> Option<PhysAddr> {
+    let ebda = (read::<u16>(PhysAddr::new(0x40E)) as u64) << 4;
+    [(ebda, ebda + 1024), (0xE0000, 0x100000)]
+        .iter()
+        .flat_map(|&(start, end)| (start..end).step_by(16))
+        .map(PhysAddr::new)
+        .find(|&addr| read::<[u8; 8]>(addr) == *RSDP_SIGNATURE && checksum_ok(addr, 20))
+}
+
+/// finds every table listed by the RSDT, or the XSDT on ACPI 2.0 and later
+pub fn init() -> Result<usize, AcpiError> {
+    let rsdp = find_rsdp().ok_or(AcpiError::NoRsdp)?;
+    let revision = read::<u8>(rsdp + 15u64);
+    let xsdt = read::<u64>(rsdp + 24u64);
+    let (root, entry_size) = if revision >= 2 && xsdt != 0 {
+        (PhysAddr::new(xsdt), 8)
+    } else {
+        (PhysAddr::new(read::<u32>(rsdp + 16u64) as u64), 4)
+    };
+
+    let root_length = read::<u32>(root + 4u64) as usize;
+    if !checksum_ok(root, root_length) {
+        return Err(AcpiError::BadChecksum(read(root)));
+    }
+
+    let mut tables = Vec::new();
+    for offset in (HEADER_SIZE..root_length).step_by(entry_size) {
+        let address = match entry_size {
+            8 => read::<u64>(root + offset as u64),
+            _ => read::<u32>(root + offset as u64) as u64,
+        };
+        let table = Table::at(PhysAddr::new(address));
+        // a broken table is left out rather than taking every other one down with it
+        if checksum_ok(table.address, table.length as usize) {
+            tables.push(table);
+        }
+    }
+
+    let count = tables.len();
+    TABLES
+        .try_init_once(|| tables)
+        .map_err(|_| AcpiError::AlreadyInitialised)?;
+    Ok(count)
+}
+
+/// every table that was found, empty before `init` or without ACPI
+pub fn tables() -> &'static [Table] {
+    TABLES.try_get().map_or(&[], |t| t.as_slice())
+}
+
+pub fn find(signature: &[u8; 4]) -> Option<Table> {
+    tables().iter().find(|t| &t.signature == signature).copied()
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IoApicEntry {
+    pub address: PhysAddr,
+    /// the first global system interrupt this IOAPIC handles
+    pub gsi_base: u32,
+}
+
+/// an ISA irq that is not wired to the global system interrupt of the same number
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    /// MPS INTI flags, polarity in bits 0-1 and trigger mode in bits 2-3
+    pub flags: u16,
+}
+
+/// the interrupt controllers described by the MADT ("APIC" table)
+#[derive(Debug, Clone)]
+pub struct Madt {
+    pub local_apic: PhysAddr,
+    /// true if the machine also has 8259 PICs that need masking
+    pub legacy_pics: bool,
+    /// local APIC ids of the enabled processors
+    pub processors: Vec<u8>,
+    pub io_apics: Vec<IoApicEntry>,
+    pub overrides: Vec<InterruptOverride>,
+}
+
+pub fn madt() -> Option<Madt> {
+    let data = find(b"APIC")?.data();
+    let mut madt = Madt {
+        local_apic: PhysAddr::new(u32_at(data, 36) as u64),
+        legacy_pics: u32_at(data, 40) & 1 != 0,
+        processors: Vec::new(),
+        io_apics: Vec::new(),
+        overrides: Vec::new(),
+    };
+
+    let mut offset = 44;
+    while offset + 2 <= data.len() {
+        let (kind, len) = (data[offset], data[offset + 1] as usize);
+        if len < 2 || offset + len > data.len() {
+            break;
+        }
+        let entry = &data[offset..offset + len];
+        match (kind, len) {
+            // processor local APIC, bit 0 of the flags says it is enabled
+            (0, 8) if u32_at(entry, 4) & 1 != 0 => madt.processors.push(entry[3]),
+            (1, 12) => madt.io_apics.push(IoApicEntry {
+                address: PhysAddr::new(u32_at(entry, 4) as u64),
+                gsi_base: u32_at(entry, 8),
+            }),
+            (2, 10) => madt.overrides.push(InterruptOverride {
+                irq: entry[3],
+                gsi: u32_at(entry, 4),
+                flags: u16_at(entry, 8),
+            }),
+            // 64 bit local APIC address override
+            (5, 12) => madt.local_apic = PhysAddr::new(u64_at(entry, 4)),
+            _ => {}
+        }
+        offset += len;
+    }
+    Some(madt)
+}
+
+/// a register described by an ACPI generic address structure
+#[derive(Debug, Clone, Copy)]
+pub struct GenericAddress {
+    /// 0 for memory, 1 for io ports, 2 for PCI configuration space
+    pub space: u8,
+    pub address: u64,
+}
+
+impl GenericAddress {
+    fn parse(data: &[u8], offset: usize) -> GenericAddress {
+        GenericAddress {
+            space: data[offset],
+            address: u64_at(data, offset + 4),
+        }
+    }
+}
+
+/// the parts of the fixed ACPI description table ("FACP") the kernel uses
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    pub dsdt: PhysAddr,
+    /// port that switches the firmware into ACPI mode when sent `acpi_enable`, 0 if it always is
+    pub smi_command: u32,
+    pub acpi_enable: u8,
+    pub pm1a_control: u16,
+    pub pm1b_control: u16,
+    /// the CMOS register that holds the century, 0 if there isn't one
+    pub century: u8,
+    /// where to write which value to reset the machine, if the firmware supports it
+    pub reset: Option<(GenericAddress, u8)>,
+}
+
+const FADT_RESET_REG_SUPPORTED: u32 = 1 << 10;
+
+pub fn fadt() -> Option<Fadt> {
+    let data = find(b"FACP")?.data();
+    // ACPI 2.0 and later can put the DSDT above 4 GiB
+    let dsdt = match data.len() >= 148 {
+        true if u64_at(data, 140) != 0 => u64_at(data, 140),
+        _ => u32_at(data, 40) as u64,
+    };
+    let reset_supported = data.len() > 128 && u32_at(data, 112) & FADT_RESET_REG_SUPPORTED != 0;
+    Some(Fadt {
+        dsdt: PhysAddr::new(dsdt),
+        smi_command: u32_at(data, 48),
+        acpi_enable: data[52],
+        pm1a_control: u32_at(data, 64) as u16,
+        pm1b_control: u32_at(data, 68) as u16,
+        century: data[108],
+        reset: match reset_supported {
+            true => Some((GenericAddress::parse(data, 116), data[128])),
+            false => None,
+        },
+    })
+}
+
+/// the high precision event timer ("HPET" table)
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    pub address: PhysAddr,
+}
+
+pub fn hpet() -> Option<Hpet> {
+    let data = find(b"HPET")?.data();
+    let address = GenericAddress::parse(data, 40);
+    // only ever seen in memory space
+    if address.space != 0 {
+        return None;
+    }
+    Some(Hpet {
+        address: PhysAddr::new(address.address),
+    })
+}
+
+/// the SLP_TYPa and SLP_TYPb values for the S5 (soft off) state. they live in the `_S5_`
+/// package of the DSDT, which is AML, so this only understands the simple encodings firmware
+/// actually uses rather than running an interpreter.
+pub fn s5_sleep_types() -> Option<(u16, u16)> {
+    let dsdt = Table::at(fadt()?.dsdt);
+    if !checksum_ok(dsdt.address, dsdt.length as usize) {
+        return None;
+    }
+    s5_package(dsdt.data())
+}
+
+/// finds the `_S5_` package in AML and reads its first two elements
+fn s5_package(data: &[u8]) -> Option<(u16, u16)> {
+    let name = data.windows(4).position(|w| w == b"_S5_")?;
+    let before = |n| name.checked_sub(n).and_then(|j| data.get(j).copied());
+
+    // NameOp, possibly followed by a root prefix, then PackageOp
+    let named = before(1) == Some(0x08) || (before(2) == Some(0x08) && before(1) == Some(b'\\'));
+    let mut i = name + 4;
+    if !named || *data.get(i)? != 0x12 {
+        return None;
+    }
+    // skip PackageOp, the package length (whose top two bits say how many bytes follow) and
+    // the element count
+    i += 1;
+    i += (*data.get(i)? >> 6) as usize + 1;
+    i += 1;
+
+    let mut element = || {
+        let value = match *data.get(i)? {
+            // ZeroOp and OneOp
+            0x00 => 0,
+            0x01 => 1,
+            // BytePrefix
+            0x0A => {
+                i += 1;
+                *data.get(i)?
+            }
+            // anything bigger (WordPrefix and so on) isn't a sleep type we know how to use
+            _ => return None,
+        };
+        i += 1;
+        Some(value as u16)
+    };
+    Some((element()?, element()?))
+}
+
+#[test_case]
+fn s5_package_elements_are_decoded() {
+    // Name (_S5_, Package (4) { 0x05, Zero, ... })
+    let aml = [
+        0x08, b'_', b'S', b'5', b'_', 0x12, 0x08, 0x04, 0x0A, 0x05, 0x00, 0x00, 0x00,
+    ];
+    assert_eq!(s5_package(&aml), Some((5, 0)));
+    // with a root prefix and OneOp
+    let aml = [
+        0x08, b'\\', b'_', b'S', b'5', b'_', 0x12, 0x06, 0x02, 0x01, 0x0A, 0x07,
+    ];
+    assert_eq!(s5_package(&aml), Some((1, 7)));
+    // WordPrefix
+    let aml = [
+        0x08, b'_', b'S', b'5', b'_', 0x12, 0x07, 0x02, 0x0B, 0x05, 0x00, 0x00,
+    ];
+    assert_eq!(s5_package(&aml), None);
+    // cut off in the package length, or with nothing before the name
+    assert_eq!(s5_package(&[0x08, b'_', b'S', b'5', b'_', 0x12]), None);
+    assert_eq!(s5_package(b"_S5_"), None);
+}
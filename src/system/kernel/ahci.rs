@@ -1,6 +1,15 @@
-use alloc::vec::Vec;
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use core::mem::size_of;
+use core::sync::atomic::{fence, Ordering};
+use lazy_static::lazy_static;
+use spin::Mutex;
 use volatile::Volatile;
+use x86_64::structures::paging::PhysFrame;
+use x86_64::{PhysAddr, VirtAddr};
 
+use super::block::{self, BlockCache, BlockDevice, BlockError, DEFAULT_CACHE_BLOCKS};
+use super::memory::{alloc_zeroed_frames, free_frames, map_mmio, phys_to_virt};
+use super::pci::{Bar, PciDevice};
 use crate::println;
 
 pub const ATA_CMD_IDENTIFY: u8 = 0xEC;
@@ -8,87 +17,87 @@ pub const ATA_CMD_IDENTIFY: u8 = 0xEC;
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FisType {
-    RegH2D = 0x27,    // Register FIS - host to device
-    RegD2H = 0x34,    // Register FIS - device to host
-    DmaAct = 0x39,    // DMA activate FIS - device to host
-    DmaSetup = 0x41,  // DMA setup FIS - bidirectional
-    Data = 0x46,      // Data FIS - bidirectional
-    Bist = 0x58,      // BIST activate FIS - bidirectional
-    PioSetup = 0x5F,  // PIO setup FIS - device to host
-    DevBits = 0xA1,   // Set device bits FIS - device to host
+    RegH2D = 0x27,   // Register FIS - host to device
+    RegD2H = 0x34,   // Register FIS - device to host
+    DmaAct = 0x39,   // DMA activate FIS - device to host
+    DmaSetup = 0x41, // DMA setup FIS - bidirectional
+    Data = 0x46,     // Data FIS - bidirectional
+    Bist = 0x58,     // BIST activate FIS - bidirectional
+    PioSetup = 0x5F, // PIO setup FIS - device to host
+    DevBits = 0xA1,  // Set device bits FIS - device to host
 }
 
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
 pub struct FisRegH2D {
     // DWORD 0
-    pub fis_type: u8,     // FIS_TYPE_REG_H2D
-    pub pmport_c: u8,     // Port multiplier (bits 0-3), Reserved (bits 4-6), Command/Control (bit 7)
-    pub command: u8,      // Command register
-    pub featurel: u8,     // Feature register, 7:0
-    
+    pub fis_type: u8, // FIS_TYPE_REG_H2D
+    pub pmport_c: u8, // Port multiplier (bits 0-3), Reserved (bits 4-6), Command/Control (bit 7)
+    pub command: u8,  // Command register
+    pub featurel: u8, // Feature register, 7:0
+
     // DWORD 1
-    pub lba0: u8,         // LBA low register, 7:0
-    pub lba1: u8,         // LBA mid register, 15:8
-    pub lba2: u8,         // LBA high register, 23:16
-    pub device: u8,       // Device register
-    
+    pub lba0: u8,   // LBA low register, 7:0
+    pub lba1: u8,   // LBA mid register, 15:8
+    pub lba2: u8,   // LBA high register, 23:16
+    pub device: u8, // Device register
+
     // DWORD 2
-    pub lba3: u8,         // LBA register, 31:24
-    pub lba4: u8,         // LBA register, 39:32
-    pub lba5: u8,         // LBA register, 47:40
-    pub featureh: u8,     // Feature register, 15:8
-    
+    pub lba3: u8,     // LBA register, 31:24
+    pub lba4: u8,     // LBA register, 39:32
+    pub lba5: u8,     // LBA register, 47:40
+    pub featureh: u8, // Feature register, 15:8
+
     // DWORD 3
-    pub countl: u8,       // Count register, 7:0
-    pub counth: u8,       // Count register, 15:8
-    pub icc: u8,          // Isochronous command completion
-    pub control: u8,      // Control register
-    
+    pub countl: u8,  // Count register, 7:0
+    pub counth: u8,  // Count register, 15:8
+    pub icc: u8,     // Isochronous command completion
+    pub control: u8, // Control register
+
     // DWORD 4
-    pub rsv1: [u8; 4],   // Reserved
+    pub rsv1: [u8; 4], // Reserved
 }
 
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
 pub struct FisRegD2H {
     // DWORD 0
-    pub fis_type: u8,     // FIS_TYPE_REG_D2H
-    pub pmport_i: u8,     // Port multiplier (bits 0-3), Reserved (bits 4-5), Interrupt (bit 6), Reserved (bit 7)
-    pub status: u8,       // Status register
-    pub error: u8,        // Error register
-    
+    pub fis_type: u8, // FIS_TYPE_REG_D2H
+    pub pmport_i: u8, // Port multiplier (bits 0-3), Reserved (bits 4-5), Interrupt (bit 6), Reserved (bit 7)
+    pub status: u8,   // Status register
+    pub error: u8,    // Error register
+
     // DWORD 1
-    pub lba0: u8,         // LBA low register, 7:0
-    pub lba1: u8,         // LBA mid register, 15:8
-    pub lba2: u8,         // LBA high register, 23:16
-    pub device: u8,       // Device register
-    
+    pub lba0: u8,   // LBA low register, 7:0
+    pub lba1: u8,   // LBA mid register, 15:8
+    pub lba2: u8,   // LBA high register, 23:16
+    pub device: u8, // Device register
+
     // DWORD 2
-    pub lba3: u8,         // LBA register, 31:24
-    pub lba4: u8,         // LBA register, 39:32
-    pub lba5: u8,         // LBA register, 47:40
-    pub rsv2: u8,         // Reserved
-    
+    pub lba3: u8, // LBA register, 31:24
+    pub lba4: u8, // LBA register, 39:32
+    pub lba5: u8, // LBA register, 47:40
+    pub rsv2: u8, // Reserved
+
     // DWORD 3
-    pub countl: u8,       // Count register, 7:0
-    pub counth: u8,       // Count register, 15:8
-    pub rsv3: [u8; 2],   // Reserved
-    
+    pub countl: u8,    // Count register, 7:0
+    pub counth: u8,    // Count register, 15:8
+    pub rsv3: [u8; 2], // Reserved
+
     // DWORD 4
-    pub rsv4: [u8; 4],   // Reserved
+    pub rsv4: [u8; 4], // Reserved
 }
 
 #[repr(C, packed)]
 #[derive(Debug, Clone, Copy)]
 pub struct FisData {
     // DWORD 0
-    pub fis_type: u8,     // FIS_TYPE_DATA
-    pub pmport: u8,       // Port multiplier (bits 0-3) and reserved (bits 4-7)
-    pub rsv1: [u8; 2],   // Reserved
-    
+    pub fis_type: u8,  // FIS_TYPE_DATA
+    pub pmport: u8,    // Port multiplier (bits 0-3) and reserved (bits 4-7)
+    pub rsv1: [u8; 2], // Reserved
+
     // DWORD 1 ~ N
-    pub data: [u32; 1],   // Payload
+    pub data: [u32; 1], // Payload
 }
 
 #[repr(C, packed)]
@@ -99,28 +108,28 @@ pub struct FisPioSetup {
     pub pmport_flags: u8, // Port multiplier and flags
     pub status: u8,       // Status register
     pub error: u8,        // Error register
-    
+
     // DWORD 1
-    pub lba0: u8,         // LBA low register, 7:0
-    pub lba1: u8,         // LBA mid register, 15:8
-    pub lba2: u8,         // LBA high register, 23:16
-    pub device: u8,       // Device register
-    
+    pub lba0: u8,   // LBA low register, 7:0
+    pub lba1: u8,   // LBA mid register, 15:8
+    pub lba2: u8,   // LBA high register, 23:16
+    pub device: u8, // Device register
+
     // DWORD 2
-    pub lba3: u8,         // LBA register, 31:24
-    pub lba4: u8,         // LBA register, 39:32
-    pub lba5: u8,         // LBA register, 47:40
-    pub rsv2: u8,         // Reserved
-    
+    pub lba3: u8, // LBA register, 31:24
+    pub lba4: u8, // LBA register, 39:32
+    pub lba5: u8, // LBA register, 47:40
+    pub rsv2: u8, // Reserved
+
     // DWORD 3
-    pub countl: u8,       // Count register, 7:0
-    pub counth: u8,       // Count register, 15:8
-    pub rsv3: u8,         // Reserved
-    pub e_status: u8,     // New value of status register
-    
+    pub countl: u8,   // Count register, 7:0
+    pub counth: u8,   // Count register, 15:8
+    pub rsv3: u8,     // Reserved
+    pub e_status: u8, // New value of status register
+
     // DWORD 4
-    pub tc: u16,          // Transfer count
-    pub rsv4: [u8; 2],   // Reserved
+    pub tc: u16,       // Transfer count
+    pub rsv4: [u8; 2], // Reserved
 }
 
 #[repr(C, packed)]
@@ -129,22 +138,22 @@ pub struct FisDmaSetup {
     // DWORD 0
     pub fis_type: u8,     // FIS_TYPE_DMA_SETUP
     pub pmport_flags: u8, // Port multiplier and flags
-    pub rsved: [u8; 2],  // Reserved
-    
+    pub rsved: [u8; 2],   // Reserved
+
     // DWORD 1 & 2
     pub dma_buffer_id: u64, // DMA Buffer Identifier
-    
+
     // DWORD 3
-    pub rsvd: u32,       // Reserved
-    
+    pub rsvd: u32, // Reserved
+
     // DWORD 4
     pub dma_buf_offset: u32, // Byte offset into buffer
-    
+
     // DWORD 5
     pub transfer_count: u32, // Number of bytes to transfer
-    
+
     // DWORD 6
-    pub resvd: u32,      // Reserved
+    pub resvd: u32, // Reserved
 }
 
 // AHCI device detection constants
@@ -159,23 +168,23 @@ pub const HBA_PORT_DET_PRESENT: u32 = 3;
 
 #[repr(C)]
 pub struct HbaPort {
-    pub clb: Volatile<u32>,    // 0x00, command list base address, 1K-byte aligned
-    pub clbu: Volatile<u32>,   // 0x04, command list base address upper 32 bits
-    pub fb: Volatile<u32>,     // 0x08, FIS base address, 256-byte aligned
-    pub fbu: Volatile<u32>,    // 0x0C, FIS base address upper 32 bits
-    pub is: Volatile<u32>,     // 0x10, interrupt status
-    pub ie: Volatile<u32>,     // 0x14, interrupt enable
-    pub cmd: Volatile<u32>,    // 0x18, command and status
-    pub rsv0: Volatile<u32>,   // 0x1C, Reserved
-    pub tfd: Volatile<u32>,    // 0x20, task file data
-    pub sig: Volatile<u32>,    // 0x24, signature
-    pub ssts: Volatile<u32>,   // 0x28, SATA status (SCR0:SStatus)
-    pub sctl: Volatile<u32>,   // 0x2C, SATA control (SCR2:SControl)
-    pub serr: Volatile<u32>,   // 0x30, SATA error (SCR1:SError)
-    pub sact: Volatile<u32>,   // 0x34, SATA active (SCR3:SActive)
-    pub ci: Volatile<u32>,     // 0x38, command issue
-    pub sntf: Volatile<u32>,   // 0x3C, SATA notification (SCR4:SNotification)
-    pub fbs: Volatile<u32>,    // 0x40, FIS-based switch control
+    pub clb: Volatile<u32>,  // 0x00, command list base address, 1K-byte aligned
+    pub clbu: Volatile<u32>, // 0x04, command list base address upper 32 bits
+    pub fb: Volatile<u32>,   // 0x08, FIS base address, 256-byte aligned
+    pub fbu: Volatile<u32>,  // 0x0C, FIS base address upper 32 bits
+    pub is: Volatile<u32>,   // 0x10, interrupt status
+    pub ie: Volatile<u32>,   // 0x14, interrupt enable
+    pub cmd: Volatile<u32>,  // 0x18, command and status
+    pub rsv0: Volatile<u32>, // 0x1C, Reserved
+    pub tfd: Volatile<u32>,  // 0x20, task file data
+    pub sig: Volatile<u32>,  // 0x24, signature
+    pub ssts: Volatile<u32>, // 0x28, SATA status (SCR0:SStatus)
+    pub sctl: Volatile<u32>, // 0x2C, SATA control (SCR2:SControl)
+    pub serr: Volatile<u32>, // 0x30, SATA error (SCR1:SError)
+    pub sact: Volatile<u32>, // 0x34, SATA active (SCR3:SActive)
+    pub ci: Volatile<u32>,   // 0x38, command issue
+    pub sntf: Volatile<u32>, // 0x3C, SATA notification (SCR4:SNotification)
+    pub fbs: Volatile<u32>,  // 0x40, FIS-based switch control
     pub rsv1: [Volatile<u32>; 11], // 0x44 ~ 0x6F, Reserved
     pub vendor: [Volatile<u32>; 4], // 0x70 ~ 0x7F, vendor specific
 }
@@ -206,35 +215,35 @@ impl HbaPort {
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DeviceType {
-    SATA,    // SATA drive
-    SATAPI,  // SATAPI drive
-    SEMB,    // Enclosure management bridge
-    PM,      // Port multiplier
+    SATA,   // SATA drive
+    SATAPI, // SATAPI drive
+    SEMB,   // Enclosure management bridge
+    PM,     // Port multiplier
 }
 
 #[repr(C)]
 pub struct HbaMem {
     // 0x00 - 0x2B, Generic Host Control
-    pub cap: Volatile<u32>,      // 0x00, Host capability
-    pub ghc: Volatile<u32>,      // 0x04, Global host control
-    pub is: Volatile<u32>,       // 0x08, Interrupt status
-    pub pi: Volatile<u32>,       // 0x0C, Port implemented
-    pub vs: Volatile<u32>,       // 0x10, Version
-    pub ccc_ctl: Volatile<u32>,  // 0x14, Command completion coalescing control
-    pub ccc_pts: Volatile<u32>,  // 0x18, Command completion coalescing ports
-    pub em_loc: Volatile<u32>,   // 0x1C, Enclosure management location
-    pub em_ctl: Volatile<u32>,   // 0x20, Enclosure management control
-    pub cap2: Volatile<u32>,     // 0x24, Host capabilities extended
-    pub bohc: Volatile<u32>,     // 0x28, BIOS/OS handoff control and status
+    pub cap: Volatile<u32>,     // 0x00, Host capability
+    pub ghc: Volatile<u32>,     // 0x04, Global host control
+    pub is: Volatile<u32>,      // 0x08, Interrupt status
+    pub pi: Volatile<u32>,      // 0x0C, Port implemented
+    pub vs: Volatile<u32>,      // 0x10, Version
+    pub ccc_ctl: Volatile<u32>, // 0x14, Command completion coalescing control
+    pub ccc_pts: Volatile<u32>, // 0x18, Command completion coalescing ports
+    pub em_loc: Volatile<u32>,  // 0x1C, Enclosure management location
+    pub em_ctl: Volatile<u32>,  // 0x20, Enclosure management control
+    pub cap2: Volatile<u32>,    // 0x24, Host capabilities extended
+    pub bohc: Volatile<u32>,    // 0x28, BIOS/OS handoff control and status
 
     // 0x2C - 0x9F, Reserved
-    pub rsv: [u8; 0xA0-0x2C],
+    pub rsv: [u8; 0xA0 - 0x2C],
 
     // 0xA0 - 0xFF, Vendor specific registers
-    pub vendor: [u8; 0x100-0xA0],
+    pub vendor: [u8; 0x100 - 0xA0],
 
     // 0x100 - 0x10FF, Port control registers
-    pub ports: [HbaPort; 32],    // 1 ~ 32 ports
+    pub ports: [HbaPort; 32], // 1 ~ 32 ports
 }
 
 impl HbaMem {
@@ -254,45 +263,484 @@ impl HbaMem {
     }
 }
 
-pub fn init_ahci(abar: usize) -> Option<&'static mut HbaMem> {
-    let hba = unsafe { &mut *(abar as *mut HbaMem) };
-    
+pub const ATA_CMD_READ_DMA_EXT: u8 = 0x25;
+pub const ATA_CMD_WRITE_DMA_EXT: u8 = 0x35;
+pub const ATA_CMD_FLUSH_CACHE_EXT: u8 = 0xEA;
+
+// Task file status bits
+const ATA_DEV_ERR: u8 = 0x01;
+const ATA_DEV_DRQ: u8 = 0x08;
+const ATA_DEV_BUSY: u8 = 0x80;
+
+// Port command and interrupt status bits
+const HBA_PXCMD_ST: u32 = 1 << 0;
+const HBA_PXCMD_FRE: u32 = 1 << 4;
+const HBA_PXCMD_FR: u32 = 1 << 14;
+const HBA_PXCMD_CR: u32 = 1 << 15;
+const HBA_PXIS_TFES: u32 = 1 << 30;
+const HBA_GHC_AE: u32 = 1 << 31;
+
+pub const SECTOR_SIZE: usize = 512;
+
+// every port gets a physically contiguous bounce buffer of this many frames, so a whole command
+// fits in one PRDT entry
+const BUFFER_FRAMES: usize = 16;
+const FRAME_SIZE: usize = 4096;
+pub const MAX_SECTORS_PER_COMMAND: usize = BUFFER_FRAMES * FRAME_SIZE / SECTOR_SIZE;
+
+// how many times to poll a register before giving up on the device
+const SPIN_TIMEOUT: usize = 10_000_000;
+
+#[repr(C)]
+struct HbaCmdHeader {
+    // DWORD 0
+    flags: u16, // Command FIS length in DWORDS (bits 0-4), ATAPI (bit 5), Write (bit 6), Prefetchable (bit 7), Port multiplier (bits 12-15)
+    prdtl: u16, // Physical region descriptor table length in entries
+
+    // DWORD 1
+    prdbc: u32, // Physical region descriptor byte count transferred
+
+    // DWORD 2, 3
+    ctba: u32,  // Command table descriptor base address, 128-byte aligned
+    ctbau: u32, // Command table descriptor base address upper 32 bits
+
+    // DWORD 4 - 7
+    rsv1: [u32; 4], // Reserved
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct HbaPrdtEntry {
+    dba: u32,  // Data base address
+    dbau: u32, // Data base address upper 32 bits
+    rsv0: u32, // Reserved
+    dbc: u32,  // Byte count - 1 (bits 0-21), Interrupt on completion (bit 31)
+}
+
+// the buffer is contiguous, so one entry covers any command
+const PRDT_ENTRIES: usize = 1;
+
+#[repr(C)]
+struct HbaCmdTable {
+    cfis: [u8; 64],                     // Command FIS
+    acmd: [u8; 16],                     // ATAPI command, 12 or 16 bytes
+    rsv: [u8; 48],                      // Reserved
+    prdt: [HbaPrdtEntry; PRDT_ENTRIES], // Physical region descriptor table entries
+}
+
+impl HbaCmdTable {
+    /// points the PRDT at the first `bytes` of the buffer at `addr` and gives back how many
+    /// entries that took. commands without data, like a cache flush, need none at all.
+    fn set_prdt(&mut self, addr: u64, bytes: usize) -> u16 {
+        if bytes == 0 {
+            return 0;
+        }
+        self.prdt[0] = HbaPrdtEntry {
+            dba: addr as u32,
+            dbau: (addr >> 32) as u32,
+            rsv0: 0,
+            dbc: (bytes - 1) as u32,
+        };
+        1
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AhciError {
+    Timeout,
+    DeviceError(u8), // contents of the error register
+    OutOfMemory,
+    OutOfRange,
+    InvalidBufferSize,
+    Unsupported,
+    MapFailed,
+}
+
+lazy_static! {
+    pub static ref AHCI_DEVICES: Mutex<Vec<Arc<Mutex<AhciDevice>>>> = Mutex::new(Vec::new());
+}
+
+/// a SATA drive attached to one port of the HBA. commands are issued one at a time through
+/// command slot 0 and waited on by polling, so every method blocks until the drive responds.
+pub struct AhciDevice {
+    name: String,
+    port_num: usize,
+    port: &'static mut HbaPort,
+    dma: PhysFrame, // two frames, one for the command list and received FIS, one for the table
+    cmd_list: VirtAddr, // command list, the received FIS area sits 1K into the same frame
+    cmd_table: VirtAddr, // command table used by slot 0
+    buffer: PhysFrame, // first of BUFFER_FRAMES contiguous frames
+    sectors: u64,
+    model: String,
+    serial: String,
+}
+
+impl AhciDevice {
+    fn new(
+        name: String,
+        port_num: usize,
+        port: &'static mut HbaPort,
+    ) -> Result<AhciDevice, AhciError> {
+        stop_cmd(port)?;
+
+        let dma = alloc_zeroed_frames(2).ok_or(AhciError::OutOfMemory)?;
+        let buffer = match alloc_zeroed_frames(BUFFER_FRAMES) {
+            Some(buffer) => buffer,
+            None => {
+                unsafe { free_frames(dma, 2) };
+                return Err(AhciError::OutOfMemory);
+            }
+        };
+        let (list_frame, table_frame) = (dma, dma + 1);
+
+        // rebase the port onto memory that we own rather than whatever the firmware left behind
+        let list_phys = list_frame.start_address().as_u64();
+        let fis_phys = list_phys + 1024;
+        let table_phys = table_frame.start_address().as_u64();
+
+        port.clb.write(list_phys as u32);
+        port.clbu.write((list_phys >> 32) as u32);
+        port.fb.write(fis_phys as u32);
+        port.fbu.write((fis_phys >> 32) as u32);
+
+        let cmd_list = phys_to_virt(list_frame.start_address());
+        let header = unsafe { &mut *cmd_list.as_mut_ptr::<HbaCmdHeader>() };
+        header.ctba = table_phys as u32;
+        header.ctbau = (table_phys >> 32) as u32;
+
+        port.serr.write(u32::MAX);
+        port.is.write(u32::MAX);
+        start_cmd(port)?;
+
+        let mut device = AhciDevice {
+            name,
+            port_num,
+            port,
+            dma,
+            cmd_list,
+            cmd_table: phys_to_virt(table_frame.start_address()),
+            buffer,
+            sectors: 0,
+            model: String::new(),
+            serial: String::new(),
+        };
+        device.identify()?;
+        Ok(device)
+    }
+
+    /// the name the drive is registered under as a block device, e.g. "sda"
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn port(&self) -> usize {
+        self.port_num
+    }
+
+    pub fn sectors(&self) -> u64 {
+        self.sectors
+    }
+
+    pub fn sector_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    pub fn serial(&self) -> &str {
+        &self.serial
+    }
+
+    /// reads `buf.len() / 512` sectors starting at `lba` into the buffer
+    pub fn read_sectors(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), AhciError> {
+        self.check_range(lba, buf.len())?;
+        let chunk_size = MAX_SECTORS_PER_COMMAND * SECTOR_SIZE;
+
+        for (i, chunk) in buf.chunks_mut(chunk_size).enumerate() {
+            let lba = lba + (i * MAX_SECTORS_PER_COMMAND) as u64;
+            let count = (chunk.len() / SECTOR_SIZE) as u16;
+            self.issue(ATA_CMD_READ_DMA_EXT, lba, count, false, chunk.len())?;
+            self.copy_from_buffer(chunk);
+        }
+        Ok(())
+    }
+
+    /// writes `buf.len() / 512` sectors starting at `lba`
+    pub fn write_sectors(&mut self, lba: u64, buf: &[u8]) -> Result<(), AhciError> {
+        self.check_range(lba, buf.len())?;
+        let chunk_size = MAX_SECTORS_PER_COMMAND * SECTOR_SIZE;
+
+        for (i, chunk) in buf.chunks(chunk_size).enumerate() {
+            let lba = lba + (i * MAX_SECTORS_PER_COMMAND) as u64;
+            let count = (chunk.len() / SECTOR_SIZE) as u16;
+            self.copy_to_buffer(chunk);
+            self.issue(ATA_CMD_WRITE_DMA_EXT, lba, count, true, chunk.len())?;
+        }
+        Ok(())
+    }
+
+    /// asks the drive to write its internal cache out to the disk
+    pub fn flush(&mut self) -> Result<(), AhciError> {
+        self.issue(ATA_CMD_FLUSH_CACHE_EXT, 0, 0, false, 0)
+    }
+
+    fn check_range(&self, lba: u64, len: usize) -> Result<(), AhciError> {
+        if len % SECTOR_SIZE != 0 {
+            return Err(AhciError::InvalidBufferSize);
+        }
+        if lba + (len / SECTOR_SIZE) as u64 > self.sectors {
+            return Err(AhciError::OutOfRange);
+        }
+        Ok(())
+    }
+
+    fn identify(&mut self) -> Result<(), AhciError> {
+        self.issue(ATA_CMD_IDENTIFY, 0, 0, false, SECTOR_SIZE)?;
+
+        let mut data = [0u8; SECTOR_SIZE];
+        self.copy_from_buffer(&mut data);
+        let word = |i: usize| u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
+
+        // word 83 bit 10 is set if the drive supports 48 bit addressing
+        if word(83) & (1 << 10) == 0 {
+            return Err(AhciError::Unsupported);
+        }
+
+        self.sectors = (100..104)
+            .rev()
+            .fold(0u64, |acc, i| (acc << 16) | word(i) as u64);
+        self.serial = ata_string(&data[20..40]);
+        self.model = ata_string(&data[54..94]);
+        Ok(())
+    }
+
+    fn issue(
+        &mut self,
+        command: u8,
+        lba: u64,
+        count: u16,
+        write: bool,
+        bytes: usize,
+    ) -> Result<(), AhciError> {
+        let port = &*self.port;
+        spin_until(|| port.tfd.read() as u8 & (ATA_DEV_BUSY | ATA_DEV_DRQ) == 0)?;
+
+        let fis = FisRegH2D {
+            fis_type: FisType::RegH2D as u8,
+            pmport_c: 1 << 7, // Set the command bit (c=1)
+            command,
+            featurel: 0,
+            lba0: lba as u8,
+            lba1: (lba >> 8) as u8,
+            lba2: (lba >> 16) as u8,
+            device: if command == ATA_CMD_IDENTIFY {
+                0
+            } else {
+                1 << 6
+            }, // LBA mode
+            lba3: (lba >> 24) as u8,
+            lba4: (lba >> 32) as u8,
+            lba5: (lba >> 40) as u8,
+            featureh: 0,
+            countl: count as u8,
+            counth: (count >> 8) as u8,
+            icc: 0,
+            control: 0,
+            rsv1: [0; 4],
+        };
+
+        let table = unsafe { &mut *self.cmd_table.as_mut_ptr::<HbaCmdTable>() };
+        table.cfis = [0; 64];
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                &fis as *const FisRegH2D as *const u8,
+                table.cfis.as_mut_ptr(),
+                size_of::<FisRegH2D>(),
+            );
+        }
+
+        let prdtl = table.set_prdt(self.buffer.start_address().as_u64(), bytes);
+
+        let header = unsafe { &mut *self.cmd_list.as_mut_ptr::<HbaCmdHeader>() };
+        header.flags = (size_of::<FisRegH2D>() / 4) as u16 | if write { 1 << 6 } else { 0 };
+        header.prdtl = prdtl;
+        header.prdbc = 0;
+
+        // make sure the command is in memory before the HBA is told to fetch it
+        fence(Ordering::SeqCst);
+        self.port.is.write(u32::MAX);
+        self.port.ci.write(1);
+
+        let port = &*self.port;
+        spin_until(|| port.ci.read() & 1 == 0 || port.is.read() & HBA_PXIS_TFES != 0)?;
+        fence(Ordering::SeqCst);
+
+        let tfd = self.port.tfd.read();
+        if self.port.is.read() & HBA_PXIS_TFES != 0 || tfd as u8 & ATA_DEV_ERR != 0 {
+            return Err(AhciError::DeviceError((tfd >> 8) as u8));
+        }
+        Ok(())
+    }
+
+    fn copy_from_buffer(&self, out: &mut [u8]) {
+        let src = phys_to_virt(self.buffer.start_address()).as_ptr::<u8>();
+        unsafe { core::ptr::copy_nonoverlapping(src, out.as_mut_ptr(), out.len()) };
+    }
+
+    fn copy_to_buffer(&mut self, data: &[u8]) {
+        let dst = phys_to_virt(self.buffer.start_address()).as_mut_ptr::<u8>();
+        unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len()) };
+    }
+}
+
+impl Drop for AhciDevice {
+    fn drop(&mut self) {
+        // if the port won't stop it may still DMA into the frames, so they are leaked instead
+        if stop_cmd(self.port).is_ok() {
+            unsafe {
+                free_frames(self.dma, 2);
+                free_frames(self.buffer, BUFFER_FRAMES);
+            }
+        }
+    }
+}
+
+impl From<AhciError> for BlockError {
+    fn from(e: AhciError) -> Self {
+        match e {
+            AhciError::OutOfRange => BlockError::OutOfRange,
+            AhciError::InvalidBufferSize => BlockError::InvalidBufferSize,
+            _ => BlockError::IoError,
+        }
+    }
+}
+
+// the device is shared behind a mutex, so the block device is implemented on the mutex itself
+impl BlockDevice for Mutex<AhciDevice> {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.lock().sectors()
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        Ok(self.lock().read_sectors(lba, buf)?)
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        Ok(self.lock().write_sectors(lba, buf)?)
+    }
+
+    fn flush(&self) -> Result<(), BlockError> {
+        Ok(self.lock().flush()?)
+    }
+}
+
+/// ATA identify strings store two characters per word with the bytes swapped
+fn ata_string(data: &[u8]) -> String {
+    let mut s = String::new();
+    for pair in data.chunks(2) {
+        s.push(pair[1] as char);
+        s.push(pair[0] as char);
+    }
+    String::from(s.trim())
+}
+
+fn spin_until(mut condition: impl FnMut() -> bool) -> Result<(), AhciError> {
+    for _ in 0..SPIN_TIMEOUT {
+        if condition() {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(AhciError::Timeout)
+}
+
+fn stop_cmd(port: &mut HbaPort) -> Result<(), AhciError> {
+    let cmd = port.cmd.read();
+    port.cmd.write(cmd & !(HBA_PXCMD_ST | HBA_PXCMD_FRE));
+    spin_until(|| port.cmd.read() & (HBA_PXCMD_FR | HBA_PXCMD_CR) == 0)
+}
+
+fn start_cmd(port: &mut HbaPort) -> Result<(), AhciError> {
+    spin_until(|| port.cmd.read() & HBA_PXCMD_CR == 0)?;
+    let cmd = port.cmd.read();
+    port.cmd.write(cmd | HBA_PXCMD_FRE | HBA_PXCMD_ST);
+    Ok(())
+}
+
+/// called by the PCI subsystem for every AHCI controller it finds, BAR5 holds the HBA registers (ABAR)
+pub fn pci_init(device: &PciDevice) -> Result<(), &'static str> {
+    let abar = match device.bars[5] {
+        Some(Bar::Memory { address, .. }) => address,
+        _ => return Err("BAR5 is not a memory BAR"),
+    };
+
+    // the HBA needs to master the bus to DMA the command lists and data
+    device.enable_bus_mastering();
+    init_ahci(abar as usize).map_err(|_| "failed to initialise the HBA")
+}
+
+/// maps the HBA registers found at the physical address `abar` and brings up every SATA drive on it
+pub fn init_ahci(abar: usize) -> Result<(), AhciError> {
+    let virt = map_mmio(PhysAddr::new(abar as u64), size_of::<HbaMem>())
+        .map_err(|_| AhciError::MapFailed)?;
+    let hba = unsafe { &mut *virt.as_mut_ptr::<HbaMem>() };
+
     // Enable AHCI by setting GHC.AE
     let ghc = hba.ghc.read();
-    hba.ghc.write(ghc | (1 << 31));
-    
+    hba.ghc.write(ghc | HBA_GHC_AE);
+
     let devices = hba.probe_ports();
     for (port_num, device_type) in devices {
         // Found a device
         match device_type {
-            DeviceType::SATA => println!("SATA drive found at port {}", port_num),
+            DeviceType::SATA => {
+                // each device owns its port registers from here on
+                let port = unsafe { &mut *(&mut hba.ports[port_num] as *mut HbaPort) };
+                // drives are named sda, sdb, ... in the order they are found
+                let name = format!("sd{}", (b'a' + AHCI_DEVICES.lock().len() as u8) as char);
+                match AhciDevice::new(name, port_num, port) {
+                    Ok(device) => {
+                        println!(
+                            "SATA drive found at port {}: {} ({} MiB)",
+                            port_num,
+                            device.model(),
+                            device.sectors() * SECTOR_SIZE as u64 / (1024 * 1024)
+                        );
+                        let name = String::from(device.name());
+                        let device = Arc::new(Mutex::new(device));
+                        AHCI_DEVICES.lock().push(device.clone());
+                        block::add_disk(
+                            &name,
+                            Arc::new(BlockCache::new(device, DEFAULT_CACHE_BLOCKS)),
+                        );
+                    }
+                    Err(e) => println!("SATA drive at port {} failed to start: {:?}", port_num, e),
+                }
+            }
             DeviceType::SATAPI => println!("SATAPI drive found at port {}", port_num),
             DeviceType::SEMB => println!("SEMB drive found at port {}", port_num),
             DeviceType::PM => println!("PM drive found at port {}", port_num),
         }
     }
 
-    Some(hba)
+    Ok(())
 }
 
-pub fn read_data() {
-    let mut fis = FisRegH2D {
-        fis_type: FisType::RegH2D as u8,
-        pmport_c: 1 << 7,    // Set the command bit (c=1)
-        command: ATA_CMD_IDENTIFY,
-        featurel: 0,
-        lba0: 0,
-        lba1: 0,
-        lba2: 0,
-        device: 0,           // Master device
-        lba3: 0,
-        lba4: 0,
-        lba5: 0,
-        featureh: 0,
-        countl: 0,
-        counth: 0,
-        icc: 0,
-        control: 0,
-        rsv1: [0; 4],
-    };
+#[test_case]
+fn commands_without_data_have_an_empty_prdt() {
+    // every field is a plain integer, so all zeroes is a valid table
+    let mut table: HbaCmdTable = unsafe { core::mem::zeroed() };
+    assert_eq!(table.set_prdt(0x1234_5000, 0), 0);
+    assert_eq!(table.prdt[0].dbc, 0);
+
+    assert_eq!(table.set_prdt(0x1_0000_2000, SECTOR_SIZE), 1);
+    let entry = table.prdt[0];
+    assert_eq!((entry.dba, entry.dbau), (0x2000, 1));
+    assert_eq!(entry.dbc, SECTOR_SIZE as u32 - 1);
 }
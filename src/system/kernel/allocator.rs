@@ -1,6 +1,8 @@
 use alloc::alloc::{GlobalAlloc, Layout};
-use core::ptr::null_mut;
-use linked_list_allocator::LockedHeap;
+use core::ptr::{null_mut, NonNull};
+use linked_list_allocator::Heap;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
 
 use x86_64::{
     structures::paging::{
@@ -9,13 +11,28 @@ use x86_64::{
     VirtAddr,
 };
 
+use super::memory;
+
+pub const HEAP_START: usize = 0x_4444_4444_0000;
+/// mapped at boot, before the frame allocator has been handed over to the kernel
+pub const HEAP_INITIAL_SIZE: usize = 100 * 1024;
+/// address space set aside for the heap to grow into
+pub const HEAP_MAX_SIZE: usize = 64 * 1024 * 1024;
+/// the heap grows by at least this much at a time
+const HEAP_GROW_SIZE: usize = 64 * 1024;
+
+/// small allocations are rounded up to one of these and served from a free list
+const SLAB_SIZES: [usize; 9] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+/// how much an empty slab takes from the heap at once
+const SLAB_CHUNK_SIZE: usize = 4096;
+
 pub fn init_heap(
     mapper: &mut impl Mapper<Size4KiB>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
 ) -> Result<(), MapToError<Size4KiB>> {
     let page_range = {
         let heap_start = VirtAddr::new(HEAP_START as u64);
-        let heap_end = heap_start + HEAP_SIZE - 1u64;
+        let heap_end = heap_start + HEAP_INITIAL_SIZE - 1u64;
         let heap_start_page = Page::containing_address(heap_start);
         let heap_end_page = Page::containing_address(heap_end);
         Page::range_inclusive(heap_start_page, heap_end_page)
@@ -27,10 +44,14 @@ pub fn init_heap(
             .ok_or(MapToError::FrameAllocationFailed)?;
         let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
         unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+    }
 
-        unsafe {
-            ALLOCATOR.lock().init(HEAP_START as *mut u8, HEAP_SIZE);
-        }
+    unsafe {
+        ALLOCATOR
+            .0
+            .lock()
+            .heap
+            .init(HEAP_START as *mut u8, HEAP_INITIAL_SIZE);
     }
     Ok(())
 }
@@ -47,8 +68,191 @@ unsafe impl GlobalAlloc for Dummy {
     }
 }
 
+/// a free list of equally sized blocks. blocks are carved out of chunks taken from the heap and
+/// never go back to it, a freed block just waits for the next allocation of its size.
+struct Slab {
+    block_size: usize,
+    free: Option<NonNull<FreeBlock>>,
+    blocks: usize,
+    used: usize,
+}
+
+struct FreeBlock {
+    next: Option<NonNull<FreeBlock>>,
+}
+
+impl Slab {
+    const fn new(block_size: usize) -> Slab {
+        Slab {
+            block_size,
+            free: None,
+            blocks: 0,
+            used: 0,
+        }
+    }
+
+    fn pop(&mut self) -> Option<NonNull<u8>> {
+        let block = self.free?;
+        self.free = unsafe { block.as_ref().next };
+        self.used += 1;
+        Some(block.cast())
+    }
+
+    unsafe fn push(&mut self, ptr: NonNull<u8>) {
+        let block = ptr.cast::<FreeBlock>();
+        block.as_ptr().write(FreeBlock { next: self.free });
+        self.free = Some(block);
+    }
+}
+
+/// size classes in front of a linked list heap that maps more pages when it runs out
+struct Allocator {
+    slabs: [Slab; SLAB_SIZES.len()],
+    heap: Heap,
+}
+
+// the free lists point into the heap, which only this allocator touches
+unsafe impl Send for Allocator {}
+
+impl Allocator {
+    const fn new() -> Allocator {
+        Allocator {
+            slabs: [
+                Slab::new(SLAB_SIZES[0]),
+                Slab::new(SLAB_SIZES[1]),
+                Slab::new(SLAB_SIZES[2]),
+                Slab::new(SLAB_SIZES[3]),
+                Slab::new(SLAB_SIZES[4]),
+                Slab::new(SLAB_SIZES[5]),
+                Slab::new(SLAB_SIZES[6]),
+                Slab::new(SLAB_SIZES[7]),
+                Slab::new(SLAB_SIZES[8]),
+            ],
+            heap: Heap::empty(),
+        }
+    }
+
+    /// the slab serving `layout`, None if it is too big or too strictly aligned for any of them
+    fn slab_index(layout: &Layout) -> Option<usize> {
+        let size = layout.size().max(layout.align());
+        SLAB_SIZES.iter().position(|&s| s >= size)
+    }
+
+    fn alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let index = match Self::slab_index(&layout) {
+            Some(index) => index,
+            None => return self.heap_alloc(layout),
+        };
+        if self.slabs[index].free.is_none() {
+            self.refill(index)?;
+        }
+        self.slabs[index].pop()
+    }
+
+    unsafe fn dealloc(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        match Self::slab_index(&layout) {
+            Some(index) => {
+                self.slabs[index].push(ptr);
+                self.slabs[index].used -= 1;
+            }
+            None => self.heap.deallocate(ptr, layout),
+        }
+    }
+
+    fn heap_alloc(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        if let Ok(ptr) = self.heap.allocate_first_fit(layout) {
+            return Some(ptr);
+        }
+        self.grow(layout.size() + layout.align()).ok()?;
+        self.heap.allocate_first_fit(layout).ok()
+    }
+
+    /// carves a fresh chunk from the heap into blocks for the slab at `index`
+    fn refill(&mut self, index: usize) -> Option<()> {
+        let block_size = self.slabs[index].block_size;
+        let chunk = self.heap_alloc(Layout::from_size_align(SLAB_CHUNK_SIZE, block_size).ok()?)?;
+        let slab = &mut self.slabs[index];
+        for offset in (0..SLAB_CHUNK_SIZE).step_by(block_size) {
+            unsafe { slab.push(NonNull::new_unchecked(chunk.as_ptr().add(offset))) };
+        }
+        slab.blocks += SLAB_CHUNK_SIZE / block_size;
+        Some(())
+    }
+
+    /// maps at least `min` more bytes onto the top of the heap
+    fn grow(&mut self, min: usize) -> Result<(), MapToError<Size4KiB>> {
+        let page_size = Page::<Size4KiB>::SIZE as usize;
+        let by = (min.max(HEAP_GROW_SIZE) + page_size - 1) / page_size * page_size;
+        let top = self.heap.top() as usize;
+        if top + by > HEAP_START + HEAP_MAX_SIZE {
+            return Err(MapToError::FrameAllocationFailed);
+        }
+
+        let start = Page::containing_address(VirtAddr::new(top as u64));
+        memory::map_kernel_pages(start, (by / page_size) as u64)?;
+        unsafe { self.heap.extend(by) };
+        Ok(())
+    }
+
+    fn stats(&self) -> HeapStats {
+        HeapStats {
+            size: self.heap.size(),
+            used: self.heap.used(),
+            slab_size: self.slabs.iter().map(|s| s.blocks * s.block_size).sum(),
+            slab_used: self.slabs.iter().map(|s| s.used * s.block_size).sum(),
+        }
+    }
+}
+
+/// heap usage in bytes. slab chunks count as used heap whether or not their blocks are taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    /// everything mapped for the heap so far
+    pub size: usize,
+    pub used: usize,
+    /// bytes held by the slabs and how many of them are handed out
+    pub slab_size: usize,
+    pub slab_used: usize,
+}
+
+/// the heap lock is only ever held with interrupts disabled, otherwise a thread preempted in the
+/// middle of an allocation would leave it locked for the timer interrupt and every other thread.
+pub struct KernelHeap(Mutex<Allocator>);
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        interrupts::without_interrupts(|| {
+            self.0
+                .lock()
+                .alloc(layout)
+                .map_or(null_mut(), |ptr| ptr.as_ptr())
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if let Some(ptr) = NonNull::new(ptr) {
+            interrupts::without_interrupts(|| self.0.lock().dealloc(ptr, layout))
+        }
+    }
+}
+
 #[global_allocator]
-static ALLOCATOR: LockedHeap = LockedHeap::empty();
+static ALLOCATOR: KernelHeap = KernelHeap(Mutex::new(Allocator::new()));
 
-pub const HEAP_START: usize = 0x_4444_4444_0000;
-pub const HEAP_SIZE: usize = 100 * 1024;
+pub fn stats() -> HeapStats {
+    interrupts::without_interrupts(|| ALLOCATOR.0.lock().stats())
+}
+
+#[test_case]
+fn heap_grows_past_its_initial_size() {
+    use alloc::vec::Vec;
+
+    let before = stats();
+    let big = Vec::<u8>::with_capacity(HEAP_INITIAL_SIZE * 2);
+    assert!(stats().size > HEAP_INITIAL_SIZE * 2);
+    drop(big);
+
+    let small = (0..100).map(alloc::boxed::Box::new).collect::<Vec<_>>();
+    assert!(stats().slab_used >= before.slab_used + 100 * 8);
+    drop(small);
+}
@@ -0,0 +1,206 @@
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+use x86_64::registers::model_specific::Msr;
+use x86_64::VirtAddr;
+
+use super::acpi::{self, InterruptOverride};
+use super::memory::map_mmio;
+
+const IA32_APIC_BASE: u32 = 0x1B;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+
+// local APIC registers, as byte offsets from its base
+const LAPIC_ID: usize = 0x20;
+const LAPIC_TPR: usize = 0x80;
+const LAPIC_EOI: usize = 0xB0;
+const LAPIC_SVR: usize = 0xF0;
+const SVR_ENABLE: u32 = 1 << 8;
+
+// IOAPIC registers, reached by writing the index to IOREGSEL and then using IOWIN
+const IOAPIC_VER: u32 = 0x01;
+const IOAPIC_REDTBL: u32 = 0x10;
+const REDIRECT_ACTIVE_LOW: u64 = 1 << 13;
+const REDIRECT_LEVEL: u64 = 1 << 14;
+const REDIRECT_MASKED: u64 = 1 << 16;
+
+/// delivered when an interrupt goes away before the cpu accepts it, it must not be acknowledged
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+/// virtual address of the local APIC registers, 0 while the 8259 PICs are still in use. kept
+/// outside the lock so every interrupt handler can send its EOI without taking one.
+static LOCAL_APIC: AtomicU64 = AtomicU64::new(0);
+static IO_APICS: Mutex<Routing> = Mutex::new(Routing {
+    io_apics: Vec::new(),
+    overrides: Vec::new(),
+});
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApicError {
+    /// ACPI has no MADT, the machine only has the 8259 PICs
+    NoMadt,
+    NoIoApic,
+    /// no IOAPIC handles this global system interrupt
+    NoRoute(u32),
+    MapFailed,
+}
+
+impl fmt::Display for ApicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApicError::NoMadt => write!(f, "no MADT found"),
+            ApicError::NoIoApic => write!(f, "the MADT lists no IOAPIC"),
+            ApicError::NoRoute(gsi) => write!(f, "no IOAPIC handles GSI {}", gsi),
+            ApicError::MapFailed => write!(f, "failed to map the APIC registers"),
+        }
+    }
+}
+
+struct IoApic {
+    base: VirtAddr,
+    gsi_base: u32,
+    entries: u32,
+}
+
+impl IoApic {
+    fn read(&self, reg: u32) -> u32 {
+        unsafe {
+            self.base.as_mut_ptr::<u32>().write_volatile(reg);
+            (self.base + 0x10u64).as_ptr::<u32>().read_volatile()
+        }
+    }
+
+    fn write(&self, reg: u32, value: u32) {
+        unsafe {
+            self.base.as_mut_ptr::<u32>().write_volatile(reg);
+            (self.base + 0x10u64)
+                .as_mut_ptr::<u32>()
+                .write_volatile(value);
+        }
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.entries
+    }
+
+    fn redirect(&self, gsi: u32, entry: u64) {
+        let reg = IOAPIC_REDTBL + (gsi - self.gsi_base) * 2;
+        // masked while the halves don't match
+        self.write(reg, REDIRECT_MASKED as u32);
+        self.write(reg + 1, (entry >> 32) as u32);
+        self.write(reg, entry as u32);
+    }
+}
+
+struct Routing {
+    io_apics: Vec<IoApic>,
+    overrides: Vec<InterruptOverride>,
+}
+
+impl Routing {
+    /// the global system interrupt an ISA irq arrives on, along with its polarity and trigger
+    /// mode bits. ISA interrupts are active high and edge triggered unless overridden.
+    fn isa(&self, irq: u8) -> (u32, u64) {
+        match self.overrides.iter().find(|o| o.irq == irq) {
+            Some(o) => {
+                let mut flags = 0;
+                if o.flags & 0b11 == 0b11 {
+                    flags |= REDIRECT_ACTIVE_LOW;
+                }
+                if (o.flags >> 2) & 0b11 == 0b11 {
+                    flags |= REDIRECT_LEVEL;
+                }
+                (o.gsi, flags)
+            }
+            None => (irq as u32, 0),
+        }
+    }
+
+    fn io_apic(&self, gsi: u32) -> Result<&IoApic, ApicError> {
+        self.io_apics
+            .iter()
+            .find(|a| a.handles(gsi))
+            .ok_or(ApicError::NoRoute(gsi))
+    }
+}
+
+fn local_apic(reg: usize) -> *mut u32 {
+    (LOCAL_APIC.load(Ordering::Relaxed) as usize + reg) as *mut u32
+}
+
+/// maps and enables the local APIC and every IOAPIC from the MADT, with all IOAPIC inputs
+/// masked. returns whether there are 8259 PICs, which the caller has to mask. interrupts should
+/// be off throughout.
+pub fn init() -> Result<bool, ApicError> {
+    let madt = acpi::madt().ok_or(ApicError::NoMadt)?;
+    if madt.io_apics.is_empty() {
+        return Err(ApicError::NoIoApic);
+    }
+
+    let mut io_apics = Vec::new();
+    for entry in &madt.io_apics {
+        let base = map_mmio(entry.address, 0x20).map_err(|_| ApicError::MapFailed)?;
+        let mut io_apic = IoApic {
+            base,
+            gsi_base: entry.gsi_base,
+            entries: 0,
+        };
+        io_apic.entries = ((io_apic.read(IOAPIC_VER) >> 16) & 0xFF) + 1;
+        for gsi in io_apic.gsi_base..io_apic.gsi_base + io_apic.entries {
+            io_apic.redirect(gsi, REDIRECT_MASKED);
+        }
+        io_apics.push(io_apic);
+    }
+    let lapic = map_mmio(madt.local_apic, 0x400).map_err(|_| ApicError::MapFailed)?;
+
+    let mut routing = IO_APICS.lock();
+    routing.io_apics = io_apics;
+    routing.overrides = madt.overrides;
+
+    unsafe {
+        let mut base = Msr::new(IA32_APIC_BASE);
+        let value = base.read();
+        base.write(value | APIC_BASE_ENABLE);
+    }
+    LOCAL_APIC.store(lapic.as_u64(), Ordering::Release);
+    unsafe {
+        local_apic(LAPIC_SVR).write_volatile(SVR_ENABLE | SPURIOUS_VECTOR as u32);
+        local_apic(LAPIC_TPR).write_volatile(0);
+    }
+    Ok(madt.legacy_pics)
+}
+
+/// true once interrupts are delivered through the APICs instead of the PICs
+pub fn is_enabled() -> bool {
+    LOCAL_APIC.load(Ordering::Acquire) != 0
+}
+
+pub fn local_apic_id() -> u8 {
+    match is_enabled() {
+        true => unsafe { (local_apic(LAPIC_ID).read_volatile() >> 24) as u8 },
+        false => 0,
+    }
+}
+
+pub fn end_of_interrupt() {
+    unsafe { local_apic(LAPIC_EOI).write_volatile(0) };
+}
+
+/// sends ISA irq `irq` to `vector` on this cpu
+pub fn route_isa(irq: u8, vector: u8) -> Result<(), ApicError> {
+    let routing = IO_APICS.lock();
+    let (gsi, flags) = routing.isa(irq);
+    let destination = (local_apic_id() as u64) << 56;
+    routing
+        .io_apic(gsi)?
+        .redirect(gsi, destination | flags | vector as u64);
+    Ok(())
+}
+
+pub fn mask_isa(irq: u8) -> Result<(), ApicError> {
+    let routing = IO_APICS.lock();
+    let (gsi, _) = routing.isa(irq);
+    routing.io_apic(gsi)?.redirect(gsi, REDIRECT_MASKED);
+    Ok(())
+}
@@ -0,0 +1,169 @@
+use super::{check_request, BlockDevice, BlockError};
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use spin::Mutex;
+
+struct Entry {
+    data: Vec<u8>,
+    dirty: bool,
+    stamp: u64,
+}
+
+struct Inner {
+    entries: BTreeMap<u64, Entry>, // keyed by lba
+    lru: BTreeMap<u64, u64>,       // stamp -> lba, the first entry is the least recently used
+    clock: u64,
+}
+
+/// an LRU write-back cache that sits in front of another block device.
+/// writes only reach the device when a dirty block is evicted or the cache is flushed.
+pub struct BlockCache {
+    device: Arc<dyn BlockDevice>,
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+impl BlockCache {
+    pub fn new(device: Arc<dyn BlockDevice>, capacity: usize) -> BlockCache {
+        BlockCache {
+            device,
+            capacity: capacity.max(1),
+            inner: Mutex::new(Inner {
+                entries: BTreeMap::new(),
+                lru: BTreeMap::new(),
+                clock: 0,
+            }),
+        }
+    }
+
+    /// marks `lba` as the most recently used block
+    fn touch(inner: &mut Inner, lba: u64) {
+        inner.clock += 1;
+        let stamp = inner.clock;
+        if let Some(entry) = inner.entries.get_mut(&lba) {
+            inner.lru.remove(&entry.stamp);
+            entry.stamp = stamp;
+            inner.lru.insert(stamp, lba);
+        }
+    }
+
+    fn insert(
+        &self,
+        inner: &mut Inner,
+        lba: u64,
+        data: Vec<u8>,
+        dirty: bool,
+    ) -> Result<(), BlockError> {
+        while inner.entries.len() >= self.capacity {
+            let (&stamp, &victim) = match inner.lru.iter().next() {
+                Some(x) => x,
+                None => break,
+            };
+            if let Some(entry) = inner.entries.get(&victim) {
+                if entry.dirty {
+                    self.device.write_blocks(victim, &entry.data)?;
+                }
+            }
+            inner.lru.remove(&stamp);
+            inner.entries.remove(&victim);
+        }
+
+        inner.clock += 1;
+        let stamp = inner.clock;
+        inner.entries.insert(lba, Entry { data, dirty, stamp });
+        inner.lru.insert(stamp, lba);
+        Ok(())
+    }
+}
+
+impl BlockDevice for BlockCache {
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.device.block_count()
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        let count = check_request(self, lba, buf.len())? as usize;
+        let bs = self.block_size();
+        let mut inner = self.inner.lock();
+
+        let mut i = 0;
+        while i < count {
+            if let Some(entry) = inner.entries.get(&(lba + i as u64)) {
+                buf[i * bs..(i + 1) * bs].copy_from_slice(&entry.data);
+                Self::touch(&mut inner, lba + i as u64);
+                i += 1;
+                continue;
+            }
+
+            // read every uncached block in this run with one request to the device
+            let mut end = i + 1;
+            while end < count && !inner.entries.contains_key(&(lba + end as u64)) {
+                end += 1;
+            }
+            self.device
+                .read_blocks(lba + i as u64, &mut buf[i * bs..end * bs])?;
+            for j in i..end {
+                let data = buf[j * bs..(j + 1) * bs].to_vec();
+                self.insert(&mut inner, lba + j as u64, data, false)?;
+            }
+            i = end;
+        }
+        Ok(())
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        let mut inner = self.inner.lock();
+
+        for (i, block) in buf.chunks(self.block_size()).enumerate() {
+            let lba = lba + i as u64;
+            match inner.entries.get_mut(&lba) {
+                Some(entry) => {
+                    entry.data.copy_from_slice(block);
+                    entry.dirty = true;
+                    Self::touch(&mut inner, lba);
+                }
+                None => self.insert(&mut inner, lba, block.to_vec(), true)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<(), BlockError> {
+        let mut inner = self.inner.lock();
+        for (lba, entry) in inner.entries.iter_mut().filter(|(_, e)| e.dirty) {
+            self.device.write_blocks(*lba, &entry.data)?;
+            entry.dirty = false;
+        }
+        self.device.flush()
+    }
+}
+
+#[test_case]
+fn cache_evicts_and_writes_back() {
+    use super::ramdisk::RamDisk;
+
+    let disk = Arc::new(RamDisk::new(8, 512));
+    let cache = BlockCache::new(disk.clone(), 2);
+    let mut buf = [0u8; 512];
+
+    for lba in 0..3u8 {
+        cache.write_blocks(lba as u64, &[lba + 1; 512]).unwrap();
+    }
+
+    // block 0 was evicted to make room for block 2, so only it has reached the disk
+    disk.read_blocks(0, &mut buf).unwrap();
+    assert_eq!(buf[0], 1);
+    disk.read_blocks(2, &mut buf).unwrap();
+    assert_eq!(buf[0], 0);
+
+    cache.read_blocks(2, &mut buf).unwrap();
+    assert_eq!(buf[0], 3);
+
+    cache.flush().unwrap();
+    disk.read_blocks(2, &mut buf).unwrap();
+    assert_eq!(buf[0], 3);
+}
@@ -0,0 +1,155 @@
+pub mod cache;
+pub mod partition;
+pub mod ramdisk;
+
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use core::fmt;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+use super::fs::FsError;
+use crate::println;
+
+pub use cache::BlockCache;
+pub use partition::{PartitionInfo, PartitionKind};
+
+/// how many blocks are cached in front of each disk, kept small until the heap can grow
+pub const DEFAULT_CACHE_BLOCKS: usize = 32;
+/// ram0 is 1 MiB of 512 byte blocks
+const RAMDISK_BLOCKS: usize = 2048;
+
+lazy_static! {
+    static ref BLOCK_DEVICES: Mutex<Vec<RegisteredDevice>> = Mutex::new(Vec::new());
+}
+
+#[derive(Clone)]
+pub struct RegisteredDevice {
+    pub name: String,
+    pub device: Arc<dyn BlockDevice>,
+    pub partition: Option<PartitionInfo>, // None for a whole disk
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    OutOfRange,
+    InvalidBufferSize,
+    ReadOnly,
+    IoError,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockError::OutOfRange => write!(f, "block out of range"),
+            BlockError::InvalidBufferSize => {
+                write!(f, "buffer is not a multiple of the block size")
+            }
+            BlockError::ReadOnly => write!(f, "device is read only"),
+            BlockError::IoError => write!(f, "input/output error"),
+        }
+    }
+}
+
+impl From<BlockError> for FsError {
+    fn from(e: BlockError) -> Self {
+        match e {
+            BlockError::ReadOnly => FsError::ReadOnly,
+            _ => FsError::IoError,
+        }
+    }
+}
+
+/// a device addressed in fixed size blocks, such as a disk, a partition or a ram disk.
+/// reads and writes always cover whole blocks, so `buf` must be a multiple of `block_size`.
+pub trait BlockDevice: Send + Sync {
+    fn block_size(&self) -> usize;
+
+    fn block_count(&self) -> u64;
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError>;
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError>;
+
+    /// makes sure every completed write has reached the device
+    fn flush(&self) -> Result<(), BlockError> {
+        Ok(())
+    }
+
+    /// size of the device in bytes
+    fn size(&self) -> u64 {
+        self.block_count() * self.block_size() as u64
+    }
+}
+
+/// checks that a transfer of `len` bytes at `lba` fits on the device, returning the number of blocks
+pub fn check_request(device: &dyn BlockDevice, lba: u64, len: usize) -> Result<u64, BlockError> {
+    let block_size = device.block_size();
+    if len % block_size != 0 {
+        return Err(BlockError::InvalidBufferSize);
+    }
+    let count = (len / block_size) as u64;
+    match lba.checked_add(count) {
+        Some(end) if end <= device.block_count() => Ok(count),
+        _ => Err(BlockError::OutOfRange),
+    }
+}
+
+/// registers ram0, a scratch disk in memory that is there whether or not any drives are found
+pub fn init() {
+    register("ram0", Arc::new(ramdisk::RamDisk::new(RAMDISK_BLOCKS, 512)));
+}
+
+/// makes a device available to filesystems under `name`, e.g. "sda"
+pub fn register(name: &str, device: Arc<dyn BlockDevice>) {
+    BLOCK_DEVICES.lock().push(RegisteredDevice {
+        name: String::from(name),
+        device,
+        partition: None,
+    });
+}
+
+/// registers a whole disk, then a device for each partition on it named sda1, sda2...
+pub fn add_disk(name: &str, device: Arc<dyn BlockDevice>) {
+    register(name, device.clone());
+
+    let partitions = match partition::scan(name, &*device) {
+        Ok(partitions) => partitions,
+        Err(e) => {
+            println!("failed to read the partition table on {}: {}", name, e);
+            return;
+        }
+    };
+    let mut devices = BLOCK_DEVICES.lock();
+    for info in partitions {
+        devices.push(RegisteredDevice {
+            name: format!("{}{}", name, info.number),
+            device: Arc::new(partition::Partition::new(
+                device.clone(),
+                info.start,
+                info.blocks,
+            )),
+            partition: Some(info),
+        });
+    }
+}
+
+pub fn get(name: &str) -> Option<Arc<dyn BlockDevice>> {
+    BLOCK_DEVICES
+        .lock()
+        .iter()
+        .find(|d| d.name == name)
+        .map(|d| d.device.clone())
+}
+
+/// every registered device, each disk is followed by its partitions
+pub fn devices() -> Vec<RegisteredDevice> {
+    BLOCK_DEVICES.lock().clone()
+}
+
+/// writes back every cached block on every device
+pub fn sync_all() -> Result<(), BlockError> {
+    for entry in devices() {
+        entry.device.flush()?;
+    }
+    Ok(())
+}
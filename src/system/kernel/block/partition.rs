@@ -0,0 +1,385 @@
+use super::{check_request, BlockDevice, BlockError};
+use alloc::{format, string::String, sync::Arc, vec, vec::Vec};
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+const MBR_TABLE: usize = 446;
+const MBR_TYPE_GPT: u8 = 0xEE;
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+
+// an extended partition can't chain more logical partitions than this, guards against loops
+const MAX_LOGICAL: usize = 128;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionKind {
+    Mbr(u8), // partition type byte
+    Gpt { type_guid: [u8; 16], name: String },
+}
+
+impl PartitionKind {
+    pub fn type_name(&self) -> String {
+        match self {
+            PartitionKind::Mbr(t) => String::from(mbr_type_name(*t)),
+            PartitionKind::Gpt { type_guid, .. } => {
+                let guid = guid_string(type_guid);
+                String::from(gpt_type_name(&guid).unwrap_or(&guid))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionInfo {
+    pub disk: String,
+    pub number: usize, // 1-4 for primary MBR partitions, 5+ for logical ones, GPT counts from 1
+    pub start: u64,
+    pub blocks: u64,
+    pub kind: PartitionKind,
+}
+
+/// a window onto a range of blocks of another device
+pub struct Partition {
+    device: Arc<dyn BlockDevice>,
+    start: u64,
+    blocks: u64,
+}
+
+impl Partition {
+    pub fn new(device: Arc<dyn BlockDevice>, start: u64, blocks: u64) -> Partition {
+        Partition {
+            device,
+            start,
+            blocks,
+        }
+    }
+}
+
+impl BlockDevice for Partition {
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.blocks
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        self.device.read_blocks(self.start + lba, buf)
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        self.device.write_blocks(self.start + lba, buf)
+    }
+
+    fn flush(&self) -> Result<(), BlockError> {
+        self.device.flush()
+    }
+}
+
+/// reads the partition table of a disk, returning nothing if it does not have one
+pub fn scan(disk: &str, device: &dyn BlockDevice) -> Result<Vec<PartitionInfo>, BlockError> {
+    let mut mbr = vec![0u8; device.block_size()];
+    device.read_blocks(0, &mut mbr)?;
+    if mbr[510..512] != MBR_SIGNATURE || looks_like_boot_sector(&mbr) {
+        return Ok(Vec::new());
+    }
+
+    let entries = (0..4)
+        .map(|i| MbrEntry::parse(&mbr[MBR_TABLE + i * 16..MBR_TABLE + (i + 1) * 16]))
+        .collect::<Vec<MbrEntry>>();
+
+    // the boot flag is the only byte with a fixed set of values, anything else means this is not
+    // a partition table at all
+    if entries.iter().any(|e| e.status != 0x00 && e.status != 0x80) {
+        return Ok(Vec::new());
+    }
+
+    if entries.iter().any(|e| e.kind == MBR_TYPE_GPT) {
+        return scan_gpt(disk, device);
+    }
+
+    let mut partitions = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.kind == 0 || entry.blocks == 0 {
+            continue;
+        }
+        if is_extended(entry.kind) {
+            scan_extended(disk, device, entry.start, &mut partitions)?;
+        } else {
+            partitions.push(PartitionInfo {
+                disk: String::from(disk),
+                number: i + 1,
+                start: entry.start,
+                blocks: entry.blocks,
+                kind: PartitionKind::Mbr(entry.kind),
+            });
+        }
+    }
+
+    partitions.retain(|p| p.start + p.blocks <= device.block_count());
+    Ok(partitions)
+}
+
+struct MbrEntry {
+    status: u8,
+    kind: u8,
+    start: u64,
+    blocks: u64,
+}
+
+impl MbrEntry {
+    fn parse(e: &[u8]) -> MbrEntry {
+        MbrEntry {
+            status: e[0],
+            kind: e[4],
+            start: u32_at(e, 8) as u64,
+            blocks: u32_at(e, 12) as u64,
+        }
+    }
+}
+
+fn is_extended(kind: u8) -> bool {
+    matches!(kind, 0x05 | 0x0F | 0x85)
+}
+
+/// a FAT or NTFS volume written straight onto the disk also ends in 0x55AA
+fn looks_like_boot_sector(sector: &[u8]) -> bool {
+    &sector[54..59] == b"FAT12"
+        || &sector[54..59] == b"FAT16"
+        || &sector[82..87] == b"FAT32"
+        || &sector[3..7] == b"NTFS"
+}
+
+/// follows the chain of extended boot records, each one describes a single logical partition and
+/// points at the next record. logical partitions are relative to their record, the links are
+/// relative to the start of the extended partition.
+fn scan_extended(
+    disk: &str,
+    device: &dyn BlockDevice,
+    base: u64,
+    partitions: &mut Vec<PartitionInfo>,
+) -> Result<(), BlockError> {
+    let mut ebr = vec![0u8; device.block_size()];
+    let mut current = base;
+
+    for number in 5..5 + MAX_LOGICAL {
+        if current >= device.block_count() {
+            break;
+        }
+        device.read_blocks(current, &mut ebr)?;
+        if ebr[510..512] != MBR_SIGNATURE {
+            break;
+        }
+
+        let logical = MbrEntry::parse(&ebr[MBR_TABLE..MBR_TABLE + 16]);
+        let next = MbrEntry::parse(&ebr[MBR_TABLE + 16..MBR_TABLE + 32]);
+
+        if logical.kind != 0 && logical.blocks != 0 {
+            partitions.push(PartitionInfo {
+                disk: String::from(disk),
+                number,
+                start: current + logical.start,
+                blocks: logical.blocks,
+                kind: PartitionKind::Mbr(logical.kind),
+            });
+        }
+
+        if !is_extended(next.kind) || next.start == 0 {
+            break;
+        }
+        current = base + next.start;
+    }
+    Ok(())
+}
+
+fn scan_gpt(disk: &str, device: &dyn BlockDevice) -> Result<Vec<PartitionInfo>, BlockError> {
+    // fall back to the backup header in the last block if the primary one or its table is damaged
+    let mut found = None;
+    for lba in [1, device.block_count() - 1] {
+        if let Some(header) = read_gpt_header(device, lba)? {
+            if let Some(table) = read_gpt_table(device, &header)? {
+                found = Some((header, table));
+                break;
+            }
+        }
+    }
+    let (header, table) = match found {
+        Some(x) => x,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut partitions = Vec::new();
+    for (i, e) in table.chunks(header.entry_size).enumerate() {
+        let mut type_guid = [0u8; 16];
+        type_guid.copy_from_slice(&e[..16]);
+        if type_guid == [0; 16] {
+            continue;
+        }
+
+        let first = u64_at(e, 32);
+        let last = u64_at(e, 40);
+        if last < first || last >= device.block_count() {
+            continue;
+        }
+
+        let name = (0..36)
+            .map(|c| u16::from_le_bytes([e[56 + c * 2], e[57 + c * 2]]))
+            .take_while(|c| *c != 0)
+            .collect::<Vec<u16>>();
+        partitions.push(PartitionInfo {
+            disk: String::from(disk),
+            number: i + 1,
+            start: first,
+            blocks: last - first + 1,
+            kind: PartitionKind::Gpt {
+                type_guid,
+                name: char::decode_utf16(name)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect(),
+            },
+        });
+    }
+    Ok(partitions)
+}
+
+struct GptHeader {
+    entries_lba: u64,
+    entries: usize,
+    entry_size: usize,
+    entries_crc: u32,
+}
+
+fn read_gpt_table(
+    device: &dyn BlockDevice,
+    header: &GptHeader,
+) -> Result<Option<Vec<u8>>, BlockError> {
+    let bs = device.block_size();
+    let len = header.entries * header.entry_size;
+    let mut table = vec![0u8; (len + bs - 1) / bs * bs];
+    device.read_blocks(header.entries_lba, &mut table)?;
+    table.truncate(len);
+    if crc32(&table) != header.entries_crc {
+        return Ok(None);
+    }
+    Ok(Some(table))
+}
+
+fn read_gpt_header(device: &dyn BlockDevice, lba: u64) -> Result<Option<GptHeader>, BlockError> {
+    let mut block = vec![0u8; device.block_size()];
+    device.read_blocks(lba, &mut block)?;
+    if &block[..8] != GPT_SIGNATURE {
+        return Ok(None);
+    }
+
+    let size = u32_at(&block, 12) as usize;
+    if size < 92 || size > block.len() {
+        return Ok(None);
+    }
+
+    // the header checksum is calculated with its own field set to zero
+    let crc = u32_at(&block, 16);
+    block[16..20].copy_from_slice(&[0; 4]);
+    if crc32(&block[..size]) != crc {
+        return Ok(None);
+    }
+
+    let entries = u32_at(&block, 80) as usize;
+    let entry_size = u32_at(&block, 84) as usize;
+    if entry_size < 128 || entries == 0 || entries > 1024 {
+        return Ok(None);
+    }
+
+    Ok(Some(GptHeader {
+        entries_lba: u64_at(&block, 72),
+        entries,
+        entry_size,
+        entries_crc: u32_at(&block, 88),
+    }))
+}
+
+/// the CRC-32 used by GPT (and zip, ethernet...), reflected with polynomial 0xEDB88320
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for b in data {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// GUIDs store their first three fields little endian and the rest big endian
+fn guid_string(g: &[u8; 16]) -> String {
+    format!(
+        "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}",
+        u32_at(g, 0),
+        u16::from_le_bytes([g[4], g[5]]),
+        u16::from_le_bytes([g[6], g[7]]),
+        g[8],
+        g[9],
+        g[10],
+        g[11],
+        g[12],
+        g[13],
+        g[14],
+        g[15]
+    )
+}
+
+fn gpt_type_name(guid: &str) -> Option<&'static str> {
+    match guid {
+        "C12A7328-F81F-11D2-BA4B-00A0C93EC93B" => Some("EFI System"),
+        "21686148-6449-6E6F-744E-656564454649" => Some("BIOS boot"),
+        "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7" => Some("Microsoft basic data"),
+        "E3C9E316-0B5C-4DB8-817D-F92DF00215AE" => Some("Microsoft reserved"),
+        "0FC63DAF-8483-4772-8E79-3D69D8477DE4" => Some("Linux filesystem"),
+        "0657FD6D-A4AB-43C4-84E5-0933C84B4F4F" => Some("Linux swap"),
+        "E6D6D379-F507-44C2-A23C-238F2A3DF928" => Some("Linux LVM"),
+        _ => None,
+    }
+}
+
+fn mbr_type_name(kind: u8) -> &'static str {
+    match kind {
+        0x01 => "FAT12",
+        0x04 | 0x06 | 0x0E => "FAT16",
+        0x05 | 0x0F | 0x85 => "Extended",
+        0x07 => "NTFS/exFAT",
+        0x0B => "FAT32",
+        0x0C => "FAT32 (LBA)",
+        0x82 => "Linux swap",
+        0x83 => "Linux",
+        0x8E => "Linux LVM",
+        0xA5 => "FreeBSD",
+        0xEE => "GPT protective",
+        0xEF => "EFI System",
+        0xFD => "Linux RAID",
+        _ => "Unknown",
+    }
+}
+
+fn u32_at(buf: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]])
+}
+
+fn u64_at(buf: &[u8], i: usize) -> u64 {
+    u32_at(buf, i) as u64 | (u32_at(buf, i + 4) as u64) << 32
+}
+
+#[test_case]
+fn crc32_check_value() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(
+        guid_string(&[
+            0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11, 0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E,
+            0xC9, 0x3B
+        ]),
+        "C12A7328-F81F-11D2-BA4B-00A0C93EC93B"
+    );
+}
@@ -0,0 +1,42 @@
+use super::{check_request, BlockDevice, BlockError};
+use alloc::{vec, vec::Vec};
+use spin::Mutex;
+
+/// a block device backed by the kernel heap, its contents are lost on reboot.
+pub struct RamDisk {
+    block_size: usize,
+    data: Mutex<Vec<u8>>,
+}
+
+impl RamDisk {
+    pub fn new(block_count: usize, block_size: usize) -> RamDisk {
+        RamDisk {
+            block_size,
+            data: Mutex::new(vec![0; block_count * block_size]),
+        }
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        (self.data.lock().len() / self.block_size) as u64
+    }
+
+    fn read_blocks(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        let start = lba as usize * self.block_size;
+        buf.copy_from_slice(&self.data.lock()[start..start + buf.len()]);
+        Ok(())
+    }
+
+    fn write_blocks(&self, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+        check_request(self, lba, buf.len())?;
+        let start = lba as usize * self.block_size;
+        self.data.lock()[start..start + buf.len()].copy_from_slice(buf);
+        Ok(())
+    }
+}
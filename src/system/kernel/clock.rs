@@ -0,0 +1,159 @@
+use conquer_once::spin::OnceCell;
+use core::arch::x86_64::_rdtsc;
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+use super::acpi;
+use super::memory::map_mmio;
+
+/// how often the timer interrupt fires
+pub const TICK_HZ: u64 = 100;
+const PIT_FREQUENCY: u64 = 1_193_182;
+const PIT_DIVISOR: u64 = (PIT_FREQUENCY + TICK_HZ / 2) / TICK_HZ;
+/// the real length of a tick, the divisor doesn't come out exact
+pub const TICK_NANOS: u64 = PIT_DIVISOR * 1_000_000_000 / PIT_FREQUENCY;
+
+/// how long the TSC is measured against a known clock for
+const CALIBRATION_NANOS: u64 = 10_000_000;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+static TSC: OnceCell<Tsc> = OnceCell::uninit();
+
+/// the time stamp counter, once its frequency is known
+struct Tsc {
+    hz: u64,
+    /// the counter and the tick based time when it took over
+    base: u64,
+    base_nanos: u64,
+}
+
+/// sets the PIT up to interrupt TICK_HZ times a second
+pub fn init() {
+    let mut command = Port::<u8>::new(0x43);
+    let mut channel0 = Port::<u8>::new(0x40);
+    unsafe {
+        // channel 0, low then high byte, square wave
+        command.write(0x36);
+        channel0.write(PIT_DIVISOR as u8);
+        channel0.write((PIT_DIVISOR >> 8) as u8);
+    }
+}
+
+/// counts a timer interrupt
+pub(super) fn tick() {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// timer interrupts since boot
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// nanoseconds since boot. only tick resolution until the TSC has been calibrated, and never
+/// goes backwards when it switches over.
+pub fn now() -> u64 {
+    match TSC.try_get() {
+        Ok(tsc) => {
+            let elapsed = unsafe { _rdtsc() }.saturating_sub(tsc.base);
+            tsc.base_nanos + (elapsed as u128 * 1_000_000_000 / tsc.hz as u128) as u64
+        }
+        Err(_) => ticks() * TICK_NANOS,
+    }
+}
+
+/// the first tick at which `now` is at least `nanos`
+pub fn tick_at(nanos: u64) -> u64 {
+    nanos / TICK_NANOS + (nanos % TICK_NANOS != 0) as u64
+}
+
+/// the TSC frequency in Hz, if it has been calibrated
+pub fn tsc_frequency() -> Option<u64> {
+    TSC.try_get().ok().map(|tsc| tsc.hz)
+}
+
+/// counts TSC cycles against the HPET main counter
+fn measure_with_hpet(hpet: acpi::Hpet) -> Option<u64> {
+    let base = map_mmio(hpet.address, 0x100).ok()?;
+    let register = |offset: u64| (base + offset).as_mut_ptr::<u64>();
+    unsafe {
+        // the top half of the capabilities is the counter period in femtoseconds
+        let capabilities = register(0x00).read_volatile();
+        let period = capabilities >> 32;
+        if period == 0 {
+            return None;
+        }
+        // without COUNT_SIZE_CAP the main counter is only 32 bits and can wrap while we measure
+        let mask = match capabilities & (1 << 13) {
+            0 => u32::MAX as u64,
+            _ => u64::MAX,
+        };
+        let config = register(0x10).read_volatile();
+        register(0x10).write_volatile(config | 1);
+
+        let counts = CALIBRATION_NANOS * 1_000_000 / period;
+        let start = register(0xF0).read_volatile() & mask;
+        let tsc_start = _rdtsc();
+        let mut elapsed = 0;
+        while elapsed < counts {
+            elapsed = (register(0xF0).read_volatile() & mask).wrapping_sub(start) & mask;
+        }
+        let cycles = _rdtsc() - tsc_start;
+        let femtos = elapsed as u128 * period as u128;
+        Some((cycles as u128 * 1_000_000_000_000_000 / femtos) as u64)
+    }
+}
+
+/// counts TSC cycles while PIT channel 2 counts down once, the speaker stays off
+fn measure_with_pit() -> u64 {
+    let mut gate = Port::<u8>::new(0x61);
+    let mut command = Port::<u8>::new(0x43);
+    let mut channel2 = Port::<u8>::new(0x42);
+    let count = PIT_FREQUENCY * CALIBRATION_NANOS / 1_000_000_000;
+    unsafe {
+        let speaker = gate.read() & !0b11;
+        gate.write(speaker);
+        // channel 2, low then high byte, interrupt on terminal count
+        command.write(0xB0);
+        channel2.write(count as u8);
+        channel2.write((count >> 8) as u8);
+
+        // raising the gate starts the count, bit 5 goes high when it reaches zero
+        gate.write(speaker | 1);
+        let tsc_start = _rdtsc();
+        while gate.read() & 0x20 == 0 {}
+        let cycles = _rdtsc() - tsc_start;
+        gate.write(speaker);
+        cycles * PIT_FREQUENCY / count
+    }
+}
+
+/// works out the TSC frequency with the HPET if ACPI lists one, otherwise with the PIT, and
+/// switches `now` over to it
+pub fn calibrate() {
+    interrupts::without_interrupts(|| {
+        let hz = acpi::hpet()
+            .and_then(measure_with_hpet)
+            .unwrap_or_else(measure_with_pit);
+        if hz == 0 {
+            return;
+        }
+        TSC.try_init_once(|| Tsc {
+            hz,
+            base: unsafe { _rdtsc() },
+            base_nanos: ticks() * TICK_NANOS,
+        })
+        .ok();
+    });
+}
+
+#[test_case]
+fn clock_moves_forward() {
+    let start = now();
+    let tick = ticks();
+    while ticks() < tick + 2 {
+        x86_64::instructions::hlt();
+    }
+    let elapsed = now() - start;
+    assert!(elapsed >= TICK_NANOS && elapsed < 10 * TICK_NANOS);
+}
@@ -0,0 +1,382 @@
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+use x86_64::structures::paging::{Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+use super::memory::{AddressSpace, USER_START};
+use super::process::{self, Pid, ProcessError};
+use super::usermode::{self, USER_STACK_PAGES, USER_STACK_TOP};
+
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_X86_64: u16 = 0x3E;
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const STT_NOTYPE: u8 = 0;
+const STT_FUNC: u8 = 2;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+
+const HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+const SECTION_HEADER_SIZE: usize = 64;
+const SYMBOL_SIZE: usize = 24;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ElfError {
+    NotElf,
+    /// a valid ELF file this loader cannot run, e.g. 32 bit or dynamically linked
+    Unsupported(&'static str),
+    /// a header points outside the file or a segment outside user space
+    Malformed(&'static str),
+    OutOfMemory,
+    Process(ProcessError),
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ElfError::NotElf => write!(f, "not an ELF file"),
+            ElfError::Unsupported(what) => write!(f, "unsupported executable: {}", what),
+            ElfError::Malformed(what) => write!(f, "malformed executable: {}", what),
+            ElfError::OutOfMemory => write!(f, "not enough memory to load the executable"),
+            ElfError::Process(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+pub fn is_elf(data: &[u8]) -> bool {
+    data.starts_with(&ELF_MAGIC)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    file_size: u64,
+    mem_size: u64,
+}
+
+/// the parts of an executable needed to load it
+#[derive(Debug)]
+struct Executable {
+    entry: u64,
+    segments: Vec<Segment>,
+}
+
+fn parse(data: &[u8]) -> Result<Executable, ElfError> {
+    if data.len() < HEADER_SIZE || !is_elf(data) {
+        return Err(ElfError::NotElf);
+    }
+    if data[4] != ELFCLASS64 {
+        return Err(ElfError::Unsupported("not a 64 bit executable"));
+    }
+    if data[5] != ELFDATA2LSB {
+        return Err(ElfError::Unsupported("not little endian"));
+    }
+    if u16_at(data, 16) != ET_EXEC {
+        return Err(ElfError::Unsupported("not a static executable"));
+    }
+    if u16_at(data, 18) != EM_X86_64 {
+        return Err(ElfError::Unsupported("not built for x86_64"));
+    }
+
+    let entry = u64_at(data, 24);
+    let ph_offset = u64_at(data, 32) as usize;
+    let ph_size = u16_at(data, 54) as usize;
+    let ph_count = u16_at(data, 56) as usize;
+    if ph_size < PROGRAM_HEADER_SIZE {
+        return Err(ElfError::Malformed("program headers are too small"));
+    }
+    match ph_count
+        .checked_mul(ph_size)
+        .and_then(|len| len.checked_add(ph_offset))
+    {
+        Some(end) if end <= data.len() => {}
+        _ => {
+            return Err(ElfError::Malformed(
+                "program headers run past the end of the file",
+            ))
+        }
+    }
+
+    let mut segments = Vec::new();
+    for i in 0..ph_count {
+        let ph = &data[ph_offset + i * ph_size..];
+        if u32_at(ph, 0) != PT_LOAD {
+            continue;
+        }
+        let segment = Segment {
+            flags: u32_at(ph, 4),
+            offset: u64_at(ph, 8),
+            vaddr: u64_at(ph, 16),
+            file_size: u64_at(ph, 32),
+            mem_size: u64_at(ph, 40),
+        };
+
+        if segment.file_size > segment.mem_size {
+            return Err(ElfError::Malformed(
+                "segment is larger in the file than in memory",
+            ));
+        }
+        match segment.offset.checked_add(segment.file_size) {
+            Some(end) if end <= data.len() as u64 => {}
+            _ => return Err(ElfError::Malformed("segment runs past the end of the file")),
+        }
+        match segment.vaddr.checked_add(segment.mem_size) {
+            Some(end)
+                if segment.vaddr >= USER_START
+                    && end <= USER_STACK_TOP - USER_STACK_PAGES * Page::<Size4KiB>::SIZE => {}
+            _ => return Err(ElfError::Malformed("segment is outside user space")),
+        }
+        segments.push(segment);
+    }
+
+    if !segments
+        .iter()
+        .any(|s| (s.vaddr..s.vaddr + s.mem_size).contains(&entry))
+    {
+        return Err(ElfError::Malformed("entry point is not inside a segment"));
+    }
+
+    Ok(Executable { entry, segments })
+}
+
+/// a function or label from the executable's symbol table, used to name addresses in fault
+/// reports
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub address: u64,
+    /// 0 for labels that don't record a size
+    pub size: u64,
+    pub name: String,
+}
+
+/// reads the symbol table if the executable still has one, anything malformed just gives up
+fn symbols(data: &[u8]) -> Vec<Symbol> {
+    let section = |i: usize| {
+        let sh_offset = u64_at(data, 40) as usize;
+        let sh_size = u16_at(data, 58) as usize;
+        let start = sh_offset.checked_add(i.checked_mul(sh_size)?)?;
+        if sh_size < SECTION_HEADER_SIZE || start.checked_add(SECTION_HEADER_SIZE)? > data.len() {
+            return None;
+        }
+        Some(&data[start..])
+    };
+    // (offset, size) of a section's contents, if they are inside the file
+    let contents = |sh: &[u8]| {
+        let (offset, size) = (u64_at(sh, 24) as usize, u64_at(sh, 32) as usize);
+        match offset.checked_add(size) {
+            Some(end) if end <= data.len() => Some((offset, size)),
+            _ => None,
+        }
+    };
+
+    let mut found = Vec::new();
+    for i in 0..u16_at(data, 60) as usize {
+        let sh = match section(i) {
+            Some(sh) if u32_at(sh, 4) == SHT_SYMTAB => sh,
+            _ => continue,
+        };
+        let strtab = match section(u32_at(sh, 40) as usize).and_then(contents) {
+            Some((offset, size)) => &data[offset..offset + size],
+            None => continue,
+        };
+        let (offset, size) = match contents(sh) {
+            Some(range) => range,
+            None => continue,
+        };
+
+        for sym in data[offset..offset + size].chunks_exact(SYMBOL_SIZE) {
+            let kind = sym[4] & 0xF;
+            let address = u64_at(sym, 8);
+            if (kind != STT_FUNC && kind != STT_NOTYPE) || u16_at(sym, 6) == 0 || address == 0 {
+                continue;
+            }
+            let name = strtab
+                .get(u32_at(sym, 0) as usize..)
+                .and_then(|s| s.split(|&b| b == 0).next())
+                .and_then(|s| core::str::from_utf8(s).ok());
+            match name {
+                Some(name) if !name.is_empty() => found.push(Symbol {
+                    address,
+                    size: u64_at(sym, 16),
+                    name: String::from(name),
+                }),
+                _ => {}
+            }
+        }
+    }
+    found.sort_by_key(|s| s.address);
+    found
+}
+
+/// the symbol `addr` falls in, and how far into it
+pub fn symbol_at(symbols: &[Symbol], addr: u64) -> Option<(&Symbol, u64)> {
+    let symbol = symbols.iter().rev().find(|s| s.address <= addr)?;
+    let offset = addr - symbol.address;
+    if symbol.size != 0 && offset >= symbol.size {
+        return None;
+    }
+    Some((symbol, offset))
+}
+
+/// an executable mapped into its own address space, ready to be started
+#[derive(Debug)]
+pub struct LoadedImage {
+    pub address_space: AddressSpace,
+    pub entry: VirtAddr,
+    pub stack_pointer: VirtAddr,
+    /// argc, argv and envp, handed over in rdi, rsi and rdx as well as on the stack
+    pub args: [u64; 3],
+    pub symbols: Vec<Symbol>,
+}
+
+/// maps every PT_LOAD segment of `data` into a fresh address space and builds the initial stack
+pub fn load(data: &[u8], args: &[String], env: &[String]) -> Result<LoadedImage, ElfError> {
+    let executable = parse(data)?;
+    let mut space = AddressSpace::new().ok_or(ElfError::OutOfMemory)?;
+
+    for segment in executable.segments.iter() {
+        if segment.mem_size == 0 {
+            continue;
+        }
+        let mut flags = PageTableFlags::empty();
+        if segment.flags & PF_W != 0 {
+            flags |= PageTableFlags::WRITABLE;
+        }
+        if segment.flags & PF_X == 0 {
+            flags |= PageTableFlags::NO_EXECUTE;
+        }
+
+        let start = Page::<Size4KiB>::containing_address(VirtAddr::new(segment.vaddr));
+        let end = Page::<Size4KiB>::containing_address(VirtAddr::new(
+            segment.vaddr + segment.mem_size - 1,
+        ));
+        for page in Page::range_inclusive(start, end) {
+            space
+                .map_user(page, flags)
+                .map_err(|_| ElfError::OutOfMemory)?;
+        }
+
+        // anything past file_size is bss and stays zeroed
+        let bytes = &data[segment.offset as usize..(segment.offset + segment.file_size) as usize];
+        space
+            .write(VirtAddr::new(segment.vaddr), bytes)
+            .map_err(|_| ElfError::OutOfMemory)?;
+    }
+
+    usermode::map_stack(&mut space).map_err(|_| ElfError::OutOfMemory)?;
+    let (stack_pointer, args) = build_stack(&mut space, args, env)?;
+
+    Ok(LoadedImage {
+        address_space: space,
+        entry: VirtAddr::new(executable.entry),
+        stack_pointer,
+        args,
+        symbols: symbols(data),
+    })
+}
+
+/// lays out the System V process stack: the strings go at the very top, below them auxv, envp
+/// and argv, and argc sits at the 16 byte aligned stack pointer
+fn build_stack(
+    space: &mut AddressSpace,
+    args: &[String],
+    env: &[String],
+) -> Result<(VirtAddr, [u64; 3]), ElfError> {
+    let mut strings = Vec::new();
+    let mut offsets = Vec::new();
+    for s in args.iter().chain(env.iter()) {
+        offsets.push(strings.len() as u64);
+        strings.extend_from_slice(s.as_bytes());
+        strings.push(0);
+    }
+    if strings.len() as u64 > USER_STACK_PAGES * Page::<Size4KiB>::SIZE / 2 {
+        return Err(ElfError::OutOfMemory);
+    }
+    let strings_start = (USER_STACK_TOP - strings.len() as u64) & !0xF;
+
+    let mut words = Vec::new();
+    words.push(args.len() as u64);
+    words.extend(offsets[..args.len()].iter().map(|o| strings_start + o));
+    words.push(0);
+    words.extend(offsets[args.len()..].iter().map(|o| strings_start + o));
+    words.push(0);
+    // an empty auxiliary vector, just AT_NULL
+    words.push(0);
+    words.push(0);
+
+    let stack_pointer = (strings_start - words.len() as u64 * 8) & !0xF;
+    let bytes = words
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .collect::<Vec<u8>>();
+
+    let write_failed = |_| ElfError::OutOfMemory;
+    space
+        .write(VirtAddr::new(strings_start), &strings)
+        .map_err(write_failed)?;
+    space
+        .write(VirtAddr::new(stack_pointer), &bytes)
+        .map_err(write_failed)?;
+
+    let argv = stack_pointer + 8;
+    let envp = argv + (args.len() as u64 + 1) * 8;
+    Ok((
+        VirtAddr::new(stack_pointer),
+        [args.len() as u64, argv, envp],
+    ))
+}
+
+/// loads a static executable and starts it as a new process
+pub fn spawn(name: &str, data: &[u8], args: &[String], env: &[String]) -> Result<Pid, ElfError> {
+    let image = load(data, args, env)?;
+    process::spawn(
+        name,
+        image.address_space,
+        image.entry,
+        image.stack_pointer,
+        image.args,
+        image.symbols,
+    )
+    .map_err(ElfError::Process)
+}
+
+#[test_case]
+fn rejects_bad_headers() {
+    assert_eq!(parse(b"not an elf").unwrap_err(), ElfError::NotElf);
+
+    let mut header = [0u8; HEADER_SIZE];
+    header[..4].copy_from_slice(&ELF_MAGIC);
+    header[4] = 1;
+    assert!(matches!(parse(&header), Err(ElfError::Unsupported(_))));
+
+    header[4] = ELFCLASS64;
+    header[5] = ELFDATA2LSB;
+    header[16] = ET_EXEC as u8;
+    header[18] = EM_X86_64 as u8;
+    header[54] = PROGRAM_HEADER_SIZE as u8;
+    header[56] = 1;
+    assert!(matches!(parse(&header), Err(ElfError::Malformed(_))));
+}
@@ -0,0 +1,272 @@
+use alloc::string::String;
+use core::fmt;
+use x86_64::instructions::interrupts;
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+
+use super::gdt;
+use super::process;
+use crate::{println, serial_println};
+
+/// a process killed by an exception exits with this plus the vector number, the same way a
+/// shell reports a process killed by a signal
+pub const FAULT_EXIT_BASE: i64 = 128;
+
+const PAGE_FAULT: u8 = 14;
+
+/// what the cpu told us about the fault on top of the stack frame
+enum Detail {
+    None,
+    ErrorCode(u64),
+    /// the error code of exceptions caused by loading a segment selector
+    Selector(u64),
+    PageFault(PageFaultErrorCode),
+}
+
+struct Report<'a> {
+    vector: u8,
+    name: &'static str,
+    frame: &'a InterruptStackFrame,
+    detail: Detail,
+    /// the process and symbol rip is in, only looked up for faults in user code
+    location: Option<String>,
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "EXCEPTION: {} (vector {})", self.name, self.vector)?;
+        match &self.detail {
+            Detail::None => {}
+            Detail::ErrorCode(code) => writeln!(f, "  error   {:#x}", code)?,
+            Detail::Selector(0) => writeln!(f, "  error   0 (no selector)")?,
+            Detail::Selector(code) => {
+                let table = match (code >> 1) & 0b11 {
+                    0 => "gdt",
+                    2 => "ldt",
+                    _ => "idt",
+                };
+                let external = if code & 1 != 0 { ", external" } else { "" };
+                writeln!(
+                    f,
+                    "  error   {:#x} ({} entry {}{})",
+                    code,
+                    table,
+                    code >> 3,
+                    external
+                )?;
+            }
+            Detail::PageFault(code) => {
+                match Cr2::read_raw() {
+                    0 => writeln!(f, "  address 0x0 (null pointer)")?,
+                    addr => writeln!(f, "  address {:#x}", addr)?,
+                }
+                write!(f, "  error   {:#x} (", code.bits())?;
+                page_fault_reason(f, *code)?;
+                writeln!(f, ")")?;
+            }
+        }
+        let frame = &**self.frame;
+        write!(f, "  rip     {:#x}", frame.instruction_pointer.as_u64())?;
+        match &self.location {
+            Some(location) => writeln!(f, " in {}", location)?,
+            None if frame.code_segment & 3 == 3 => writeln!(f, " in user code")?,
+            None => writeln!(f, " in the kernel")?,
+        }
+        write!(
+            f,
+            "  rsp     {:#x}  rflags {:#x}  cs {:#x}  ss {:#x}",
+            frame.stack_pointer.as_u64(),
+            frame.cpu_flags,
+            frame.code_segment,
+            frame.stack_segment
+        )
+    }
+}
+
+fn page_fault_reason(f: &mut fmt::Formatter, code: PageFaultErrorCode) -> fmt::Result {
+    let access = if code.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+        "execute"
+    } else if code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+        "write"
+    } else {
+        "read"
+    };
+    let cause = if code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+        "protection violation"
+    } else {
+        "page not present"
+    };
+    let mode = if code.contains(PageFaultErrorCode::USER_MODE) {
+        "user"
+    } else {
+        "kernel"
+    };
+    write!(f, "{} {}, {}", mode, access, cause)?;
+    if code.contains(PageFaultErrorCode::MALFORMED_TABLE) {
+        write!(f, ", reserved bit set in a page table")?;
+    }
+    if code.contains(PageFaultErrorCode::PROTECTION_KEY) {
+        write!(f, ", protection key")?;
+    }
+    Ok(())
+}
+
+fn report(vector: u8, name: &'static str, frame: &InterruptStackFrame, detail: Detail) -> bool {
+    let from_user = frame.code_segment & 3 == 3;
+    let report = Report {
+        vector,
+        name,
+        frame,
+        detail,
+        location: if from_user {
+            process::locate(frame.instruction_pointer)
+        } else {
+            None
+        },
+    };
+    serial_println!("{}", report);
+    println!("{}", report);
+    from_user
+}
+
+/// reports the exception, then kills the process if it came from user code. anything else is
+/// a kernel bug and panics.
+fn fault(vector: u8, name: &'static str, frame: &InterruptStackFrame, detail: Detail) {
+    if report(vector, name, frame, detail) {
+        // the thread was in user code so it holds no kernel locks, it can be preempted while
+        // it tears the process down like during any syscall
+        interrupts::enable();
+        process::exit(FAULT_EXIT_BASE + vector as i64);
+    }
+    panic!("{} in the kernel", name);
+}
+
+macro_rules! handler {
+    ($handler:ident, $vector:expr, $name:expr) => {
+        extern "x86-interrupt" fn $handler(frame: InterruptStackFrame) {
+            fault($vector, $name, &frame, Detail::None);
+        }
+    };
+    ($handler:ident, $vector:expr, $name:expr, $detail:path) => {
+        extern "x86-interrupt" fn $handler(frame: InterruptStackFrame, error_code: u64) {
+            fault($vector, $name, &frame, $detail(error_code));
+        }
+    };
+}
+
+handler!(divide_error, 0, "divide error");
+handler!(overflow, 4, "overflow");
+handler!(bound_range_exceeded, 5, "bound range exceeded");
+handler!(invalid_opcode, 6, "invalid opcode");
+handler!(device_not_available, 7, "device not available");
+handler!(invalid_tss, 10, "invalid TSS", Detail::Selector);
+handler!(
+    segment_not_present,
+    11,
+    "segment not present",
+    Detail::Selector
+);
+handler!(
+    stack_segment_fault,
+    12,
+    "stack segment fault",
+    Detail::Selector
+);
+handler!(
+    general_protection_fault,
+    13,
+    "general protection fault",
+    Detail::Selector
+);
+handler!(x87_floating_point, 16, "x87 floating point");
+handler!(alignment_check, 17, "alignment check", Detail::ErrorCode);
+handler!(simd_floating_point, 19, "SIMD floating point");
+handler!(virtualization, 20, "virtualization");
+handler!(cp_protection, 21, "control protection", Detail::ErrorCode);
+handler!(hv_injection, 28, "hypervisor injection");
+handler!(
+    vmm_communication,
+    29,
+    "VMM communication",
+    Detail::ErrorCode
+);
+handler!(security, 30, "security exception", Detail::ErrorCode);
+
+extern "x86-interrupt" fn page_fault(frame: InterruptStackFrame, error_code: PageFaultErrorCode) {
+    fault(
+        PAGE_FAULT,
+        "page fault",
+        &frame,
+        Detail::PageFault(error_code),
+    );
+}
+
+// debug, nmi and breakpoint are only reported, execution carries on afterwards
+
+extern "x86-interrupt" fn debug(frame: InterruptStackFrame) {
+    report(1, "debug", &frame, Detail::None);
+}
+
+extern "x86-interrupt" fn non_maskable_interrupt(frame: InterruptStackFrame) {
+    report(2, "non maskable interrupt", &frame, Detail::None);
+}
+
+extern "x86-interrupt" fn breakpoint(frame: InterruptStackFrame) {
+    report(3, "breakpoint", &frame, Detail::None);
+}
+
+extern "x86-interrupt" fn double_fault(frame: InterruptStackFrame, _error_code: u64) -> ! {
+    report(8, "double fault", &frame, Detail::None);
+    panic!("double fault")
+}
+
+extern "x86-interrupt" fn machine_check(frame: InterruptStackFrame) -> ! {
+    report(18, "machine check", &frame, Detail::None);
+    panic!("machine check")
+}
+
+/// installs a handler for every cpu exception
+pub fn register(idt: &mut InterruptDescriptorTable) {
+    idt.divide_error.set_handler_fn(divide_error);
+    idt.debug.set_handler_fn(debug);
+    idt.non_maskable_interrupt
+        .set_handler_fn(non_maskable_interrupt);
+    idt.breakpoint.set_handler_fn(breakpoint);
+    idt.overflow.set_handler_fn(overflow);
+    idt.bound_range_exceeded
+        .set_handler_fn(bound_range_exceeded);
+    idt.invalid_opcode.set_handler_fn(invalid_opcode);
+    idt.device_not_available
+        .set_handler_fn(device_not_available);
+    unsafe {
+        idt.double_fault
+            .set_handler_fn(double_fault)
+            .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+    }
+    idt.invalid_tss.set_handler_fn(invalid_tss);
+    idt.segment_not_present.set_handler_fn(segment_not_present);
+    idt.stack_segment_fault.set_handler_fn(stack_segment_fault);
+    idt.general_protection_fault
+        .set_handler_fn(general_protection_fault);
+    idt.page_fault.set_handler_fn(page_fault);
+    idt.x87_floating_point.set_handler_fn(x87_floating_point);
+    idt.alignment_check.set_handler_fn(alignment_check);
+    idt.machine_check.set_handler_fn(machine_check);
+    idt.simd_floating_point.set_handler_fn(simd_floating_point);
+    idt.virtualization.set_handler_fn(virtualization);
+    idt.cp_protection_exception.set_handler_fn(cp_protection);
+    idt.hv_injection_exception.set_handler_fn(hv_injection);
+    idt.vmm_communication_exception
+        .set_handler_fn(vmm_communication);
+    idt.security_exception.set_handler_fn(security);
+}
+
+#[test_case]
+fn user_page_fault_kills_the_process() {
+    use super::usermode;
+
+    // mov byte [0], 1
+    let image = [0xC6, 0x04, 0x25, 0x00, 0x00, 0x00, 0x00, 0x01];
+    let pid = usermode::spawn("fault test", &image).unwrap();
+    assert_eq!(process::wait(pid), Ok(FAULT_EXIT_BASE + PAGE_FAULT as i64));
+}
@@ -0,0 +1,1150 @@
+use super::{DirEntry, FileSystem, FileType, FsError, InodeId, Metadata};
+use crate::system::kernel::block::BlockDevice;
+use crate::system::kernel::rtc::{self, DateTime, SystemTime};
+use alloc::{collections::BTreeMap, format, string::String, sync::Arc, vec, vec::Vec};
+use spin::Mutex;
+
+const ROOT: InodeId = 1;
+
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0F;
+
+const ENTRY_SIZE: usize = 32;
+const ENTRY_FREE: u8 = 0xE5;
+const ENTRY_END: u8 = 0x00;
+
+const FAT_MASK: u32 = 0x0FFF_FFFF;
+const FAT_EOC: u32 = 0x0FFF_FFFF;
+const FAT_BAD: u32 = 0x0FFF_FFF7;
+
+// set in the reserved byte of a short entry by windows and linux for all lowercase 8.3 names
+const CASE_LOWER_BASE: u8 = 0x08;
+const CASE_LOWER_EXT: u8 = 0x10;
+
+const FSINFO_LEAD_SIG: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIG: u32 = 0x6141_7272;
+const FSINFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+// each long name entry holds 13 UTF-16 characters scattered across these offsets
+const LFN_CHARS: usize = 13;
+const LFN_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+struct Node {
+    file_type: FileType,
+    parent: InodeId,
+    cluster: u32, // first cluster, 0 for an empty file
+    size: u32,
+    pos: u64, // byte offset of the short entry on the volume, unused for the root
+}
+
+/// a directory entry as it was found on disk, with its long name already put back together
+struct RawEntry {
+    name: String,
+    short: [u8; 11],
+    attr: u8,
+    cluster: u32,
+    size: u32,
+    pos: u64,        // the short entry
+    slots: Vec<u64>, // every slot used by the entry, long name entries included
+}
+
+impl RawEntry {
+    fn file_type(&self) -> FileType {
+        if self.attr & ATTR_DIRECTORY != 0 {
+            FileType::Directory
+        } else {
+            FileType::File
+        }
+    }
+}
+
+struct Inner {
+    nodes: BTreeMap<InodeId, Node>,
+    by_pos: BTreeMap<u64, InodeId>,
+    next: InodeId,
+    free_clusters: u32, // both come from FSInfo and are only hints
+    next_free: u32,
+}
+
+/// a FAT32 volume on a block device. FAT has no inode numbers, so inodes are handed out the first
+/// time an entry is looked up and follow the entry around if it is renamed.
+pub struct Fat32 {
+    device: Arc<dyn BlockDevice>,
+    bytes_per_sector: usize,
+    sectors_per_cluster: usize,
+    fat_start: u64,
+    fat_sectors: u64,
+    num_fats: u64,
+    data_start: u64, // first sector of cluster 2
+    cluster_count: u32,
+    fsinfo_sector: Option<u64>,
+    inner: Mutex<Inner>,
+}
+
+impl Fat32 {
+    /// reads the BIOS parameter block, failing with `Unsupported` if the device is not FAT32
+    pub fn new(device: Arc<dyn BlockDevice>) -> Result<Fat32, FsError> {
+        let block_size = device.block_size();
+        if block_size < 512 {
+            return Err(FsError::Unsupported);
+        }
+        let mut boot = vec![0u8; block_size];
+        device.read_blocks(0, &mut boot)?;
+
+        if boot[510] != 0x55 || boot[511] != 0xAA {
+            return Err(FsError::Unsupported);
+        }
+
+        let bytes_per_sector = u16_at(&boot, 11) as usize;
+        let sectors_per_cluster = boot[13] as usize;
+        let reserved = u16_at(&boot, 14) as u64;
+        let num_fats = boot[16] as u64;
+        let root_entries = u16_at(&boot, 17);
+        let total_16 = u16_at(&boot, 19) as u64;
+        let fat_size_16 = u16_at(&boot, 22);
+        let total_32 = u32_at(&boot, 32) as u64;
+        let fat_sectors = u32_at(&boot, 36) as u64;
+        let root_cluster = u32_at(&boot, 44);
+        let fsinfo = u16_at(&boot, 48) as u64;
+
+        // FAT12/16 volumes have a fixed root directory and a 16 bit FAT size
+        if root_entries != 0 || fat_size_16 != 0 || fat_sectors == 0 {
+            return Err(FsError::Unsupported);
+        }
+        if !matches!(bytes_per_sector, 512 | 1024 | 2048 | 4096)
+            || bytes_per_sector % block_size != 0
+            || !sectors_per_cluster.is_power_of_two()
+            || num_fats == 0
+        {
+            return Err(FsError::Unsupported);
+        }
+
+        let total = if total_16 != 0 { total_16 } else { total_32 };
+        let data_start = reserved + num_fats * fat_sectors;
+        if total <= data_start {
+            return Err(FsError::Unsupported);
+        }
+        let cluster_count = ((total - data_start) / sectors_per_cluster as u64) as u32;
+
+        let mut fs = Fat32 {
+            device,
+            bytes_per_sector,
+            sectors_per_cluster,
+            fat_start: reserved,
+            fat_sectors,
+            num_fats,
+            data_start,
+            cluster_count,
+            fsinfo_sector: None,
+            inner: Mutex::new(Inner {
+                nodes: BTreeMap::new(),
+                by_pos: BTreeMap::new(),
+                next: ROOT + 1,
+                free_clusters: FSINFO_UNKNOWN,
+                next_free: 2,
+            }),
+        };
+
+        if fsinfo != 0 && fsinfo < reserved {
+            let mut buf = vec![0u8; bytes_per_sector];
+            fs.read_sectors(fsinfo, &mut buf)?;
+            if u32_at(&buf, 0) == FSINFO_LEAD_SIG && u32_at(&buf, 484) == FSINFO_STRUCT_SIG {
+                let mut inner = fs.inner.lock();
+                inner.free_clusters = u32_at(&buf, 488);
+                inner.next_free = u32_at(&buf, 492);
+                drop(inner);
+                fs.fsinfo_sector = Some(fsinfo);
+            }
+        }
+
+        fs.inner.lock().nodes.insert(
+            ROOT,
+            Node {
+                file_type: FileType::Directory,
+                parent: ROOT,
+                cluster: root_cluster,
+                size: 0,
+                pos: 0,
+            },
+        );
+        Ok(fs)
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.bytes_per_sector * self.sectors_per_cluster
+    }
+
+    fn cluster_sector(&self, cluster: u32) -> u64 {
+        self.data_start + (cluster as u64 - 2) * self.sectors_per_cluster as u64
+    }
+
+    fn read_sectors(&self, sector: u64, buf: &mut [u8]) -> Result<(), FsError> {
+        let ratio = (self.bytes_per_sector / self.device.block_size()) as u64;
+        Ok(self.device.read_blocks(sector * ratio, buf)?)
+    }
+
+    fn write_sectors(&self, sector: u64, buf: &[u8]) -> Result<(), FsError> {
+        let ratio = (self.bytes_per_sector / self.device.block_size()) as u64;
+        Ok(self.device.write_blocks(sector * ratio, buf)?)
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.cluster_count + 2
+    }
+
+    fn fat_get(&self, cluster: u32) -> Result<u32, FsError> {
+        let offset = cluster as u64 * 4;
+        let bps = self.bytes_per_sector as u64;
+        let mut buf = vec![0u8; self.bytes_per_sector];
+        self.read_sectors(self.fat_start + offset / bps, &mut buf)?;
+        Ok(u32_at(&buf, (offset % bps) as usize) & FAT_MASK)
+    }
+
+    /// updates an entry in every copy of the FAT, the top four bits are reserved and kept as they are
+    fn fat_set(&self, cluster: u32, value: u32) -> Result<(), FsError> {
+        let offset = cluster as u64 * 4;
+        let bps = self.bytes_per_sector as u64;
+        let mut buf = vec![0u8; self.bytes_per_sector];
+        for fat in 0..self.num_fats {
+            let sector = self.fat_start + fat * self.fat_sectors + offset / bps;
+            self.read_sectors(sector, &mut buf)?;
+            let idx = (offset % bps) as usize;
+            let old = u32_at(&buf, idx);
+            put_u32(&mut buf, idx, (old & !FAT_MASK) | (value & FAT_MASK));
+            self.write_sectors(sector, &buf)?;
+        }
+        Ok(())
+    }
+
+    /// follows a cluster chain from its first cluster, an empty file has no clusters at all
+    fn chain(&self, first: u32) -> Result<Vec<u32>, FsError> {
+        let mut clusters = Vec::new();
+        let mut cluster = first;
+        if cluster == 0 {
+            return Ok(clusters);
+        }
+        loop {
+            // a chain longer than the volume must loop back on itself
+            if !self.is_valid_cluster(cluster) || clusters.len() > self.cluster_count as usize {
+                return Err(FsError::IoError);
+            }
+            clusters.push(cluster);
+            match self.fat_get(cluster)? {
+                x if x >= 0x0FFF_FFF8 => return Ok(clusters),
+                FAT_BAD => return Err(FsError::IoError),
+                x => cluster = x,
+            }
+        }
+    }
+
+    /// finds a free cluster, zeroes it and links it onto the end of `prev` if given
+    fn alloc_cluster(&self, inner: &mut Inner, prev: Option<u32>) -> Result<u32, FsError> {
+        let bps = self.bytes_per_sector;
+        let mut buf = vec![0u8; bps];
+        let mut loaded = None;
+
+        let mut cluster = if self.is_valid_cluster(inner.next_free) {
+            inner.next_free
+        } else {
+            2
+        };
+        for _ in 0..self.cluster_count {
+            let offset = cluster as usize * 4;
+            let sector = self.fat_start + (offset / bps) as u64;
+            if loaded != Some(sector) {
+                self.read_sectors(sector, &mut buf)?;
+                loaded = Some(sector);
+            }
+
+            if u32_at(&buf, offset % bps) & FAT_MASK == 0 {
+                self.fat_set(cluster, FAT_EOC)?;
+                if let Some(prev) = prev {
+                    self.fat_set(prev, cluster)?;
+                }
+                self.zero_cluster(cluster)?;
+                inner.next_free = cluster + 1;
+                if inner.free_clusters != FSINFO_UNKNOWN {
+                    inner.free_clusters = inner.free_clusters.saturating_sub(1);
+                }
+                return Ok(cluster);
+            }
+
+            cluster += 1;
+            if !self.is_valid_cluster(cluster) {
+                cluster = 2;
+            }
+        }
+        Err(FsError::NoSpace)
+    }
+
+    fn free_clusters(&self, inner: &mut Inner, clusters: &[u32]) -> Result<(), FsError> {
+        for &cluster in clusters {
+            self.fat_set(cluster, 0)?;
+            if inner.free_clusters != FSINFO_UNKNOWN {
+                inner.free_clusters += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn zero_cluster(&self, cluster: u32) -> Result<(), FsError> {
+        let zeroes = vec![0u8; self.bytes_per_sector];
+        let start = self.cluster_sector(cluster);
+        for sector in start..start + self.sectors_per_cluster as u64 {
+            self.write_sectors(sector, &zeroes)?;
+        }
+        Ok(())
+    }
+
+    /// copies bytes from a cluster chain, `pos` is relative to the start of the chain
+    fn read_at(&self, clusters: &[u32], pos: usize, buf: &mut [u8]) -> Result<(), FsError> {
+        let bps = self.bytes_per_sector;
+        let mut sector_buf = vec![0u8; bps];
+        let mut done = 0;
+        while done < buf.len() {
+            let (sector, in_sector) = self.locate(clusters, pos + done)?;
+            let len = (bps - in_sector).min(buf.len() - done);
+            if len == bps {
+                self.read_sectors(sector, &mut buf[done..done + bps])?;
+            } else {
+                self.read_sectors(sector, &mut sector_buf)?;
+                buf[done..done + len].copy_from_slice(&sector_buf[in_sector..in_sector + len]);
+            }
+            done += len;
+        }
+        Ok(())
+    }
+
+    fn write_at(&self, clusters: &[u32], pos: usize, buf: &[u8]) -> Result<(), FsError> {
+        let bps = self.bytes_per_sector;
+        let mut sector_buf = vec![0u8; bps];
+        let mut done = 0;
+        while done < buf.len() {
+            let (sector, in_sector) = self.locate(clusters, pos + done)?;
+            let len = (bps - in_sector).min(buf.len() - done);
+            if len == bps {
+                self.write_sectors(sector, &buf[done..done + bps])?;
+            } else {
+                self.read_sectors(sector, &mut sector_buf)?;
+                sector_buf[in_sector..in_sector + len].copy_from_slice(&buf[done..done + len]);
+                self.write_sectors(sector, &sector_buf)?;
+            }
+            done += len;
+        }
+        Ok(())
+    }
+
+    /// turns an offset into a cluster chain into a sector and an offset within that sector
+    fn locate(&self, clusters: &[u32], pos: usize) -> Result<(u64, usize), FsError> {
+        let cluster = *clusters
+            .get(pos / self.cluster_size())
+            .ok_or(FsError::IoError)?;
+        let in_cluster = pos % self.cluster_size();
+        let sector = self.cluster_sector(cluster) + (in_cluster / self.bytes_per_sector) as u64;
+        Ok((sector, in_cluster % self.bytes_per_sector))
+    }
+
+    fn read_slot(&self, pos: u64) -> Result<[u8; ENTRY_SIZE], FsError> {
+        let bps = self.bytes_per_sector as u64;
+        let mut buf = vec![0u8; self.bytes_per_sector];
+        self.read_sectors(pos / bps, &mut buf)?;
+        let idx = (pos % bps) as usize;
+        let mut entry = [0u8; ENTRY_SIZE];
+        entry.copy_from_slice(&buf[idx..idx + ENTRY_SIZE]);
+        Ok(entry)
+    }
+
+    fn update_slot(&self, pos: u64, f: impl FnOnce(&mut [u8])) -> Result<(), FsError> {
+        let bps = self.bytes_per_sector as u64;
+        let mut buf = vec![0u8; self.bytes_per_sector];
+        self.read_sectors(pos / bps, &mut buf)?;
+        let idx = (pos % bps) as usize;
+        f(&mut buf[idx..idx + ENTRY_SIZE]);
+        self.write_sectors(pos / bps, &buf)
+    }
+
+    /// calls `f` with the position and contents of every slot in a directory, stopping early if it
+    /// returns false
+    fn for_each_slot(
+        &self,
+        dir_cluster: u32,
+        mut f: impl FnMut(u64, &[u8]) -> bool,
+    ) -> Result<(), FsError> {
+        let bps = self.bytes_per_sector;
+        let mut buf = vec![0u8; bps];
+        for cluster in self.chain(dir_cluster)? {
+            let start = self.cluster_sector(cluster);
+            for sector in start..start + self.sectors_per_cluster as u64 {
+                self.read_sectors(sector, &mut buf)?;
+                for (i, slot) in buf.chunks(ENTRY_SIZE).enumerate() {
+                    if !f(sector * bps as u64 + (i * ENTRY_SIZE) as u64, slot) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_dir(&self, dir_cluster: u32) -> Result<Vec<RawEntry>, FsError> {
+        let mut entries = Vec::new();
+        let mut long: Vec<[u16; LFN_CHARS]> = Vec::new();
+        let mut slots = Vec::new();
+        let mut checksum = 0;
+
+        self.for_each_slot(dir_cluster, |pos, e| {
+            if e[0] == ENTRY_END {
+                return false;
+            }
+            if e[0] == ENTRY_FREE {
+                long.clear();
+                slots.clear();
+                return true;
+            }
+
+            if e[11] & 0x3F == ATTR_LONG_NAME {
+                if e[0] & 0x40 != 0 {
+                    long.clear();
+                    slots.clear();
+                    checksum = e[13];
+                }
+                let mut chars = [0u16; LFN_CHARS];
+                for (c, off) in chars.iter_mut().zip(LFN_OFFSETS.iter()) {
+                    *c = u16_at(e, *off);
+                }
+                long.push(chars);
+                slots.push(pos);
+                return true;
+            }
+
+            let mut short = [0u8; 11];
+            short.copy_from_slice(&e[..11]);
+            if e[11] & ATTR_VOLUME_ID != 0 || short[0] == b'.' {
+                long.clear();
+                slots.clear();
+                return true;
+            }
+
+            // a long name only belongs to this entry if its checksum matches the short name
+            let name = if !long.is_empty() && checksum == lfn_checksum(&short) {
+                decode_long_name(&long)
+            } else {
+                decode_short_name(&short, e[12])
+            };
+            slots.push(pos);
+
+            entries.push(RawEntry {
+                name,
+                short,
+                attr: e[11],
+                cluster: get_cluster(e),
+                size: u32_at(e, 28),
+                pos,
+                slots: core::mem::take(&mut slots),
+            });
+            long.clear();
+            true
+        })?;
+        Ok(entries)
+    }
+
+    fn find(&self, dir_cluster: u32, name: &str) -> Result<RawEntry, FsError> {
+        self.read_dir(dir_cluster)?
+            .into_iter()
+            .find(|e| e.name.eq_ignore_ascii_case(name))
+            .ok_or(FsError::NotFound)
+    }
+
+    /// finds `count` free slots in a row, growing the directory by a cluster at a time if needed
+    fn free_slots(
+        &self,
+        inner: &mut Inner,
+        dir_cluster: u32,
+        count: usize,
+    ) -> Result<Vec<u64>, FsError> {
+        let mut run = Vec::new();
+        self.for_each_slot(dir_cluster, |pos, e| {
+            if e[0] == ENTRY_FREE || e[0] == ENTRY_END {
+                run.push(pos);
+            } else {
+                run.clear();
+            }
+            run.len() < count
+        })?;
+
+        let mut last = *self.chain(dir_cluster)?.last().ok_or(FsError::IoError)?;
+        while run.len() < count {
+            last = self.alloc_cluster(inner, Some(last))?;
+            let start = self.cluster_sector(last) * self.bytes_per_sector as u64;
+            let slots = (self.cluster_size() / ENTRY_SIZE) as u64;
+            run.extend((0..slots).map(|i| start + i * ENTRY_SIZE as u64));
+        }
+        run.truncate(count);
+        Ok(run)
+    }
+
+    /// writes `entry` into a directory under `name`, along with long name entries if the name
+    /// cannot be stored as 8.3. returns the position of the short entry.
+    fn add_entry(
+        &self,
+        inner: &mut Inner,
+        dir_cluster: u32,
+        name: &str,
+        mut entry: [u8; ENTRY_SIZE],
+    ) -> Result<u64, FsError> {
+        let existing = self.read_dir(dir_cluster)?;
+        if existing.iter().any(|e| e.name.eq_ignore_ascii_case(name)) {
+            return Err(FsError::AlreadyExists);
+        }
+
+        let (short, case, long) = match short_name(name) {
+            Some((short, case)) if !existing.iter().any(|e| e.short == short) => {
+                (short, case, Vec::new())
+            }
+            _ => (
+                generate_short_name(name, &existing),
+                0,
+                name.encode_utf16().collect::<Vec<u16>>(),
+            ),
+        };
+
+        let long_count = (long.len() + LFN_CHARS - 1) / LFN_CHARS;
+        let slots = self.free_slots(inner, dir_cluster, long_count + 1)?;
+        let checksum = lfn_checksum(&short);
+
+        // long name entries are stored last part first, right before the short entry
+        for (i, pos) in slots[..long_count].iter().enumerate() {
+            let seq = long_count - i;
+            let mut e = [0u8; ENTRY_SIZE];
+            e[0] = seq as u8 | if i == 0 { 0x40 } else { 0 };
+            e[11] = ATTR_LONG_NAME;
+            e[13] = checksum;
+            for (j, off) in LFN_OFFSETS.iter().enumerate() {
+                let idx = (seq - 1) * LFN_CHARS + j;
+                let c = match idx {
+                    x if x < long.len() => long[x],
+                    x if x == long.len() => 0,
+                    _ => 0xFFFF,
+                };
+                put_u16(&mut e, *off, c);
+            }
+            self.update_slot(*pos, |slot| slot.copy_from_slice(&e))?;
+        }
+
+        entry[..11].copy_from_slice(&short);
+        entry[12] = case;
+        let pos = slots[long_count];
+        self.update_slot(pos, |slot| slot.copy_from_slice(&entry))?;
+        Ok(pos)
+    }
+
+    fn delete_entry(&self, inner: &mut Inner, entry: &RawEntry) -> Result<(), FsError> {
+        for pos in entry.slots.iter() {
+            self.update_slot(*pos, |slot| slot[0] = ENTRY_FREE)?;
+        }
+        if let Some(inode) = inner.by_pos.remove(&entry.pos) {
+            inner.nodes.remove(&inode);
+        }
+        Ok(())
+    }
+
+    /// writes the cached size and first cluster of a node back into its directory entry
+    fn update_node(&self, inode: InodeId, node: &Node) -> Result<(), FsError> {
+        if inode == ROOT {
+            return Ok(());
+        }
+        let (date, time) = timestamp();
+        self.update_slot(node.pos, |e| {
+            set_cluster(e, node.cluster);
+            if node.file_type == FileType::File {
+                put_u32(e, 28, node.size);
+            }
+            put_u16(e, 22, time);
+            put_u16(e, 24, date);
+            put_u16(e, 18, date);
+        })
+    }
+
+    fn inode_for(&self, inner: &mut Inner, parent: InodeId, entry: &RawEntry) -> InodeId {
+        if let Some(inode) = inner.by_pos.get(&entry.pos) {
+            return *inode;
+        }
+        let inode = inner.next;
+        inner.next += 1;
+        inner.nodes.insert(
+            inode,
+            Node {
+                file_type: entry.file_type(),
+                parent,
+                cluster: entry.cluster,
+                size: entry.size,
+                pos: entry.pos,
+            },
+        );
+        inner.by_pos.insert(entry.pos, inode);
+        inode
+    }
+
+    fn dir_cluster(&self, inner: &Inner, inode: InodeId) -> Result<u32, FsError> {
+        let node = inner.nodes.get(&inode).ok_or(FsError::NotFound)?;
+        match node.file_type {
+            FileType::Directory => Ok(node.cluster),
+            FileType::File => Err(FsError::NotADirectory),
+        }
+    }
+
+    fn file_node<'a>(&self, inner: &'a mut Inner, inode: InodeId) -> Result<&'a mut Node, FsError> {
+        let node = inner.nodes.get_mut(&inode).ok_or(FsError::NotFound)?;
+        match node.file_type {
+            FileType::File => Ok(node),
+            FileType::Directory => Err(FsError::IsADirectory),
+        }
+    }
+
+    /// true if `inode` is `ancestor` or somewhere below it
+    fn is_descendant(&self, inner: &Inner, mut inode: InodeId, ancestor: InodeId) -> bool {
+        loop {
+            if inode == ancestor {
+                return true;
+            }
+            if inode == ROOT {
+                return false;
+            }
+            inode = match inner.nodes.get(&inode) {
+                Some(node) => node.parent,
+                None => return false,
+            };
+        }
+    }
+
+    fn write_locked(
+        &self,
+        inner: &mut Inner,
+        inode: InodeId,
+        offset: usize,
+        buf: &[u8],
+    ) -> Result<usize, FsError> {
+        let (first, size) = {
+            let node = self.file_node(inner, inode)?;
+            (node.cluster, node.size as usize)
+        };
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let end = offset + buf.len();
+        if end > u32::MAX as usize {
+            return Err(FsError::NoSpace);
+        }
+
+        let mut clusters = self.chain(first)?;
+        let allocated = clusters.len() * self.cluster_size();
+        let needed = (end + self.cluster_size() - 1) / self.cluster_size();
+        while clusters.len() < needed {
+            let cluster = self.alloc_cluster(inner, clusters.last().copied())?;
+            clusters.push(cluster);
+        }
+
+        // new clusters are already zeroed, but the old last cluster may still hold stale data
+        // between the end of the file and where this write starts
+        if offset > size {
+            let gap_end = offset.min(allocated);
+            if gap_end > size {
+                self.write_at(&clusters, size, &vec![0u8; gap_end - size])?;
+            }
+        }
+        self.write_at(&clusters, offset, buf)?;
+
+        let node = self.file_node(inner, inode)?;
+        node.cluster = clusters[0];
+        node.size = node.size.max(end as u32);
+        let node = inner.nodes.get(&inode).ok_or(FsError::NotFound)?;
+        self.update_node(inode, node)?;
+        Ok(buf.len())
+    }
+}
+
+impl FileSystem for Fat32 {
+    fn name(&self) -> &str {
+        "fat32"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT
+    }
+
+    fn lookup(&self, dir: InodeId, name: &str) -> Result<InodeId, FsError> {
+        let mut inner = self.inner.lock();
+        let entry = self.find(self.dir_cluster(&inner, dir)?, name)?;
+        Ok(self.inode_for(&mut inner, dir, &entry))
+    }
+
+    fn metadata(&self, inode: InodeId) -> Result<Metadata, FsError> {
+        let inner = self.inner.lock();
+        let node = inner.nodes.get(&inode).ok_or(FsError::NotFound)?;
+        // the root directory has no entry of its own to keep times in
+        let (created, modified) = match inode {
+            ROOT => (None, None),
+            _ => {
+                let e = self.read_slot(node.pos)?;
+                (
+                    from_timestamp(u16_at(&e, 16), u16_at(&e, 14)),
+                    from_timestamp(u16_at(&e, 24), u16_at(&e, 22)),
+                )
+            }
+        };
+        Ok(Metadata {
+            inode,
+            file_type: node.file_type,
+            size: match node.file_type {
+                FileType::File => node.size as usize,
+                FileType::Directory => self.read_dir(node.cluster)?.len(),
+            },
+            created,
+            modified,
+        })
+    }
+
+    fn read(&self, inode: InodeId, offset: usize, buf: &mut [u8]) -> Result<usize, FsError> {
+        let mut inner = self.inner.lock();
+        let node = self.file_node(&mut inner, inode)?;
+        let size = node.size as usize;
+        if offset >= size {
+            return Ok(0);
+        }
+        let n = buf.len().min(size - offset);
+        let clusters = self.chain(node.cluster)?;
+        self.read_at(&clusters, offset, &mut buf[..n])?;
+        Ok(n)
+    }
+
+    fn write(&self, inode: InodeId, offset: usize, buf: &[u8]) -> Result<usize, FsError> {
+        let mut inner = self.inner.lock();
+        self.write_locked(&mut inner, inode, offset, buf)
+    }
+
+    fn truncate(&self, inode: InodeId, size: usize) -> Result<(), FsError> {
+        let mut inner = self.inner.lock();
+        let (first, old_size) = {
+            let node = self.file_node(&mut inner, inode)?;
+            (node.cluster, node.size as usize)
+        };
+
+        if size > old_size {
+            // writing the last byte zero fills everything in between
+            self.write_locked(&mut inner, inode, size - 1, &[0])?;
+            return Ok(());
+        }
+
+        let clusters = self.chain(first)?;
+        let keep = (size + self.cluster_size() - 1) / self.cluster_size();
+        if keep < clusters.len() {
+            if keep > 0 {
+                self.fat_set(clusters[keep - 1], FAT_EOC)?;
+            }
+            self.free_clusters(&mut inner, &clusters[keep..])?;
+        }
+
+        let node = self.file_node(&mut inner, inode)?;
+        node.size = size as u32;
+        if keep == 0 {
+            node.cluster = 0;
+        }
+        let node = inner.nodes.get(&inode).ok_or(FsError::NotFound)?;
+        self.update_node(inode, node)
+    }
+
+    fn create(&self, dir: InodeId, name: &str, file_type: FileType) -> Result<InodeId, FsError> {
+        if !is_valid_name(name) {
+            return Err(FsError::InvalidPath);
+        }
+
+        let mut inner = self.inner.lock();
+        let dir_cluster = self.dir_cluster(&inner, dir)?;
+        if self.find(dir_cluster, name).is_ok() {
+            return Err(FsError::AlreadyExists);
+        }
+
+        let pos = match file_type {
+            FileType::File => {
+                self.add_entry(&mut inner, dir_cluster, name, new_entry(ATTR_ARCHIVE, 0))?
+            }
+            FileType::Directory => {
+                let cluster = self.alloc_cluster(&mut inner, None)?;
+
+                // ".." points at cluster 0 when the parent is the root directory
+                let parent = if dir == ROOT { 0 } else { dir_cluster };
+                let start = self.cluster_sector(cluster) * self.bytes_per_sector as u64;
+                let mut dot = new_entry(ATTR_DIRECTORY, cluster);
+                dot[..11].copy_from_slice(b".          ");
+                let mut dotdot = new_entry(ATTR_DIRECTORY, parent);
+                dotdot[..11].copy_from_slice(b"..         ");
+                self.update_slot(start, |e| e.copy_from_slice(&dot))?;
+                self.update_slot(start + ENTRY_SIZE as u64, |e| e.copy_from_slice(&dotdot))?;
+
+                let entry = new_entry(ATTR_DIRECTORY, cluster);
+                match self.add_entry(&mut inner, dir_cluster, name, entry) {
+                    Ok(pos) => pos,
+                    Err(e) => {
+                        self.free_clusters(&mut inner, &[cluster])?;
+                        return Err(e);
+                    }
+                }
+            }
+        };
+
+        let entry = self
+            .read_dir(dir_cluster)?
+            .into_iter()
+            .find(|e| e.pos == pos)
+            .ok_or(FsError::IoError)?;
+        Ok(self.inode_for(&mut inner, dir, &entry))
+    }
+
+    fn remove(&self, dir: InodeId, name: &str) -> Result<(), FsError> {
+        let mut inner = self.inner.lock();
+        let entry = self.find(self.dir_cluster(&inner, dir)?, name)?;
+
+        if entry.file_type() == FileType::Directory && !self.read_dir(entry.cluster)?.is_empty() {
+            return Err(FsError::DirectoryNotEmpty);
+        }
+
+        let clusters = self.chain(entry.cluster)?;
+        self.free_clusters(&mut inner, &clusters)?;
+        self.delete_entry(&mut inner, &entry)
+    }
+
+    fn rename(
+        &self,
+        from_dir: InodeId,
+        from_name: &str,
+        to_dir: InodeId,
+        to_name: &str,
+    ) -> Result<(), FsError> {
+        if !is_valid_name(to_name) {
+            return Err(FsError::InvalidPath);
+        }
+
+        let mut inner = self.inner.lock();
+        let from_cluster = self.dir_cluster(&inner, from_dir)?;
+        let to_cluster = self.dir_cluster(&inner, to_dir)?;
+        let src = self.find(from_cluster, from_name)?;
+        let inode = self.inode_for(&mut inner, from_dir, &src);
+        let file_type = src.file_type();
+
+        // a directory cannot be moved inside itself
+        if file_type == FileType::Directory && self.is_descendant(&inner, to_dir, inode) {
+            return Err(FsError::InvalidPath);
+        }
+
+        let mut same_entry = false;
+        if let Ok(existing) = self.find(to_cluster, to_name) {
+            if existing.pos == src.pos {
+                if existing.name == to_name {
+                    return Ok(());
+                }
+                // only the case of the name is changing
+                same_entry = true;
+            } else {
+                match (file_type, existing.file_type()) {
+                    (FileType::File, FileType::Directory) => return Err(FsError::IsADirectory),
+                    (FileType::Directory, FileType::File) => return Err(FsError::NotADirectory),
+                    (FileType::Directory, FileType::Directory)
+                        if !self.read_dir(existing.cluster)?.is_empty() =>
+                    {
+                        return Err(FsError::DirectoryNotEmpty)
+                    }
+                    _ => {}
+                }
+                let clusters = self.chain(existing.cluster)?;
+                self.free_clusters(&mut inner, &clusters)?;
+                self.delete_entry(&mut inner, &existing)?;
+            }
+        }
+
+        // the old entry is copied so the attributes and creation time survive the move
+        let raw = self.read_slot(src.pos)?;
+        let pos = if same_entry {
+            self.delete_entry(&mut inner, &src)?;
+            self.add_entry(&mut inner, to_cluster, to_name, raw)?
+        } else {
+            let pos = self.add_entry(&mut inner, to_cluster, to_name, raw)?;
+            self.delete_entry(&mut inner, &src)?;
+            pos
+        };
+
+        if file_type == FileType::Directory && from_dir != to_dir {
+            let parent = if to_dir == ROOT { 0 } else { to_cluster };
+            // ".." is normally the second slot, but some tools put long name entries before it
+            let mut dotdot = None;
+            self.for_each_slot(src.cluster, |pos, e| {
+                if &e[..11] == b"..         " && e[11] & 0x3F != ATTR_LONG_NAME {
+                    dotdot = Some(pos);
+                }
+                dotdot.is_none() && e[0] != ENTRY_END
+            })?;
+            if let Some(pos) = dotdot {
+                self.update_slot(pos, |e| set_cluster(e, parent))?;
+            }
+        }
+
+        // delete_entry forgot the node, so put it back under its new position
+        inner.nodes.insert(
+            inode,
+            Node {
+                file_type,
+                parent: to_dir,
+                cluster: src.cluster,
+                size: src.size,
+                pos,
+            },
+        );
+        inner.by_pos.insert(pos, inode);
+        Ok(())
+    }
+
+    fn readdir(&self, dir: InodeId) -> Result<Vec<DirEntry>, FsError> {
+        let mut inner = self.inner.lock();
+        let entries = self.read_dir(self.dir_cluster(&inner, dir)?)?;
+        Ok(entries
+            .iter()
+            .map(|e| DirEntry {
+                name: e.name.clone(),
+                inode: self.inode_for(&mut inner, dir, e),
+                file_type: e.file_type(),
+            })
+            .collect())
+    }
+
+    fn sync(&self) -> Result<(), FsError> {
+        let inner = self.inner.lock();
+        if let Some(sector) = self.fsinfo_sector {
+            let mut buf = vec![0u8; self.bytes_per_sector];
+            self.read_sectors(sector, &mut buf)?;
+            put_u32(&mut buf, 488, inner.free_clusters);
+            put_u32(&mut buf, 492, inner.next_free);
+            self.write_sectors(sector, &buf)?;
+        }
+        Ok(self.device.flush()?)
+    }
+}
+
+/// a short directory entry with everything filled in except the name
+fn new_entry(attr: u8, cluster: u32) -> [u8; ENTRY_SIZE] {
+    let (date, time) = timestamp();
+    let mut e = [0u8; ENTRY_SIZE];
+    e[11] = attr;
+    put_u16(&mut e, 14, time);
+    put_u16(&mut e, 16, date);
+    put_u16(&mut e, 18, date);
+    put_u16(&mut e, 22, time);
+    put_u16(&mut e, 24, date);
+    set_cluster(&mut e, cluster);
+    e
+}
+
+/// FAT (date, time) for new and modified entries. FAT keeps local time in two second steps and
+/// can't go before 1980.
+fn timestamp() -> (u16, u16) {
+    let now = DateTime::now();
+    let year = now.year.clamp(1980, 2107) as u16;
+    let date = (year - 1980) << 9 | (now.month as u16) << 5 | now.day as u16;
+    let time = (now.hour as u16) << 11 | (now.minute as u16) << 5 | (now.second as u16) / 2;
+    (date, time)
+}
+
+/// the inverse of `timestamp`, None if the entry never had a date set
+fn from_timestamp(date: u16, time: u16) -> Option<SystemTime> {
+    if date == 0 {
+        return None;
+    }
+    DateTime {
+        year: 1980 + (date >> 9) as i64,
+        month: (date >> 5 & 0x0F) as u8,
+        day: (date & 0x1F) as u8,
+        hour: (time >> 11) as u8,
+        minute: (time >> 5 & 0x3F) as u8,
+        second: (time & 0x1F) as u8 * 2,
+        nanosecond: 0,
+        offset: rtc::timezone_offset(),
+    }
+    .to_system_time()
+}
+
+fn get_cluster(e: &[u8]) -> u32 {
+    (u16_at(e, 20) as u32) << 16 | u16_at(e, 26) as u32
+}
+
+fn set_cluster(e: &mut [u8], cluster: u32) {
+    put_u16(e, 20, (cluster >> 16) as u16);
+    put_u16(e, 26, cluster as u16);
+}
+
+fn lfn_checksum(short: &[u8; 11]) -> u8 {
+    short.iter().fold(0u8, |sum, b| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(*b)
+    })
+}
+
+fn decode_long_name(parts: &[[u16; LFN_CHARS]]) -> String {
+    let units = parts
+        .iter()
+        .rev()
+        .flat_map(|p| p.iter().copied())
+        .take_while(|c| *c != 0)
+        .collect::<Vec<u16>>();
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+fn decode_short_name(short: &[u8; 11], case: u8) -> String {
+    let part = |bytes: &[u8], lower: bool| -> String {
+        bytes
+            .iter()
+            .enumerate()
+            .map(|(i, b)| match (i, *b) {
+                (0, 0x05) => 0xE5 as char, // 0xE5 as a first byte is stored as 0x05
+                (_, b) if lower => b.to_ascii_lowercase() as char,
+                (_, b) => b as char,
+            })
+            .collect::<String>()
+            .trim_end()
+            .into()
+    };
+    let base = part(&short[..8], case & CASE_LOWER_BASE != 0);
+    let ext = part(&short[8..], case & CASE_LOWER_EXT != 0);
+    if ext.is_empty() {
+        base
+    } else {
+        format!("{}.{}", base, ext)
+    }
+}
+
+fn is_short_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"$%'-_@~`!(){}^#&".contains(&b)
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.encode_utf16().count() <= 255
+        && !name.chars().all(|c| c == '.' || c == ' ')
+        && !name
+            .chars()
+            .any(|c| (c as u32) < 0x20 || "\"*/:<>?\\|".contains(c))
+}
+
+/// the 8.3 form of a name if it can be stored without a long name, along with its case flags
+fn short_name(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, ext) = match name.rfind('.') {
+        Some(0) => return None,
+        Some(i) => (&name[..i], &name[i + 1..]),
+        None => (name, ""),
+    };
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 || name.ends_with('.') {
+        return None;
+    }
+    if !base.bytes().chain(ext.bytes()).all(is_short_char) {
+        return None;
+    }
+
+    // each half has to be entirely one case to be stored without a long name
+    let case = |s: &str, flag: u8| {
+        let lower = s.bytes().any(|b| b.is_ascii_lowercase());
+        let upper = s.bytes().any(|b| b.is_ascii_uppercase());
+        match (lower, upper) {
+            (true, true) => None,
+            (true, false) => Some(flag),
+            _ => Some(0),
+        }
+    };
+    let flags = case(base, CASE_LOWER_BASE)? | case(ext, CASE_LOWER_EXT)?;
+
+    let mut short = [b' '; 11];
+    for (i, b) in base.bytes().enumerate() {
+        short[i] = b.to_ascii_uppercase();
+    }
+    for (i, b) in ext.bytes().enumerate() {
+        short[8 + i] = b.to_ascii_uppercase();
+    }
+    Some((short, flags))
+}
+
+/// builds a unique `BASIS~N.EXT` short name for a name that needs a long name entry
+fn generate_short_name(name: &str, existing: &[RawEntry]) -> [u8; 11] {
+    let upper = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    let trimmed = upper.trim_start_matches(|c| c == '.' || c == ' ');
+    let (base, ext) = match trimmed.rfind('.') {
+        Some(i) => (&trimmed[..i], &trimmed[i + 1..]),
+        None => (trimmed, ""),
+    };
+    let clean = |s: &str| {
+        s.bytes()
+            .filter(|b| *b != b' ' && *b != b'.')
+            .map(|b| if is_short_char(b) { b } else { b'_' })
+            .collect::<Vec<u8>>()
+    };
+    let mut base = clean(base);
+    let ext = clean(ext);
+    if base.is_empty() {
+        base.push(b'_');
+    }
+
+    let mut n = 1;
+    loop {
+        let tail = format!("~{}", n);
+        let keep = (8 - tail.len()).min(base.len());
+        let mut short = [b' '; 11];
+        short[..keep].copy_from_slice(&base[..keep]);
+        short[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+        for (i, b) in ext.iter().take(3).enumerate() {
+            short[8 + i] = *b;
+        }
+        if !existing.iter().any(|e| e.short == short) {
+            return short;
+        }
+        n += 1;
+    }
+}
+
+fn u16_at(buf: &[u8], i: usize) -> u16 {
+    u16::from_le_bytes([buf[i], buf[i + 1]])
+}
+
+fn u32_at(buf: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]])
+}
+
+fn put_u16(buf: &mut [u8], i: usize, value: u16) {
+    buf[i..i + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut [u8], i: usize, value: u32) {
+    buf[i..i + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+#[test_case]
+fn fat_short_names() {
+    assert_eq!(short_name("README.TXT"), Some((*b"README  TXT", 0)));
+    assert_eq!(
+        short_name("notes.md"),
+        Some((*b"NOTES   MD ", CASE_LOWER_BASE | CASE_LOWER_EXT))
+    );
+    assert_eq!(short_name("Readme.txt"), None);
+    assert_eq!(short_name("a long name.txt"), None);
+    assert_eq!(&generate_short_name("a long name.txt", &[]), b"ALONGN~1TXT");
+    assert_eq!(
+        decode_short_name(b"NOTES   MD ", CASE_LOWER_BASE),
+        "notes.MD"
+    );
+}
@@ -0,0 +1,391 @@
+pub mod fat32;
+pub mod ramfs;
+
+use super::block::{self, BlockDevice};
+use super::rtc::SystemTime;
+use crate::println;
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::fmt;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+lazy_static! {
+    pub static ref VFS: Mutex<Vfs> = Mutex::new(Vfs::new());
+}
+
+/// mounts a fresh ramfs at the root of the tree, this must run after the heap has been initialised
+pub fn init() -> Result<(), FsError> {
+    let root = Arc::new(ramfs::RamFs::new());
+    root.create(root.root(), "tmp", FileType::Directory)?;
+    root.create(root.root(), "mnt", FileType::Directory)?;
+    VFS.lock().mount("/", root)
+}
+
+/// mounts the filesystem on `device` at `path`, FAT32 is the only on-disk format so far
+pub fn mount_device(path: &str, device: Arc<dyn BlockDevice>) -> Result<(), FsError> {
+    let fs = Arc::new(fat32::Fat32::new(device)?);
+    VFS.lock().mount(path, fs)
+}
+
+/// mounts every block device that holds a filesystem we understand at /mnt/<device>
+pub fn mount_disks() {
+    for entry in block::devices() {
+        let name = entry.name;
+        let fs = match fat32::Fat32::new(entry.device) {
+            Ok(fs) => Arc::new(fs),
+            Err(_) => continue,
+        };
+
+        let path = format!("/mnt/{}", name);
+        let res = {
+            let mut vfs = VFS.lock();
+            vfs.resolve_parent(&path)
+                .and_then(|(parent, name)| {
+                    match parent.fs.create(parent.inode, &name, FileType::Directory) {
+                        Err(FsError::AlreadyExists) => Ok(0),
+                        res => res,
+                    }
+                })
+                .and_then(|_| vfs.mount(&path, fs))
+        };
+        match res {
+            Ok(()) => println!("mounted {} at {}", name, path),
+            Err(e) => println!("failed to mount {}: {}", name, e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    NotFound,
+    AlreadyExists,
+    NotADirectory,
+    IsADirectory,
+    DirectoryNotEmpty,
+    InvalidPath,
+    NotMounted,
+    CrossDevice,
+    ReadOnly,
+    NoSpace,
+    IoError,
+    Unsupported,
+    /// an argument other than the path was wrong, like seeking to before the start of a file
+    InvalidArgument,
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FsError::NotFound => write!(f, "no such file or directory"),
+            FsError::AlreadyExists => write!(f, "file already exists"),
+            FsError::NotADirectory => write!(f, "not a directory"),
+            FsError::IsADirectory => write!(f, "is a directory"),
+            FsError::DirectoryNotEmpty => write!(f, "directory not empty"),
+            FsError::InvalidPath => write!(f, "invalid path"),
+            FsError::NotMounted => write!(f, "no filesystem mounted at this path"),
+            FsError::CrossDevice => write!(f, "cannot move files between filesystems"),
+            FsError::ReadOnly => write!(f, "read only filesystem"),
+            FsError::NoSpace => write!(f, "no space left on device"),
+            FsError::IoError => write!(f, "input/output error"),
+            FsError::Unsupported => write!(f, "operation not supported"),
+            FsError::InvalidArgument => write!(f, "invalid argument"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Directory,
+}
+
+/// identifies a file or directory within a single filesystem, only unique per mount
+pub type InodeId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub inode: InodeId,
+    pub file_type: FileType,
+    pub size: usize,
+    /// None when the filesystem doesn't keep times for this node
+    pub created: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+}
+
+impl Metadata {
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type == FileType::File
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub inode: InodeId,
+    pub file_type: FileType,
+}
+
+/// implemented by every filesystem that can be mounted into the vfs.
+/// all paths have already been resolved by the time these methods are called, so a filesystem
+/// only ever sees inode ids and single path components.
+pub trait FileSystem: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn root(&self) -> InodeId;
+
+    fn lookup(&self, dir: InodeId, name: &str) -> Result<InodeId, FsError>;
+
+    fn metadata(&self, inode: InodeId) -> Result<Metadata, FsError>;
+
+    fn read(&self, inode: InodeId, offset: usize, buf: &mut [u8]) -> Result<usize, FsError>;
+
+    fn write(&self, inode: InodeId, offset: usize, buf: &[u8]) -> Result<usize, FsError>;
+
+    fn truncate(&self, inode: InodeId, size: usize) -> Result<(), FsError>;
+
+    fn create(&self, dir: InodeId, name: &str, file_type: FileType) -> Result<InodeId, FsError>;
+
+    /// removes a file or an empty directory
+    fn remove(&self, dir: InodeId, name: &str) -> Result<(), FsError>;
+
+    fn rename(
+        &self,
+        from_dir: InodeId,
+        from_name: &str,
+        to_dir: InodeId,
+        to_name: &str,
+    ) -> Result<(), FsError>;
+
+    fn readdir(&self, dir: InodeId) -> Result<Vec<DirEntry>, FsError>;
+
+    /// flushes any cached data to the underlying device
+    fn sync(&self) -> Result<(), FsError> {
+        Ok(())
+    }
+}
+
+/// a file or directory resolved through the vfs
+#[derive(Clone)]
+pub struct VNode {
+    pub fs: Arc<dyn FileSystem>,
+    pub inode: InodeId,
+}
+
+impl VNode {
+    pub fn metadata(&self) -> Result<Metadata, FsError> {
+        self.fs.metadata(self.inode)
+    }
+}
+
+struct Mount {
+    path: String,
+    fs: Arc<dyn FileSystem>,
+}
+
+/// the virtual filesystem, maps absolute paths onto the filesystems mounted in the tree.
+pub struct Vfs {
+    mounts: Vec<Mount>, // sorted longest path first so the deepest mount always matches first
+    cwd: String,
+}
+
+impl Vfs {
+    pub fn new() -> Self {
+        Vfs {
+            mounts: Vec::new(),
+            cwd: String::from("/"),
+        }
+    }
+
+    pub fn mount(&mut self, path: &str, fs: Arc<dyn FileSystem>) -> Result<(), FsError> {
+        let path = self.absolute(path)?;
+
+        if self.mounts.iter().any(|m| m.path == path) {
+            return Err(FsError::AlreadyExists);
+        }
+
+        // everything except the root must be mounted over an existing directory
+        if path != "/" && !self.resolve(&path)?.metadata()?.is_dir() {
+            return Err(FsError::NotADirectory);
+        }
+
+        self.mounts.push(Mount { path, fs });
+        self.mounts.sort_by(|a, b| b.path.len().cmp(&a.path.len()));
+        Ok(())
+    }
+
+    pub fn unmount(&mut self, path: &str) -> Result<Arc<dyn FileSystem>, FsError> {
+        let path = self.absolute(path)?;
+
+        if self
+            .mounts
+            .iter()
+            .any(|m| m.path != path && Vfs::is_under(&m.path, &path))
+        {
+            return Err(FsError::DirectoryNotEmpty);
+        }
+
+        let idx = self
+            .mounts
+            .iter()
+            .position(|m| m.path == path)
+            .ok_or(FsError::NotMounted)?;
+
+        let mount = self.mounts.remove(idx);
+        mount.fs.sync()?;
+        Ok(mount.fs)
+    }
+
+    /// returns (mount path, filesystem name) for every mounted filesystem
+    pub fn mounts(&self) -> Vec<(String, String)> {
+        self.mounts
+            .iter()
+            .rev()
+            .map(|m| (m.path.clone(), m.fs.name().to_string()))
+            .collect()
+    }
+
+    /// flushes every mounted filesystem
+    pub fn sync(&self) -> Result<(), FsError> {
+        for mount in self.mounts.iter() {
+            mount.fs.sync()?;
+        }
+        Ok(())
+    }
+
+    pub fn cwd(&self) -> String {
+        self.cwd.clone()
+    }
+
+    pub fn set_cwd(&mut self, path: &str) -> Result<(), FsError> {
+        let path = self.absolute(path)?;
+        if !self.resolve(&path)?.metadata()?.is_dir() {
+            return Err(FsError::NotADirectory);
+        }
+        self.cwd = path;
+        Ok(())
+    }
+
+    /// turns a path relative to the current directory into a normalised absolute path
+    pub fn absolute(&self, path: &str) -> Result<String, FsError> {
+        if path.is_empty() {
+            return Err(FsError::InvalidPath);
+        }
+        if path.starts_with('/') {
+            normalise(path)
+        } else {
+            normalise(&(self.cwd.clone() + "/" + path))
+        }
+    }
+
+    pub fn resolve(&self, path: &str) -> Result<VNode, FsError> {
+        let path = self.absolute(path)?;
+        let (mount, rest) = self.mount_for(&path)?;
+
+        let mut inode = mount.fs.root();
+        for component in rest.split('/').filter(|c| !c.is_empty()) {
+            inode = mount.fs.lookup(inode, component)?;
+        }
+
+        Ok(VNode {
+            fs: mount.fs.clone(),
+            inode,
+        })
+    }
+
+    /// resolves the directory containing `path`, returning it along with the final path component
+    pub fn resolve_parent(&self, path: &str) -> Result<(VNode, String), FsError> {
+        let path = self.absolute(path)?;
+        let (parent, name) = split_path(&path).ok_or(FsError::InvalidPath)?;
+
+        if self.mounts.iter().any(|m| m.path == path) {
+            // the root of a mounted filesystem cannot be created, removed or renamed
+            return Err(FsError::InvalidPath);
+        }
+
+        let parent = self.resolve(parent)?;
+        if !parent.metadata()?.is_dir() {
+            return Err(FsError::NotADirectory);
+        }
+        Ok((parent, name.to_string()))
+    }
+
+    fn mount_for<'a>(&self, path: &'a str) -> Result<(&Mount, &'a str), FsError> {
+        let mount = self
+            .mounts
+            .iter()
+            .find(|m| Vfs::is_under(path, &m.path))
+            .ok_or(FsError::NotMounted)?;
+
+        let rest = if mount.path == "/" {
+            path
+        } else {
+            &path[mount.path.len()..]
+        };
+        Ok((mount, rest))
+    }
+
+    fn is_under(path: &str, dir: &str) -> bool {
+        dir == "/"
+            || path == dir
+            || (path.starts_with(dir) && path.as_bytes().get(dir.len()) == Some(&b'/'))
+    }
+}
+
+/// collapses `.`, `..` and repeated slashes in an absolute path
+pub fn normalise(path: &str) -> Result<String, FsError> {
+    if !path.starts_with('/') {
+        return Err(FsError::InvalidPath);
+    }
+
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            x => components.push(x),
+        }
+    }
+
+    let mut res = String::new();
+    for component in components {
+        res.push('/');
+        res.push_str(component);
+    }
+    if res.is_empty() {
+        res.push('/');
+    }
+    Ok(res)
+}
+
+/// splits a normalised absolute path into its parent directory and final component
+pub fn split_path(path: &str) -> Option<(&str, &str)> {
+    let idx = path.rfind('/')?;
+    let name = &path[idx + 1..];
+    if name.is_empty() {
+        return None;
+    }
+    let parent = if idx == 0 { "/" } else { &path[..idx] };
+    Some((parent, name))
+}
+
+#[test_case]
+fn normalise_paths() {
+    assert_eq!(normalise("/").unwrap(), "/");
+    assert_eq!(normalise("//a/./b/../c/").unwrap(), "/a/c");
+    assert_eq!(normalise("/../..").unwrap(), "/");
+    assert_eq!(normalise("a/b"), Err(FsError::InvalidPath));
+    assert_eq!(split_path("/a/b"), Some(("/a", "b")));
+    assert_eq!(split_path("/a"), Some(("/", "a")));
+    assert_eq!(split_path("/"), None);
+}
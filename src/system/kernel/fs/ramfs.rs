@@ -0,0 +1,292 @@
+use super::{DirEntry, FileSystem, FileType, FsError, InodeId, Metadata};
+use crate::system::kernel::rtc::SystemTime;
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+use spin::Mutex;
+
+const ROOT: InodeId = 1;
+
+struct Node {
+    file_type: FileType,
+    parent: InodeId,
+    data: Vec<u8>,
+    children: BTreeMap<String, InodeId>,
+    created: SystemTime,
+    modified: SystemTime,
+}
+
+impl Node {
+    fn new(file_type: FileType, parent: InodeId) -> Node {
+        let now = SystemTime::now();
+        Node {
+            file_type,
+            parent,
+            data: Vec::new(),
+            children: BTreeMap::new(),
+            created: now,
+            modified: now,
+        }
+    }
+}
+
+struct Inner {
+    nodes: BTreeMap<InodeId, Node>,
+    next: InodeId,
+}
+
+impl Inner {
+    fn node(&self, inode: InodeId) -> Result<&Node, FsError> {
+        self.nodes.get(&inode).ok_or(FsError::NotFound)
+    }
+
+    fn node_mut(&mut self, inode: InodeId) -> Result<&mut Node, FsError> {
+        self.nodes.get_mut(&inode).ok_or(FsError::NotFound)
+    }
+
+    fn dir(&self, inode: InodeId) -> Result<&Node, FsError> {
+        let node = self.node(inode)?;
+        match node.file_type {
+            FileType::Directory => Ok(node),
+            FileType::File => Err(FsError::NotADirectory),
+        }
+    }
+
+    fn file_mut(&mut self, inode: InodeId) -> Result<&mut Node, FsError> {
+        let node = self.node_mut(inode)?;
+        match node.file_type {
+            FileType::File => Ok(node),
+            FileType::Directory => Err(FsError::IsADirectory),
+        }
+    }
+
+    /// a directory's entries changed
+    fn touch(&mut self, dir: InodeId) -> Result<(), FsError> {
+        self.node_mut(dir)?.modified = SystemTime::now();
+        Ok(())
+    }
+
+    /// true if `inode` is `ancestor` or somewhere below it
+    fn is_descendant(&self, mut inode: InodeId, ancestor: InodeId) -> bool {
+        loop {
+            if inode == ancestor {
+                return true;
+            }
+            if inode == ROOT {
+                return false;
+            }
+            inode = match self.nodes.get(&inode) {
+                Some(node) => node.parent,
+                None => return false,
+            };
+        }
+    }
+}
+
+/// a filesystem that lives entirely on the kernel heap, its contents are lost on reboot.
+pub struct RamFs {
+    inner: Mutex<Inner>,
+}
+
+impl RamFs {
+    pub fn new() -> RamFs {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(ROOT, Node::new(FileType::Directory, ROOT));
+        RamFs {
+            inner: Mutex::new(Inner {
+                nodes,
+                next: ROOT + 1,
+            }),
+        }
+    }
+}
+
+impl FileSystem for RamFs {
+    fn name(&self) -> &str {
+        "ramfs"
+    }
+
+    fn root(&self) -> InodeId {
+        ROOT
+    }
+
+    fn lookup(&self, dir: InodeId, name: &str) -> Result<InodeId, FsError> {
+        let inner = self.inner.lock();
+        inner
+            .dir(dir)?
+            .children
+            .get(name)
+            .copied()
+            .ok_or(FsError::NotFound)
+    }
+
+    fn metadata(&self, inode: InodeId) -> Result<Metadata, FsError> {
+        let inner = self.inner.lock();
+        let node = inner.node(inode)?;
+        Ok(Metadata {
+            inode,
+            file_type: node.file_type,
+            size: match node.file_type {
+                FileType::File => node.data.len(),
+                FileType::Directory => node.children.len(),
+            },
+            created: Some(node.created),
+            modified: Some(node.modified),
+        })
+    }
+
+    fn read(&self, inode: InodeId, offset: usize, buf: &mut [u8]) -> Result<usize, FsError> {
+        let mut inner = self.inner.lock();
+        let node = inner.file_mut(inode)?;
+        if offset >= node.data.len() {
+            return Ok(0);
+        }
+        let n = buf.len().min(node.data.len() - offset);
+        buf[..n].copy_from_slice(&node.data[offset..offset + n]);
+        Ok(n)
+    }
+
+    fn write(&self, inode: InodeId, offset: usize, buf: &[u8]) -> Result<usize, FsError> {
+        let mut inner = self.inner.lock();
+        let node = inner.file_mut(inode)?;
+        let end = offset + buf.len();
+        if end > node.data.len() {
+            node.data.resize(end, 0);
+        }
+        node.data[offset..end].copy_from_slice(buf);
+        node.modified = SystemTime::now();
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, inode: InodeId, size: usize) -> Result<(), FsError> {
+        let mut inner = self.inner.lock();
+        let node = inner.file_mut(inode)?;
+        node.data.resize(size, 0);
+        node.data.shrink_to_fit();
+        node.modified = SystemTime::now();
+        Ok(())
+    }
+
+    fn create(&self, dir: InodeId, name: &str, file_type: FileType) -> Result<InodeId, FsError> {
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return Err(FsError::InvalidPath);
+        }
+
+        let mut inner = self.inner.lock();
+        if inner.dir(dir)?.children.contains_key(name) {
+            return Err(FsError::AlreadyExists);
+        }
+
+        let inode = inner.next;
+        inner.next += 1;
+        inner.nodes.insert(inode, Node::new(file_type, dir));
+        inner
+            .node_mut(dir)?
+            .children
+            .insert(String::from(name), inode);
+        inner.touch(dir)?;
+        Ok(inode)
+    }
+
+    fn remove(&self, dir: InodeId, name: &str) -> Result<(), FsError> {
+        let mut inner = self.inner.lock();
+        let inode = *inner
+            .dir(dir)?
+            .children
+            .get(name)
+            .ok_or(FsError::NotFound)?;
+
+        if !inner.node(inode)?.children.is_empty() {
+            return Err(FsError::DirectoryNotEmpty);
+        }
+
+        inner.node_mut(dir)?.children.remove(name);
+        inner.nodes.remove(&inode);
+        inner.touch(dir)
+    }
+
+    fn rename(
+        &self,
+        from_dir: InodeId,
+        from_name: &str,
+        to_dir: InodeId,
+        to_name: &str,
+    ) -> Result<(), FsError> {
+        if to_name.is_empty() || to_name == "." || to_name == ".." || to_name.contains('/') {
+            return Err(FsError::InvalidPath);
+        }
+
+        let mut inner = self.inner.lock();
+        let inode = *inner
+            .dir(from_dir)?
+            .children
+            .get(from_name)
+            .ok_or(FsError::NotFound)?;
+        let file_type = inner.node(inode)?.file_type;
+
+        // a directory cannot be moved inside itself
+        if file_type == FileType::Directory && inner.is_descendant(to_dir, inode) {
+            return Err(FsError::InvalidPath);
+        }
+
+        if let Some(&existing) = inner.dir(to_dir)?.children.get(to_name) {
+            if existing == inode {
+                return Ok(());
+            }
+            let target = inner.node(existing)?;
+            match (file_type, target.file_type) {
+                (FileType::File, FileType::Directory) => return Err(FsError::IsADirectory),
+                (FileType::Directory, FileType::File) => return Err(FsError::NotADirectory),
+                (FileType::Directory, FileType::Directory) if !target.children.is_empty() => {
+                    return Err(FsError::DirectoryNotEmpty)
+                }
+                _ => {}
+            }
+            inner.nodes.remove(&existing);
+        }
+
+        inner.node_mut(from_dir)?.children.remove(from_name);
+        inner
+            .node_mut(to_dir)?
+            .children
+            .insert(String::from(to_name), inode);
+        inner.node_mut(inode)?.parent = to_dir;
+        inner.touch(from_dir)?;
+        inner.touch(to_dir)
+    }
+
+    fn readdir(&self, dir: InodeId) -> Result<Vec<DirEntry>, FsError> {
+        let inner = self.inner.lock();
+        let dir = inner.dir(dir)?;
+        let mut entries = Vec::new();
+        for (name, inode) in dir.children.iter() {
+            entries.push(DirEntry {
+                name: name.clone(),
+                inode: *inode,
+                file_type: inner.node(*inode)?.file_type,
+            });
+        }
+        Ok(entries)
+    }
+}
+
+#[test_case]
+fn ramfs_create_write_rename() {
+    let fs = RamFs::new();
+    let dir = fs.create(fs.root(), "dir", FileType::Directory).unwrap();
+    let file = fs.create(dir, "file", FileType::File).unwrap();
+
+    assert_eq!(fs.write(file, 2, b"hello").unwrap(), 5);
+    let mut buf = [0u8; 8];
+    assert_eq!(fs.read(file, 0, &mut buf).unwrap(), 7);
+    assert_eq!(&buf[..7], b"\0\0hello");
+
+    assert_eq!(fs.remove(fs.root(), "dir"), Err(FsError::DirectoryNotEmpty));
+    assert_eq!(
+        fs.rename(fs.root(), "dir", dir, "inner"),
+        Err(FsError::InvalidPath)
+    );
+
+    fs.rename(dir, "file", fs.root(), "moved").unwrap();
+    assert_eq!(fs.lookup(fs.root(), "moved").unwrap(), file);
+    assert_eq!(fs.lookup(dir, "file"), Err(FsError::NotFound));
+    fs.remove(fs.root(), "dir").unwrap();
+}
@@ -1,93 +1,190 @@
-use super::gdt;
-use crate::println;
+use core::fmt;
+use core::sync::atomic::{AtomicU16, AtomicUsize, Ordering};
 use lazy_static::lazy_static;
 use pic8259::ChainedPics;
-use spin;
+use x86_64::instructions::interrupts;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
 
+use super::apic;
+use crate::println;
+
+/// masks every PIC line except the timer and the cascade, then loads the IDT. the PICs must
+/// have been initialised already.
 pub fn init_idt() {
+    unsafe { PICS.lock().write_masks(!0b101, 0xFF) };
     IDT.load();
+    register_irq(1, keyboard_interrupt).expect("keyboard irq already taken");
 }
 
-extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: breakpoint\n{:#?}", stack_frame);
+/// moves interrupt delivery from the 8259 PICs to the local APIC and IOAPIC described by the
+/// ACPI MADT and routes every irq claimed so far through the IOAPIC to the same vector. without
+/// a MADT the PICs are left as they were.
+pub fn enable_apic() {
+    interrupts::without_interrupts(|| match apic::init() {
+        Ok(legacy_pics) => {
+            if legacy_pics {
+                unsafe { PICS.lock().disable() };
+            }
+            let claimed = ISA_CLAIMED.load(Ordering::Acquire);
+            for irq in (0..16).filter(|irq| claimed & (1 << irq) != 0) {
+                if let Err(e) = apic::route_isa(irq, IRQ_BASE + irq) {
+                    println!("failed to route irq {}: {}", irq, e);
+                }
+            }
+        }
+        Err(e) => println!("APIC unavailable ({}), using the 8259 PIC", e),
+    });
 }
 
-extern "x86-interrupt" fn double_fault_handler(
-    stack_frame: InterruptStackFrame,
-    _error_code: u64,
-) -> ! {
-    panic!("EXCEPTION: double fault\n{:#?}", stack_frame)
+/// acknowledges the interrupt on whichever controller delivered it
+pub fn end_of_interrupt(vector: u8) {
+    if apic::is_enabled() {
+        apic::end_of_interrupt();
+    } else {
+        unsafe { PICS.lock().notify_end_of_interrupt(vector) };
+    }
 }
 
-extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    unsafe {
-        GLOBALTIMER.lock().inc();
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
-    }
+extern "x86-interrupt" fn timer_interrupt_handler(stack_frame: InterruptStackFrame) {
+    super::clock::tick();
+    super::tasks::timer::wake_expired();
+    // may not return until this thread is scheduled again, so it has to be acknowledged first
+    end_of_interrupt(InterruptIndex::Timer.as_u8());
+    super::multitasking::scheduler::tick(stack_frame.code_segment & 3 == 3);
 }
 
-extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    use pc_keyboard::{layouts, HandleControl, Keyboard, ScancodeSet1};
-    use spin::Mutex;
+fn keyboard_interrupt() {
     use x86_64::instructions::port::Port;
 
-    lazy_static! {
-        static ref KEYBOARD: Mutex<Keyboard<layouts::Uk105Key, ScancodeSet1>> = {
-            Mutex::new(Keyboard::new(
-                layouts::Uk105Key,
-                ScancodeSet1,
-                HandleControl::Ignore,
-            ))
-        };
-    }
-
-    let _keyboard = KEYBOARD.lock();
     let mut port = Port::new(0x60);
     let scancode: u8 = unsafe { port.read() };
-
     super::tasks::keyboard::add_scancode(scancode);
+}
 
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
-    }
+extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {}
+
+/// ISA irq n is delivered on vector IRQ_BASE + n, through the PICs or the IOAPIC
+pub const IRQ_BASE: u8 = PIC_1_OFFSET;
+
+/// runs with interrupts disabled, the end of interrupt is sent once it returns
+pub type IrqHandler = fn();
+
+/// the handler of each ISA irq as a fn pointer, 0 if there isn't one
+static HANDLERS: [AtomicUsize; 16] = [const { AtomicUsize::new(0) }; 16];
+/// the ISA irqs that have a handler, the timer is always taken
+static ISA_CLAIMED: AtomicU16 = AtomicU16::new(1);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterruptError {
+    /// irqs only go up to 15
+    InvalidIrq(u8),
+    InUse(u8),
+    /// the IOAPICs don't handle the line the irq is wired to
+    NoRoute(u8),
 }
 
-lazy_static! {
-    static ref IDT: InterruptDescriptorTable = {
-        let mut idt = InterruptDescriptorTable::new();
-        idt.breakpoint.set_handler_fn(breakpoint_handler);
-        unsafe {
-            idt.double_fault
-                .set_handler_fn(double_fault_handler)
-                .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+impl fmt::Display for InterruptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InterruptError::InvalidIrq(irq) => write!(f, "{} is not an ISA irq", irq),
+            InterruptError::InUse(irq) => write!(f, "irq {} already has a handler", irq),
+            InterruptError::NoRoute(irq) => write!(f, "irq {} can't be routed", irq),
         }
-        idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
-        idt[InterruptIndex::Keyboard.as_usize()].set_handler_fn(keyboard_interrupt_handler);
-        idt
-    };
+    }
 }
 
-lazy_static! {
-    pub static ref GLOBALTIMER: spin::Mutex<Timer> = spin::Mutex::new(Timer::new());
+fn claim(vector: u8, handler: IrqHandler) -> bool {
+    HANDLERS[(vector - IRQ_BASE) as usize]
+        .compare_exchange(0, handler as usize, Ordering::AcqRel, Ordering::Acquire)
+        .is_ok()
 }
 
-pub struct Timer {
-    pub val: i64,
+/// installs `handler` for ISA irq `irq` (e.g. 4 for COM1 or 12 for the PS/2 mouse) and unmasks
+/// it, returns the vector it arrives on
+pub fn register_irq(irq: u8, handler: IrqHandler) -> Result<u8, InterruptError> {
+    if irq >= 16 {
+        return Err(InterruptError::InvalidIrq(irq));
+    }
+    let vector = IRQ_BASE + irq;
+    if ISA_CLAIMED.load(Ordering::Acquire) & (1 << irq) != 0 || !claim(vector, handler) {
+        return Err(InterruptError::InUse(irq));
+    }
+    ISA_CLAIMED.fetch_or(1 << irq, Ordering::AcqRel);
+
+    interrupts::without_interrupts(|| {
+        if apic::is_enabled() {
+            apic::route_isa(irq, vector)
+        } else {
+            let mut pics = PICS.lock();
+            let [mut master, mut slave] = unsafe { pics.read_masks() };
+            match irq {
+                0..=7 => master &= !(1 << irq),
+                _ => slave &= !(1 << (irq - 8)),
+            }
+            unsafe { pics.write_masks(master, slave) };
+            Ok(())
+        }
+    })
+    .map_err(|_| {
+        unregister(vector);
+        InterruptError::NoRoute(irq)
+    })?;
+    Ok(vector)
 }
 
-impl Timer {
-    pub fn new() -> Self {
-        Self { val: 0 }
+/// masks the irq behind `vector` and removes its handler
+pub fn unregister(vector: u8) {
+    if vector <= IRQ_BASE || vector >= IRQ_BASE + 16 {
+        return;
     }
-    pub fn inc(&mut self) {
-        self.val += 1
-    }
-    pub fn clear(&mut self) {
-        self.val = 0
+    let irq = vector - IRQ_BASE;
+    interrupts::without_interrupts(|| {
+        if apic::is_enabled() {
+            apic::mask_isa(irq).ok();
+        } else {
+            let mut pics = PICS.lock();
+            let [mut master, mut slave] = unsafe { pics.read_masks() };
+            match irq {
+                0..=7 => master |= 1 << irq,
+                _ => slave |= 1 << (irq - 8),
+            }
+            unsafe { pics.write_masks(master, slave) };
+        }
+    });
+    ISA_CLAIMED.fetch_and(!(1 << irq), Ordering::AcqRel);
+    HANDLERS[irq as usize].store(0, Ordering::Release);
+}
+
+fn dispatch(vector: u8) {
+    let handler = HANDLERS[(vector - IRQ_BASE) as usize].load(Ordering::Acquire);
+    if handler != 0 {
+        let handler: IrqHandler = unsafe { core::mem::transmute(handler) };
+        handler();
     }
+    end_of_interrupt(vector);
+}
+
+extern "x86-interrupt" fn irq_stub<const VECTOR: u8>(_stack_frame: InterruptStackFrame) {
+    dispatch(VECTOR);
+}
+
+/// points each vector at the stub that dispatches to whatever handler gets registered for it
+macro_rules! irq_stubs {
+    ($idt:ident, $($vector:literal)*) => {
+        $( $idt[$vector].set_handler_fn(irq_stub::<$vector>); )*
+    };
+}
+
+lazy_static! {
+    static ref IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        super::exceptions::register(&mut idt);
+        idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
+        irq_stubs!(idt, 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47);
+        idt[apic::SPURIOUS_VECTOR as usize].set_handler_fn(spurious_interrupt_handler);
+        super::syscall::register(&mut idt);
+        idt
+    };
 }
 
 pub const PIC_1_OFFSET: u8 = 32;
@@ -100,7 +197,6 @@ pub static PICS: spin::Mutex<ChainedPics> =
 #[repr(u8)]
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
-    Keyboard,
 }
 
 impl InterruptIndex {
@@ -112,3 +208,23 @@ impl InterruptIndex {
         usize::from(self.as_u8())
     }
 }
+
+#[test_case]
+fn vectors_can_be_claimed_and_released() {
+    fn nothing() {}
+
+    assert_eq!(register_irq(0, nothing), Err(InterruptError::InUse(0)));
+    assert_eq!(register_irq(1, nothing), Err(InterruptError::InUse(1)));
+    assert_eq!(
+        register_irq(16, nothing),
+        Err(InterruptError::InvalidIrq(16))
+    );
+
+    // nothing in QEMU is wired to irq 5
+    let vector = register_irq(5, nothing).unwrap();
+    assert_eq!(vector, IRQ_BASE + 5);
+    assert_eq!(register_irq(5, nothing), Err(InterruptError::InUse(5)));
+    unregister(vector);
+    assert_eq!(register_irq(5, nothing), Ok(vector));
+    unregister(vector);
+}
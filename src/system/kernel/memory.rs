@@ -1,10 +1,148 @@
 use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
-use x86_64::structures::paging::{mapper, OffsetPageTable};
+use conquer_once::spin::OnceCell;
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::{mapper, OffsetPageTable, PageTableFlags};
 use x86_64::{
-    structures::paging::{FrameAllocator, Mapper, Page, PageTable, PhysFrame, Size4KiB},
+    structures::paging::{
+        page_table::PageTableEntry, FrameAllocator, FrameDeallocator, Mapper, Page, PageTable,
+        PhysFrame, Size4KiB,
+    },
     PhysAddr, VirtAddr,
 };
 
+static PHYSICAL_MEMORY_OFFSET: OnceCell<VirtAddr> = OnceCell::uninit();
+
+// both are only ever locked through `with_memory`
+lazy_static! {
+    static ref MAPPER: Mutex<Option<OffsetPageTable<'static>>> = Mutex::new(None);
+    static ref FRAME_ALLOCATOR: Mutex<Option<BitmapFrameAllocator>> = Mutex::new(None);
+}
+
+/// hands the active page table and the frame allocator over to the kernel once the heap exists,
+/// so that drivers can allocate and map memory after boot.
+pub fn install(
+    physical_memory_offset: VirtAddr,
+    mapper: OffsetPageTable<'static>,
+    frame_allocator: BitmapFrameAllocator,
+) {
+    PHYSICAL_MEMORY_OFFSET
+        .try_init_once(|| physical_memory_offset)
+        .expect("memory::install has already been called once");
+    interrupts::without_interrupts(|| {
+        *MAPPER.lock() = Some(mapper);
+        *FRAME_ALLOCATOR.lock() = Some(frame_allocator);
+    });
+}
+
+/// runs `f` with the kernel's page table and frame allocator, None if they are not installed
+/// yet. the heap grows through them while it holds its own lock with interrupts disabled, so
+/// they are locked with interrupts disabled too and can never be held by a preempted thread.
+fn with_memory<R>(
+    f: impl FnOnce(&mut OffsetPageTable<'static>, &mut BitmapFrameAllocator) -> R,
+) -> Option<R> {
+    interrupts::without_interrupts(|| {
+        let mut mapper = MAPPER.lock();
+        let mut frame_allocator = FRAME_ALLOCATOR.lock();
+        match (mapper.as_mut(), frame_allocator.as_mut()) {
+            (Some(mapper), Some(frame_allocator)) => Some(f(mapper, frame_allocator)),
+            _ => None,
+        }
+    })
+}
+
+/// every physical address is mapped by the bootloader at a fixed offset in virtual memory
+pub fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
+    let offset = PHYSICAL_MEMORY_OFFSET
+        .try_get()
+        .expect("memory has not been initialised");
+    *offset + addr.as_u64()
+}
+
+/// allocates a single physical frame and fills it with zeros, for use as DMA memory
+pub fn alloc_zeroed_frame() -> Option<PhysFrame> {
+    alloc_zeroed_frames(1)
+}
+
+/// allocates `count` physically contiguous frames filled with zeros and returns the first one,
+/// for DMA buffers that a device reads in one go
+pub fn alloc_zeroed_frames(count: usize) -> Option<PhysFrame> {
+    let frame = with_memory(|_, frame_allocator| frame_allocator.allocate_contiguous(count))??;
+    unsafe {
+        core::ptr::write_bytes(
+            phys_to_virt(frame.start_address()).as_mut_ptr::<u8>(),
+            0,
+            count * Page::<Size4KiB>::SIZE as usize,
+        );
+    }
+    Some(frame)
+}
+
+/// gives back `count` frames starting at `start`. nothing may use them afterwards.
+pub unsafe fn free_frames(start: PhysFrame, count: usize) {
+    with_memory(|_, frame_allocator| frame_allocator.deallocate_contiguous(start, count));
+}
+
+/// how much physical memory is in use, None before the allocator has been installed
+pub fn frame_stats() -> Option<FrameStats> {
+    with_memory(|_, frame_allocator| frame_allocator.stats())
+}
+
+/// backs `count` pages from `start` onwards with fresh frames in the kernel's page table, for
+/// the heap to grow into
+pub fn map_kernel_pages(start: Page, count: u64) -> Result<(), mapper::MapToError<Size4KiB>> {
+    use x86_64::structures::paging::PageTableFlags as Flags;
+
+    with_memory(|mapper, frame_allocator| {
+        for page in Page::range(start, start + count) {
+            let frame = frame_allocator
+                .allocate_frame()
+                .ok_or(mapper::MapToError::FrameAllocationFailed)?;
+            let flags = Flags::PRESENT | Flags::WRITABLE;
+            unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+        }
+        Ok(())
+    })
+    .unwrap_or(Err(mapper::MapToError::FrameAllocationFailed))
+}
+
+fn reserve_mmio_memory(size_in_pages: u64) -> Page {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    static MMIO_ALLOC_NEXT: AtomicU64 = AtomicU64::new(0x_6666_6666_0000);
+    let start_addr = VirtAddr::new(
+        MMIO_ALLOC_NEXT.fetch_add(size_in_pages * Page::<Size4KiB>::SIZE, Ordering::Relaxed),
+    );
+    Page::from_start_address(start_addr).expect("MMIO_ALLOC_NEXT: not page aligned")
+}
+
+/// maps a range of device registers into virtual memory with caching disabled
+pub fn map_mmio(phys: PhysAddr, size: usize) -> Result<VirtAddr, mapper::MapToError<Size4KiB>> {
+    use x86_64::structures::paging::PageTableFlags as Flags;
+
+    let first = PhysFrame::<Size4KiB>::containing_address(phys);
+    let last = PhysFrame::<Size4KiB>::containing_address(phys + (size as u64).max(1) - 1u64);
+    let pages = last.start_address().as_u64() / Page::<Size4KiB>::SIZE
+        - first.start_address().as_u64() / Page::<Size4KiB>::SIZE
+        + 1;
+
+    let start = reserve_mmio_memory(pages);
+    let flags = Flags::PRESENT | Flags::WRITABLE | Flags::NO_CACHE | Flags::WRITE_THROUGH;
+
+    with_memory(|mapper, frame_allocator| {
+        for (page, frame) in
+            Page::range(start, start + pages).zip(PhysFrame::range(first, last + 1))
+        {
+            unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+        }
+        Ok(())
+    })
+    .unwrap_or(Err(mapper::MapToError::FrameAllocationFailed))?;
+
+    Ok(start.start_address() + (phys.as_u64() - first.start_address().as_u64()))
+}
+
 unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
     use x86_64::registers::control::Cr3;
 
@@ -21,44 +159,181 @@ pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static>
     OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
 
-pub struct BootInfoFrameAllocator {
-    memory_map: &'static MemoryMap,
+const FRAME_SIZE: u64 = Page::<Size4KiB>::SIZE;
+
+/// keeps one bit per physical frame, set while the frame is in use. frames the bootloader did
+/// not mark as usable start out set and are never handed out. the bitmap itself lives at the
+/// start of the first usable region that is big enough for it.
+pub struct BitmapFrameAllocator {
+    bitmap: &'static mut [u64],
+    usable: usize,
+    free: usize,
+    /// every word before this one was full the last time it was checked
     next: usize,
 }
 
-impl BootInfoFrameAllocator {
-    pub unsafe fn init(memory_map: &'static MemoryMap) -> Self {
-        BootInfoFrameAllocator {
-            memory_map,
+/// physical memory usage, counted in 4 KiB frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    pub total: usize,
+    pub free: usize,
+}
+
+impl FrameStats {
+    pub fn used(&self) -> usize {
+        self.total - self.free
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.total as u64 * FRAME_SIZE
+    }
+
+    pub fn free_bytes(&self) -> u64 {
+        self.free as u64 * FRAME_SIZE
+    }
+
+    pub fn used_bytes(&self) -> u64 {
+        self.used() as u64 * FRAME_SIZE
+    }
+}
+
+impl BitmapFrameAllocator {
+    /// every usable region in `memory_map` must really be unused, and all of physical memory must
+    /// be mapped at `physical_memory_offset`
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: VirtAddr) -> Self {
+        let usable = || {
+            memory_map
+                .iter()
+                .filter(|r| r.region_type == MemoryRegionType::Usable)
+        };
+
+        let frames = usable()
+            .map(|r| r.range.end_frame_number)
+            .max()
+            .unwrap_or(0);
+        let words = ((frames + 63) / 64) as usize;
+        let bitmap_frames = (words as u64 * 8 + FRAME_SIZE - 1) / FRAME_SIZE;
+        let home = usable()
+            .find(|r| r.range.end_frame_number - r.range.start_frame_number >= bitmap_frames)
+            .expect("no usable region is big enough for the frame bitmap")
+            .range
+            .start_frame_number;
+
+        let bitmap = core::slice::from_raw_parts_mut(
+            (physical_memory_offset + home * FRAME_SIZE).as_mut_ptr::<u64>(),
+            words,
+        );
+        bitmap.fill(u64::MAX);
+
+        let mut allocator = BitmapFrameAllocator {
+            bitmap,
+            usable: 0,
+            free: 0,
             next: 0,
+        };
+        for region in usable() {
+            for frame in region.range.start_frame_number..region.range.end_frame_number {
+                allocator.mark(frame as usize, false);
+                allocator.usable += 1;
+            }
         }
+        for frame in home..home + bitmap_frames {
+            allocator.mark(frame as usize, true);
+        }
+        allocator.free = allocator.usable - bitmap_frames as usize;
+        allocator
     }
 
-    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
-        let regions = self.memory_map.iter();
-        let usable_regions = regions.filter(|r| r.region_type == MemoryRegionType::Usable);
+    fn is_used(&self, frame: usize) -> bool {
+        self.bitmap[frame / 64] & (1 << (frame % 64)) != 0
+    }
 
-        let address_ranges = usable_regions.map(|r| r.range.start_addr()..r.range.end_addr());
+    fn mark(&mut self, frame: usize, used: bool) {
+        if used {
+            self.bitmap[frame / 64] |= 1 << (frame % 64);
+        } else {
+            self.bitmap[frame / 64] &= !(1 << (frame % 64));
+        }
+    }
 
-        let frame_addresses = address_ranges.flat_map(|r| r.step_by(4096));
+    fn frame_at(index: usize) -> PhysFrame {
+        PhysFrame::containing_address(PhysAddr::new(index as u64 * FRAME_SIZE))
+    }
 
-        frame_addresses.map(|a| PhysFrame::containing_address(PhysAddr::new(a)))
+    /// finds `count` free frames in a row, first fit from the lowest address
+    pub fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrame> {
+        if count == 0 || count > self.free {
+            return None;
+        }
+        let mut run = 0;
+        for frame in self.next * 64..self.bitmap.len() * 64 {
+            if self.is_used(frame) {
+                run = 0;
+                continue;
+            }
+            run += 1;
+            if run == count {
+                let start = frame + 1 - count;
+                for f in start..=frame {
+                    self.mark(f, true);
+                }
+                self.free -= count;
+                return Some(Self::frame_at(start));
+            }
+        }
+        None
     }
-}
 
-pub struct EmptyFrameAllocator;
+    /// frees `count` frames starting at `start`, they must have come from this allocator
+    pub unsafe fn deallocate_contiguous(&mut self, start: PhysFrame, count: usize) {
+        for frame in PhysFrame::range(start, start + count as u64) {
+            self.deallocate_frame(frame);
+        }
+    }
 
-unsafe impl FrameAllocator<Size4KiB> for EmptyFrameAllocator {
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            total: self.usable,
+            free: self.free,
+        }
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        while self.next < self.bitmap.len() {
+            let word = self.bitmap[self.next];
+            if word != u64::MAX {
+                let frame = self.next * 64 + word.trailing_ones() as usize;
+                self.mark(frame, true);
+                self.free -= 1;
+                return Some(Self::frame_at(frame));
+            }
+            self.next += 1;
+        }
         None
     }
 }
 
-unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        let index = (frame.start_address().as_u64() / FRAME_SIZE) as usize;
+        assert!(
+            index < self.bitmap.len() * 64 && self.is_used(index),
+            "freed {:?} which is not allocated",
+            frame
+        );
+        self.mark(index, false);
+        self.free += 1;
+        self.next = self.next.min(index / 64);
+    }
+}
+
+pub struct EmptyFrameAllocator;
+
+unsafe impl FrameAllocator<Size4KiB> for EmptyFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        let frame = self.usable_frames().nth(self.next);
-        self.next += 1;
-        frame
+        None
     }
 }
 
@@ -86,11 +361,15 @@ impl ThreadId {
         self.0
     }
 
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         use core::sync::atomic::{AtomicU64, Ordering};
         static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
         ThreadId(NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed))
     }
+
+    pub(crate) fn from_u64(id: u64) -> Self {
+        ThreadId(id)
+    }
 }
 
 fn reserve_stack_memory(size_in_pages: u64) -> Page {
@@ -128,3 +407,245 @@ pub fn alloc_stack(
         end: stack_end.start_address(),
     })
 }
+
+/// allocates a stack with the kernel's own page table and frame allocator
+pub fn alloc_kernel_stack(size_in_pages: u64) -> Result<StackBounds, mapper::MapToError<Size4KiB>> {
+    with_memory(|mapper, frame_allocator| alloc_stack(size_in_pages, mapper, frame_allocator))
+        .unwrap_or(Err(mapper::MapToError::FrameAllocationFailed))
+}
+
+/// unmaps a stack from `alloc_kernel_stack` and gives its frames back, nothing may be running
+/// on it anymore
+pub unsafe fn free_kernel_stack(stack: StackBounds) {
+    let start = Page::<Size4KiB>::containing_address(stack.start);
+    let end = Page::<Size4KiB>::containing_address(stack.end);
+    with_memory(|mapper, frame_allocator| {
+        for page in Page::range(start, end) {
+            if let Ok((frame, flush)) = mapper.unmap(page) {
+                flush.flush();
+                frame_allocator.deallocate_frame(frame);
+            }
+        }
+    });
+}
+
+/// the part of the address space that belongs to user programs. everything outside it is shared
+/// by every address space, the kernel itself lives in the lower half next to it.
+pub const USER_START: u64 = 0x_1000_0000_0000;
+pub const USER_END: u64 = 0x_2000_0000_0000;
+
+/// true if every byte of the range is mapped into user space, and writable too if `writable`
+/// is set. used to check pointers handed to syscalls by ring 3 code
+pub fn is_user_accessible(start: VirtAddr, len: u64, writable: bool) -> bool {
+    // the pointer belongs to whichever address space is loaded right now
+    let offset = match PHYSICAL_MEMORY_OFFSET.try_get() {
+        Ok(offset) => *offset,
+        Err(_) => return false,
+    };
+    let mapper = unsafe { OffsetPageTable::new(active_level_4_table(offset), offset) };
+    user_accessible_in(&mapper, start, len, writable)
+}
+
+fn user_accessible_in(mapper: &OffsetPageTable, start: VirtAddr, len: u64, writable: bool) -> bool {
+    use x86_64::structures::paging::{mapper::TranslateResult, PageTableFlags as Flags, Translate};
+
+    if len == 0 {
+        return true;
+    }
+    let end = match start.as_u64().checked_add(len - 1) {
+        Some(end) if end < 0x_8000_0000_0000 => VirtAddr::new(end),
+        _ => return false,
+    };
+
+    let needed = match writable {
+        true => Flags::USER_ACCESSIBLE | Flags::WRITABLE,
+        false => Flags::USER_ACCESSIBLE,
+    };
+    let first = Page::<Size4KiB>::containing_address(start);
+    let last = Page::<Size4KiB>::containing_address(end);
+    Page::range_inclusive(first, last).all(|page| {
+        matches!(
+            mapper.translate(page.start_address()),
+            TranslateResult::Mapped { flags, .. } if flags.contains(needed)
+        )
+    })
+}
+
+/// a level 4 page table of its own. the user range starts out empty and every other entry is
+/// copied from the kernel's table, so kernel code keeps working whichever table is loaded.
+#[derive(Debug)]
+pub struct AddressSpace {
+    level_4_frame: PhysFrame,
+}
+
+impl AddressSpace {
+    pub fn new() -> Option<AddressSpace> {
+        let level_4_frame = alloc_zeroed_frame()?;
+        let table =
+            unsafe { &mut *phys_to_virt(level_4_frame.start_address()).as_mut_ptr::<PageTable>() };
+
+        let space = AddressSpace { level_4_frame };
+        with_memory(|mapper, _| {
+            let user = Self::user_entries();
+            for (i, entry) in mapper.level_4_table().iter().enumerate() {
+                if !user.contains(&i) {
+                    table[i] = entry.clone();
+                }
+            }
+        })?;
+        Some(space)
+    }
+
+    /// the level 4 entries covering USER_START..USER_END
+    fn user_entries() -> core::ops::Range<usize> {
+        let index = |addr| {
+            usize::from(Page::<Size4KiB>::containing_address(VirtAddr::new(addr)).p4_index())
+        };
+        index(USER_START)..index(USER_END)
+    }
+
+    /// the frame to load into CR3 to switch to this address space
+    pub fn level_4_frame(&self) -> PhysFrame {
+        self.level_4_frame
+    }
+
+    fn mapper(&mut self) -> OffsetPageTable<'_> {
+        let offset = *PHYSICAL_MEMORY_OFFSET
+            .try_get()
+            .expect("memory has not been initialised");
+        let table = unsafe {
+            &mut *phys_to_virt(self.level_4_frame.start_address()).as_mut_ptr::<PageTable>()
+        };
+        unsafe { OffsetPageTable::new(table, offset) }
+    }
+
+    /// backs a page in the user range with a zeroed frame. if the page is already mapped the
+    /// permissions are merged, so two segments sharing a page both get what they asked for.
+    pub fn map_user(
+        &mut self,
+        page: Page,
+        flags: PageTableFlags,
+    ) -> Result<(), mapper::MapToError<Size4KiB>> {
+        use x86_64::structures::paging::mapper::TranslateResult;
+        use x86_64::structures::paging::Translate;
+
+        debug_assert!((USER_START..USER_END).contains(&page.start_address().as_u64()));
+        let flags = flags | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+
+        let mut mapper = self.mapper();
+        if let TranslateResult::Mapped {
+            flags: existing, ..
+        } = mapper.translate(page.start_address())
+        {
+            let mut merged = existing | flags;
+            if !existing.contains(PageTableFlags::NO_EXECUTE)
+                || !flags.contains(PageTableFlags::NO_EXECUTE)
+            {
+                merged.remove(PageTableFlags::NO_EXECUTE);
+            }
+            unsafe {
+                mapper
+                    .update_flags(page, merged)
+                    .map_err(|_| mapper::MapToError::FrameAllocationFailed)?
+                    .ignore()
+            };
+            return Ok(());
+        }
+
+        let frame = alloc_zeroed_frame().ok_or(mapper::MapToError::FrameAllocationFailed)?;
+        // the table is not loaded yet, so there is nothing to flush
+        with_memory(|_, frame_allocator| unsafe {
+            mapper
+                .map_to(page, frame, flags, frame_allocator)
+                .map(|flush| flush.ignore())
+        })
+        .unwrap_or(Err(mapper::MapToError::FrameAllocationFailed))
+    }
+
+    /// copies bytes into memory that has already been mapped with `map_user`, through the
+    /// physical memory mapping so read only pages can be filled in as well
+    pub fn write(&mut self, addr: VirtAddr, bytes: &[u8]) -> Result<(), mapper::TranslateError> {
+        use x86_64::structures::paging::mapper::Translate;
+
+        let mapper = self.mapper();
+        let mut written = 0;
+        while written < bytes.len() {
+            let virt = addr + written;
+            let phys = mapper
+                .translate_addr(virt)
+                .ok_or(mapper::TranslateError::PageNotMapped)?;
+            let in_page = (Page::<Size4KiB>::SIZE - u64::from(virt.page_offset())) as usize;
+            let n = in_page.min(bytes.len() - written);
+            unsafe {
+                core::ptr::copy_nonoverlapping(
+                    bytes[written..].as_ptr(),
+                    phys_to_virt(phys).as_mut_ptr::<u8>(),
+                    n,
+                );
+            }
+            written += n;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AddressSpace {
+    /// frees every frame mapped in the user range, the tables that mapped them and the level 4
+    /// table itself. the address space must not be loaded in CR3 anymore.
+    fn drop(&mut self) {
+        let table =
+            unsafe { &*phys_to_virt(self.level_4_frame.start_address()).as_ptr::<PageTable>() };
+        with_memory(|_, frame_allocator| {
+            for i in Self::user_entries() {
+                free_table(&table[i], 3, frame_allocator);
+            }
+            unsafe { frame_allocator.deallocate_frame(self.level_4_frame) };
+        });
+    }
+}
+
+/// frees whatever `entry` points to. a table at `level` 1 maps data frames, higher levels map
+/// further tables which are freed first.
+fn free_table(entry: &PageTableEntry, level: u8, frame_allocator: &mut BitmapFrameAllocator) {
+    let frame = match entry.frame() {
+        Ok(frame) => frame,
+        Err(_) => return,
+    };
+    if level > 0 {
+        let table = unsafe { &*phys_to_virt(frame.start_address()).as_ptr::<PageTable>() };
+        for entry in table.iter() {
+            free_table(entry, level - 1, frame_allocator);
+        }
+    }
+    unsafe { frame_allocator.deallocate_frame(frame) };
+}
+
+#[test_case]
+fn freed_frames_are_reused() {
+    let before = frame_stats().unwrap();
+    let frame = alloc_zeroed_frames(4).unwrap();
+    assert_eq!(frame_stats().unwrap().free, before.free - 4);
+
+    unsafe { free_frames(frame, 4) };
+    assert_eq!(frame_stats().unwrap(), before);
+    assert_eq!(alloc_zeroed_frames(4), Some(frame));
+    unsafe { free_frames(frame, 4) };
+}
+
+#[test_case]
+fn read_only_user_pages_are_not_writable() {
+    let mut space = AddressSpace::new().unwrap();
+    let page = Page::<Size4KiB>::containing_address(VirtAddr::new(USER_START));
+    space.map_user(page, PageTableFlags::empty()).unwrap();
+    space.map_user(page + 1, PageTableFlags::WRITABLE).unwrap();
+
+    let mapper = space.mapper();
+    let start = page.start_address();
+    assert!(user_accessible_in(&mapper, start, 8, false));
+    assert!(!user_accessible_in(&mapper, start, 8, true));
+    assert!(user_accessible_in(&mapper, start + 4096u64, 8, true));
+    // the first byte is on the read only page
+    assert!(!user_accessible_in(&mapper, start + 4095u64, 8, true));
+    // nothing is mapped after the second page
+    assert!(!user_accessible_in(&mapper, start + 8190u64, 8, false));
+}
@@ -1,10 +1,24 @@
+pub mod acpi;
+pub mod ahci;
 pub mod allocator;
+pub mod apic;
 pub mod authenticator;
+pub mod block;
+pub mod clock;
+pub mod elf;
+pub mod exceptions;
+pub mod fs;
 pub mod gdt;
 pub mod interrupts;
 pub mod memory;
 pub mod multitasking;
+pub mod pci;
+pub mod power;
+pub mod process;
 pub mod render;
+pub mod rtc;
 pub mod serial;
+pub mod syscall;
 pub mod sysinit;
 pub mod tasks;
+pub mod usermode;
@@ -1,2 +1,9 @@
+pub mod scheduler;
 pub mod thread;
 mod thread_switch;
+
+pub use scheduler::{
+    current, current_process, exit, exit_if_killed, join, kill, poll_join, sleep_until, spawn,
+    spawn_in, threads, try_join, yield_now, ThreadError, ThreadInfo,
+};
+pub use thread::ThreadState;
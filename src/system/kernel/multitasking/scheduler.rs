@@ -0,0 +1,482 @@
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    string::String,
+    vec::Vec,
+};
+use core::fmt;
+use core::task::Waker;
+use spin::Mutex;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::PhysFrame;
+use x86_64::{instructions::interrupts, VirtAddr};
+
+use super::thread::{Thread, ThreadState};
+use super::thread_switch::context_switch_to;
+use crate::system::kernel::clock;
+use crate::system::kernel::gdt;
+use crate::system::kernel::memory::{self, ThreadId};
+use crate::system::kernel::process::Pid;
+
+/// 64 KiB of stack for every kernel thread
+const STACK_PAGES: u64 = 16;
+
+// every access to the scheduler happens with interrupts disabled, so the timer interrupt can
+// never find it locked by the thread it preempted.
+static SCHEDULER: Mutex<Option<Scheduler>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum SwitchReason {
+    /// preempted by the timer
+    Paused,
+    Yield,
+    /// sleeping or waiting on another thread, it is woken up elsewhere
+    Blocked,
+    Exit,
+}
+
+impl SwitchReason {
+    pub(super) fn from_u64(reason: u64) -> SwitchReason {
+        match reason {
+            0 => SwitchReason::Paused,
+            1 => SwitchReason::Yield,
+            2 => SwitchReason::Blocked,
+            _ => SwitchReason::Exit,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThreadError {
+    NotInitialised,
+    OutOfMemory,
+    NotFound,
+    /// a thread tried to join itself
+    Deadlock,
+    /// the thread was killed while it was waiting
+    Killed,
+}
+
+impl fmt::Display for ThreadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ThreadError::NotInitialised => write!(f, "the scheduler is not running"),
+            ThreadError::OutOfMemory => write!(f, "not enough memory for a thread stack"),
+            ThreadError::NotFound => write!(f, "no such thread"),
+            ThreadError::Deadlock => write!(f, "a thread cannot join itself"),
+            ThreadError::Killed => write!(f, "the thread was killed"),
+        }
+    }
+}
+
+/// a snapshot of a thread for listing
+#[derive(Debug, Clone)]
+pub struct ThreadInfo {
+    pub id: u64,
+    pub name: String,
+    /// the process the thread belongs to, 0 for the kernel
+    pub pid: u64,
+    pub state: ThreadState,
+    /// start and end of the thread's stack, None for the boot stack
+    pub stack: Option<(u64, u64)>,
+}
+
+struct Scheduler {
+    threads: BTreeMap<ThreadId, Thread>,
+    ready: VecDeque<ThreadId>,
+    current: ThreadId,
+    /// runs when nothing else can, never sits in the ready queue
+    idle: ThreadId,
+    /// loaded for every thread that does not have an address space of its own
+    kernel_page_table: PhysFrame,
+}
+
+impl Scheduler {
+    fn current_mut(&mut self) -> &mut Thread {
+        self.threads
+            .get_mut(&self.current)
+            .expect("the current thread is not registered")
+    }
+
+    fn make_ready(&mut self, id: ThreadId) {
+        if let Some(thread) = self.threads.get_mut(&id) {
+            thread.state = ThreadState::Ready;
+            self.ready.push_back(id);
+        }
+    }
+
+    fn wake_sleepers(&mut self, now: u64) {
+        let woken = self
+            .threads
+            .values()
+            .filter(|t| matches!(t.state, ThreadState::Sleeping(wake) if wake <= now))
+            .map(|t| t.id())
+            .collect::<Vec<ThreadId>>();
+        for id in woken {
+            self.make_ready(id);
+        }
+    }
+
+    /// picks the thread to switch to, or None if the current thread should keep running
+    fn next(&mut self, reason: SwitchReason) -> Option<(ThreadId, VirtAddr)> {
+        let next = match self.ready.pop_front() {
+            Some(id) => id,
+            None => match reason {
+                SwitchReason::Paused | SwitchReason::Yield => return None,
+                SwitchReason::Blocked | SwitchReason::Exit => self.idle,
+            },
+        };
+        if next == self.current {
+            self.current_mut().state = ThreadState::Running;
+            return None;
+        }
+
+        let prev = self.current;
+        let kernel_page_table = self.kernel_page_table;
+        self.current = next;
+        let thread = self.current_mut();
+        thread.state = ThreadState::Running;
+        if let Some(stack) = thread.stack_bounds() {
+            gdt::set_kernel_stack(stack.end());
+        }
+        let page_table = thread.page_table.unwrap_or(kernel_page_table);
+        let (active, flags) = Cr3::read();
+        if active != page_table {
+            // safe because the kernel half, including this stack, is mapped in every table
+            unsafe { Cr3::write(page_table, flags) };
+        }
+        let stack_ptr = thread
+            .stack_ptr
+            .take()
+            .expect("switching to a thread that was never paused");
+        Some((prev, stack_ptr))
+    }
+}
+
+/// turns the code that is currently running into the first thread and starts an idle thread.
+/// preemption begins with the next timer tick.
+pub fn init() {
+    let kernel = Thread::bootstrap("kernel");
+    let idle = new_thread("idle", || loop {
+        interrupts::enable_and_hlt();
+    })
+    .expect("failed to create the idle thread");
+
+    let mut threads = BTreeMap::new();
+    let current = kernel.id();
+    let idle_id = idle.id();
+    threads.insert(current, kernel);
+    threads.insert(idle_id, idle);
+
+    interrupts::without_interrupts(|| {
+        *SCHEDULER.lock() = Some(Scheduler {
+            threads,
+            ready: VecDeque::new(),
+            current,
+            idle: idle_id,
+            kernel_page_table: Cr3::read().0,
+        });
+    });
+}
+
+fn new_thread<F>(name: &str, f: F) -> Result<Thread, ThreadError>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let stack = memory::alloc_kernel_stack(STACK_PAGES).map_err(|_| ThreadError::OutOfMemory)?;
+    Ok(Thread::new(name, stack, Box::new(Box::new(f))))
+}
+
+/// starts running `f` on a new kernel thread
+pub fn spawn<F>(name: &str, f: F) -> Result<ThreadId, ThreadError>
+where
+    F: FnOnce() + Send + 'static,
+{
+    add_thread(new_thread(name, f)?)
+}
+
+/// like `spawn`, but the thread belongs to `process` and runs with its `page_table` loaded
+/// instead of the kernel's
+pub fn spawn_in<F>(
+    name: &str,
+    process: Pid,
+    page_table: PhysFrame,
+    f: F,
+) -> Result<ThreadId, ThreadError>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let mut thread = new_thread(name, f)?;
+    thread.page_table = Some(page_table);
+    thread.process = process;
+    add_thread(thread)
+}
+
+fn add_thread(thread: Thread) -> Result<ThreadId, ThreadError> {
+    let id = thread.id();
+    interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let scheduler = scheduler.as_mut().ok_or(ThreadError::NotInitialised)?;
+        scheduler.threads.insert(id, thread);
+        scheduler.ready.push_back(id);
+        Ok(id)
+    })
+}
+
+/// switches away from the current thread, interrupts must already be disabled
+fn schedule(reason: SwitchReason) {
+    let next = SCHEDULER.lock().as_mut().and_then(|s| s.next(reason));
+    if let Some((prev, stack_ptr)) = next {
+        unsafe { context_switch_to(stack_ptr, prev, reason) };
+    }
+}
+
+/// called by the timer interrupt after the end of interrupt has been sent, `from_user` is
+/// whether it interrupted ring 3
+pub fn tick(from_user: bool) {
+    let now = clock::ticks();
+    let (killed, next) = match SCHEDULER.lock().as_mut() {
+        Some(scheduler) => {
+            scheduler.wake_sleepers(now);
+            // user code can't be holding any kernel locks, so a killed thread can end right here
+            match from_user && scheduler.current_mut().killed {
+                true => (true, None),
+                false => (false, scheduler.next(SwitchReason::Paused)),
+            }
+        }
+        None => return,
+    };
+    if killed {
+        exit();
+    }
+    if let Some((prev, stack_ptr)) = next {
+        unsafe { context_switch_to(stack_ptr, prev, SwitchReason::Paused) };
+    }
+}
+
+/// runs on the new thread's stack once the old one has been saved
+pub(super) fn add_paused_thread(stack_ptr: VirtAddr, id: ThreadId, reason: SwitchReason) {
+    let mut scheduler = SCHEDULER.lock();
+    let scheduler = scheduler
+        .as_mut()
+        .expect("switched threads without a scheduler");
+    let idle = scheduler.idle;
+    if let Some(thread) = scheduler.threads.get_mut(&id) {
+        thread.stack_ptr = Some(stack_ptr);
+        match reason {
+            SwitchReason::Paused | SwitchReason::Yield if id == idle => {
+                thread.state = ThreadState::Ready
+            }
+            SwitchReason::Paused | SwitchReason::Yield => scheduler.make_ready(id),
+            // the thread's state already says what it is waiting for
+            SwitchReason::Blocked | SwitchReason::Exit => {}
+        }
+    }
+}
+
+/// gives the rest of the time slice to the next ready thread
+pub fn yield_now() {
+    interrupts::without_interrupts(|| schedule(SwitchReason::Yield));
+}
+
+/// blocks the current thread until the timer has ticked `wake` times since boot, returns false
+/// if threads are not running yet and nothing was done
+pub fn sleep_until(wake: u64) -> bool {
+    interrupts::without_interrupts(|| {
+        match SCHEDULER.lock().as_mut() {
+            Some(scheduler) => scheduler.current_mut().state = ThreadState::Sleeping(wake),
+            None => return false,
+        }
+        schedule(SwitchReason::Blocked);
+        true
+    })
+}
+
+/// ends the current thread, anything joining it is woken up
+pub fn exit() -> ! {
+    interrupts::without_interrupts(|| {
+        let waker = SCHEDULER.lock().as_mut().and_then(|scheduler| {
+            let current = scheduler.current;
+            scheduler.current_mut().state = ThreadState::Exited;
+            let joiners = scheduler
+                .threads
+                .values()
+                .filter(|t| t.state == ThreadState::Joining(current))
+                .map(|t| t.id())
+                .collect::<Vec<ThreadId>>();
+            for id in joiners {
+                scheduler.make_ready(id);
+            }
+            scheduler.current_mut().exit_waker.take()
+        });
+        // whatever it wakes can't run before the switch away, so the stack is free by then
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        schedule(SwitchReason::Exit);
+    });
+    unreachable!("an exited thread was scheduled again")
+}
+
+/// blocks until the thread `id` has exited, then forgets about it and frees its stack
+pub fn join(id: ThreadId) -> Result<(), ThreadError> {
+    loop {
+        let exited = interrupts::without_interrupts(|| {
+            {
+                let mut scheduler = SCHEDULER.lock();
+                let scheduler = scheduler.as_mut().ok_or(ThreadError::NotInitialised)?;
+                if id == scheduler.current {
+                    return Err(ThreadError::Deadlock);
+                }
+                if scheduler.current_mut().killed {
+                    return Err(ThreadError::Killed);
+                }
+                match scheduler.threads.get(&id).map(|t| t.state) {
+                    None => return Err(ThreadError::NotFound),
+                    Some(ThreadState::Exited) => return Ok(scheduler.threads.remove(&id)),
+                    Some(_) => scheduler.current_mut().state = ThreadState::Joining(id),
+                }
+            }
+            schedule(SwitchReason::Blocked);
+            Ok(None)
+        })?;
+        if let Some(thread) = exited {
+            free_stack(thread);
+            return Ok(());
+        }
+    }
+}
+
+/// `join` without waiting, false if the thread hasn't exited yet
+pub fn try_join(id: ThreadId) -> Result<bool, ThreadError> {
+    join_if_exited(id, None)
+}
+
+/// `try_join` for async code, if the thread hasn't exited yet `waker` is woken once it has
+pub fn poll_join(id: ThreadId, waker: &Waker) -> Result<bool, ThreadError> {
+    join_if_exited(id, Some(waker))
+}
+
+fn join_if_exited(id: ThreadId, waker: Option<&Waker>) -> Result<bool, ThreadError> {
+    let exited = interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let scheduler = scheduler.as_mut().ok_or(ThreadError::NotInitialised)?;
+        match scheduler.threads.get_mut(&id) {
+            None => Err(ThreadError::NotFound),
+            Some(thread) if thread.state == ThreadState::Exited => {
+                Ok(scheduler.threads.remove(&id))
+            }
+            Some(thread) => {
+                if let Some(waker) = waker {
+                    thread.exit_waker = Some(waker.clone());
+                }
+                Ok(None)
+            }
+        }
+    })?;
+    match exited {
+        Some(thread) => {
+            free_stack(thread);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// ends the thread `id` the next time it is safe to, when the timer interrupts its user code or
+/// on its way out of a syscall. if it is sleeping or joining it is woken up for it. a kernel
+/// thread that never runs user code or makes a syscall doesn't end.
+pub fn kill(id: ThreadId) -> Result<(), ThreadError> {
+    interrupts::without_interrupts(|| {
+        let mut scheduler = SCHEDULER.lock();
+        let scheduler = scheduler.as_mut().ok_or(ThreadError::NotInitialised)?;
+        let thread = scheduler
+            .threads
+            .get_mut(&id)
+            .ok_or(ThreadError::NotFound)?;
+        thread.killed = true;
+        if matches!(
+            thread.state,
+            ThreadState::Sleeping(_) | ThreadState::Joining(_)
+        ) {
+            scheduler.make_ready(id);
+        }
+        Ok(())
+    })
+}
+
+/// ends the current thread if it has been killed, for places where it holds no locks
+pub fn exit_if_killed() {
+    let killed = interrupts::without_interrupts(|| {
+        SCHEDULER
+            .lock()
+            .as_mut()
+            .map_or(false, |s| s.current_mut().killed)
+    });
+    if killed {
+        exit();
+    }
+}
+
+// the memory locks are taken with interrupts enabled elsewhere, so this happens outside the
+// scheduler lock
+fn free_stack(thread: Thread) {
+    if let Some(stack) = thread.stack_bounds() {
+        unsafe { memory::free_kernel_stack(stack) };
+    }
+}
+
+pub fn current() -> Option<ThreadId> {
+    interrupts::without_interrupts(|| SCHEDULER.lock().as_ref().map(|s| s.current))
+}
+
+/// the process the current thread belongs to, the kernel's before threads are running
+pub fn current_process() -> Pid {
+    interrupts::without_interrupts(|| {
+        SCHEDULER
+            .lock()
+            .as_ref()
+            .and_then(|s| s.threads.get(&s.current))
+            .map_or(Pid::KERNEL, |t| t.process())
+    })
+}
+
+pub fn threads() -> Vec<ThreadInfo> {
+    interrupts::without_interrupts(|| match SCHEDULER.lock().as_ref() {
+        Some(scheduler) => scheduler
+            .threads
+            .values()
+            .map(|t| ThreadInfo {
+                id: t.id().as_u64(),
+                name: String::from(t.name()),
+                pid: t.process().as_u64(),
+                state: t.state(),
+                stack: t
+                    .stack_bounds()
+                    .map(|b| (b.start().as_u64(), b.end().as_u64())),
+            })
+            .collect(),
+        None => Vec::new(),
+    })
+}
+
+#[test_case]
+fn spawned_thread_runs_and_joins() {
+    use alloc::sync::Arc;
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    let counter = Arc::new(AtomicU64::new(0));
+    let c = counter.clone();
+    let id = spawn("test", move || {
+        for _ in 0..3 {
+            c.fetch_add(1, Ordering::SeqCst);
+            yield_now();
+        }
+    })
+    .unwrap();
+
+    join(id).unwrap();
+    assert_eq!(counter.load(Ordering::SeqCst), 3);
+    assert_eq!(join(id), Err(ThreadError::NotFound));
+}
@@ -1,9 +1,119 @@
 use crate::system::kernel::memory::{StackBounds, ThreadId};
-use x86_64::VirtAddr;
+use crate::system::kernel::process::Pid;
+use alloc::string::String;
+use core::task::Waker;
+use x86_64::{structures::paging::PhysFrame, VirtAddr};
+
+use super::thread_switch::{thread_entry_trampoline, ThreadEntry};
+
+/// rflags a new thread starts with, only the interrupt flag is set
+const INITIAL_RFLAGS: u64 = 0x200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadState {
+    Running,
+    Ready,
+    /// waiting for the timer to reach the given tick
+    Sleeping(u64),
+    /// waiting for another thread to exit
+    Joining(ThreadId),
+    /// finished, kept around until it is joined
+    Exited,
+}
+
+impl ThreadState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ThreadState::Running => "running",
+            ThreadState::Ready => "ready",
+            ThreadState::Sleeping(_) => "sleeping",
+            ThreadState::Joining(_) => "joining",
+            ThreadState::Exited => "exited",
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Thread {
     id: ThreadId,
-    stack_ptr: Option<VirtAddr>,
+    name: String,
+    pub(super) state: ThreadState,
+    pub(super) stack_ptr: Option<VirtAddr>,
     stack_bounds: Option<StackBounds>,
+    /// the level 4 table the thread runs under, None for the kernel's own
+    pub(super) page_table: Option<PhysFrame>,
+    pub(super) process: Pid,
+    /// set by `kill`, the thread ends the next time it is safe to
+    pub(super) killed: bool,
+    /// woken once the thread has exited, for `poll_join`
+    pub(super) exit_waker: Option<Waker>,
+}
+
+impl Thread {
+    /// the thread that is already running when the scheduler starts, it keeps the boot stack
+    pub(super) fn bootstrap(name: &str) -> Thread {
+        Thread {
+            id: ThreadId::new(),
+            name: String::from(name),
+            state: ThreadState::Running,
+            stack_ptr: None,
+            stack_bounds: None,
+            page_table: None,
+            process: Pid::KERNEL,
+            killed: false,
+            exit_waker: None,
+        }
+    }
+
+    /// lays out `stack_bounds` as if the thread had been paused by `asm_thread_switch`, so the
+    /// first switch to it pops zeroed registers and returns into `thread_entry_trampoline`
+    pub(super) fn new(name: &str, stack_bounds: StackBounds, entry: ThreadEntry) -> Thread {
+        let entry = alloc::boxed::Box::into_raw(entry);
+        let mut stack_ptr = stack_bounds.end().as_u64();
+        let mut push = |value: u64| {
+            stack_ptr -= 8;
+            unsafe { (stack_ptr as *mut u64).write(value) };
+        };
+
+        push(thread_entry_trampoline as unsafe extern "C" fn() as u64);
+        push(0); // rbp
+        push(0); // rbx
+        push(entry as u64); // r12
+        push(0); // r13
+        push(0); // r14
+        push(0); // r15
+        push(INITIAL_RFLAGS);
+
+        Thread {
+            id: ThreadId::new(),
+            name: String::from(name),
+            state: ThreadState::Ready,
+            stack_ptr: Some(VirtAddr::new(stack_ptr)),
+            stack_bounds: Some(stack_bounds),
+            page_table: None,
+            process: Pid::KERNEL,
+            killed: false,
+            exit_waker: None,
+        }
+    }
+
+    pub fn id(&self) -> ThreadId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn state(&self) -> ThreadState {
+        self.state
+    }
+
+    pub fn stack_bounds(&self) -> Option<StackBounds> {
+        self.stack_bounds
+    }
+
+    pub fn process(&self) -> Pid {
+        self.process
+    }
 }
@@ -1,5 +1,16 @@
-
 .intel_syntax noprefix
+
+// rdi: stack pointer of the thread to switch to
+// rsi: id of the thread being paused
+// rdx: reason for the switch, passed through to add_paused_thread
+.global asm_thread_switch
+asm_thread_switch:
+    push rbp
+    push rbx
+    push r12
+    push r13
+    push r14
+    push r15
     pushfq
 
     mov rax, rsp
@@ -9,4 +20,17 @@
     call add_paused_thread
 
     popfq
-    ret
\ No newline at end of file
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbx
+    pop rbp
+    ret
+
+// new threads "return" here from their first switch with the boxed entry closure in r12
+.global thread_entry_trampoline
+thread_entry_trampoline:
+    mov rdi, r12
+    call thread_entry
+    ud2
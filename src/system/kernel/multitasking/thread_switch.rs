@@ -1,2 +1,48 @@
-use core::arch::global_asm;
+use alloc::boxed::Box;
+use core::arch::{asm, global_asm};
+use x86_64::VirtAddr;
+
+use super::scheduler::{self, SwitchReason};
+use crate::system::kernel::memory::ThreadId;
+
 global_asm!(include_str!("thread_switch.asm"));
+
+/// the closure a new thread runs, boxed twice so it fits in a single register
+pub(super) type ThreadEntry = Box<Box<dyn FnOnce() + Send + 'static>>;
+
+extern "C" {
+    pub(super) fn thread_entry_trampoline();
+}
+
+/// saves the current thread's registers on its stack and continues on `new_stack_ptr`.
+/// must be called with interrupts disabled, they are restored from the new thread's flags.
+pub(super) unsafe fn context_switch_to(
+    new_stack_ptr: VirtAddr,
+    prev_thread_id: ThreadId,
+    reason: SwitchReason,
+) {
+    asm!(
+        "call asm_thread_switch",
+        in("rdi") new_stack_ptr.as_u64(),
+        in("rsi") prev_thread_id.as_u64(),
+        in("rdx") reason as u64,
+        clobber_abi("sysv64"),
+    );
+}
+
+/// called by `asm_thread_switch` once it is running on the new thread's stack
+#[no_mangle]
+extern "C" fn add_paused_thread(paused_stack_ptr: u64, paused_thread_id: u64, reason: u64) {
+    scheduler::add_paused_thread(
+        VirtAddr::new(paused_stack_ptr),
+        ThreadId::from_u64(paused_thread_id),
+        SwitchReason::from_u64(reason),
+    );
+}
+
+#[no_mangle]
+extern "C" fn thread_entry(entry: *mut Box<dyn FnOnce() + Send + 'static>) -> ! {
+    let entry = unsafe { Box::from_raw(entry) };
+    entry();
+    scheduler::exit()
+}
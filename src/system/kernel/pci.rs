@@ -0,0 +1,338 @@
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+use super::ahci;
+use crate::println;
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+// Configuration space offsets shared by every header type
+const PCI_VENDOR_ID: u8 = 0x00;
+const PCI_COMMAND: u8 = 0x04;
+const PCI_CLASS: u8 = 0x08;
+const PCI_HEADER_TYPE: u8 = 0x0C;
+const PCI_BAR0: u8 = 0x10;
+const PCI_INTERRUPT: u8 = 0x3C;
+
+// Command register bits
+pub const PCI_COMMAND_IO: u16 = 1 << 0;
+pub const PCI_COMMAND_MEMORY: u16 = 1 << 1;
+pub const PCI_COMMAND_BUS_MASTER: u16 = 1 << 2;
+
+lazy_static! {
+    pub static ref PCI_DEVICES: Mutex<Vec<PciDevice>> = Mutex::new(Vec::new());
+}
+
+/// a driver that claims every device whose class code matches. `prog_if` can be left as None to
+/// match any programming interface within the subclass.
+pub struct PciDriver {
+    pub name: &'static str,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: Option<u8>,
+    pub init: fn(&PciDevice) -> Result<(), &'static str>,
+}
+
+impl PciDriver {
+    fn matches(&self, device: &PciDevice) -> bool {
+        self.class == device.class
+            && self.subclass == device.subclass
+            && self.prog_if.map_or(true, |p| p == device.prog_if)
+    }
+}
+
+static DRIVERS: &[PciDriver] = &[PciDriver {
+    name: "ahci",
+    class: 0x01,
+    subclass: 0x06,
+    prog_if: Some(0x01),
+    init: ahci::pci_init,
+}];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bar {
+    Memory {
+        address: u64,
+        size: u64,
+        prefetchable: bool,
+    },
+    Io {
+        port: u16,
+        size: u16,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    pub revision: u8,
+    pub header_type: u8,
+    pub bars: [Option<Bar>; 6],
+    pub interrupt_line: u8,
+    pub interrupt_pin: u8,
+    pub driver: Option<&'static str>,
+}
+
+impl PciDevice {
+    fn probe(bus: u8, device: u8, function: u8) -> Option<PciDevice> {
+        let id = config_read(bus, device, function, PCI_VENDOR_ID);
+        if id & 0xFFFF == 0xFFFF {
+            return None;
+        }
+
+        let class = config_read(bus, device, function, PCI_CLASS);
+        let header_type = (config_read(bus, device, function, PCI_HEADER_TYPE) >> 16) as u8;
+        let interrupt = config_read(bus, device, function, PCI_INTERRUPT);
+
+        let mut dev = PciDevice {
+            bus,
+            device,
+            function,
+            vendor_id: id as u16,
+            device_id: (id >> 16) as u16,
+            class: (class >> 24) as u8,
+            subclass: (class >> 16) as u8,
+            prog_if: (class >> 8) as u8,
+            revision: class as u8,
+            header_type,
+            bars: [None; 6],
+            interrupt_line: interrupt as u8,
+            interrupt_pin: (interrupt >> 8) as u8,
+            driver: None,
+        };
+
+        // only general devices (header type 0) have six BARs, bridges have two
+        let bar_count = match header_type & 0x7F {
+            0x00 => 6,
+            0x01 => 2,
+            _ => 0,
+        };
+        let mut i = 0;
+        while i < bar_count {
+            let (bar, width) = dev.read_bar(i);
+            dev.bars[i] = bar;
+            i += width;
+        }
+
+        Some(dev)
+    }
+
+    /// decodes a BAR and measures the size of the region behind it, returns how many BAR slots it used
+    fn read_bar(&self, index: usize) -> (Option<Bar>, usize) {
+        let offset = PCI_BAR0 + index as u8 * 4;
+        let value = self.read(offset);
+
+        // decoding has to be switched off while the BAR is overwritten to find its size
+        let command = self.read_command();
+        self.write_command(command & !(PCI_COMMAND_IO | PCI_COMMAND_MEMORY));
+        self.write(offset, 0xFFFF_FFFF);
+        let mask = self.read(offset);
+        self.write(offset, value);
+
+        let res = if value & 1 == 1 {
+            let size = (!(mask & !0x3)).wrapping_add(1) as u16;
+            match value & !0x3 {
+                0 => (None, 1),
+                port => (
+                    Some(Bar::Io {
+                        port: port as u16,
+                        size,
+                    }),
+                    1,
+                ),
+            }
+        } else {
+            let prefetchable = value & 0x8 != 0;
+            if (value >> 1) & 0x3 == 0x2 {
+                // 64 bit BARs take up this slot and the next one
+                let upper_offset = offset + 4;
+                let upper = self.read(upper_offset);
+                self.write(upper_offset, 0xFFFF_FFFF);
+                let upper_mask = self.read(upper_offset);
+                self.write(upper_offset, upper);
+
+                let address = (upper as u64) << 32 | (value & !0xF) as u64;
+                let mask = (upper_mask as u64) << 32 | (mask & !0xF) as u64;
+                let size = (!mask).wrapping_add(1);
+                match address {
+                    0 => (None, 2),
+                    address => (
+                        Some(Bar::Memory {
+                            address,
+                            size,
+                            prefetchable,
+                        }),
+                        2,
+                    ),
+                }
+            } else {
+                let size = (!(mask & !0xF)).wrapping_add(1) as u64;
+                match value & !0xF {
+                    0 => (None, 1),
+                    address => (
+                        Some(Bar::Memory {
+                            address: address as u64,
+                            size,
+                            prefetchable,
+                        }),
+                        1,
+                    ),
+                }
+            }
+        };
+
+        self.write_command(command);
+        res
+    }
+
+    pub fn read(&self, offset: u8) -> u32 {
+        config_read(self.bus, self.device, self.function, offset)
+    }
+
+    pub fn write(&self, offset: u8, value: u32) {
+        config_write(self.bus, self.device, self.function, offset, value)
+    }
+
+    pub fn read_command(&self) -> u16 {
+        self.read(PCI_COMMAND) as u16
+    }
+
+    pub fn write_command(&self, command: u16) {
+        // the upper half of this register is the status register, writing 1s there clears bits
+        self.write(PCI_COMMAND, command as u32);
+    }
+
+    /// lets the device access memory on its own, required for any device that does DMA
+    pub fn enable_bus_mastering(&self) {
+        let command = self.read_command();
+        self.write_command(command | PCI_COMMAND_MEMORY | PCI_COMMAND_BUS_MASTER);
+    }
+
+    pub fn is_multifunction(&self) -> bool {
+        self.header_type & 0x80 != 0
+    }
+
+    pub fn class_name(&self) -> &'static str {
+        class_name(self.class, self.subclass)
+    }
+
+    pub fn vendor_name(&self) -> &'static str {
+        match self.vendor_id {
+            0x8086 => "Intel",
+            0x1022 => "AMD",
+            0x10DE => "NVIDIA",
+            0x1234 => "QEMU",
+            0x1AF4 | 0x1B36 => "Red Hat",
+            0x10EC => "Realtek",
+            0x15AD => "VMware",
+            0x80EE => "VirtualBox",
+            _ => "Unknown",
+        }
+    }
+}
+
+pub fn config_read(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    unsafe {
+        Port::<u32>::new(CONFIG_ADDRESS).write(config_address(bus, device, function, offset));
+        Port::<u32>::new(CONFIG_DATA).read()
+    }
+}
+
+pub fn config_write(bus: u8, device: u8, function: u8, offset: u8, value: u32) {
+    unsafe {
+        Port::<u32>::new(CONFIG_ADDRESS).write(config_address(bus, device, function, offset));
+        Port::<u32>::new(CONFIG_DATA).write(value);
+    }
+}
+
+fn config_address(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    0x8000_0000
+        | (bus as u32) << 16
+        | (device as u32) << 11
+        | (function as u32) << 8
+        | (offset as u32 & 0xFC)
+}
+
+/// scans every bus for devices using configuration mechanism #1
+pub fn scan() -> Vec<PciDevice> {
+    let mut devices = Vec::new();
+    for bus in 0..=255u8 {
+        for device in 0..32u8 {
+            let first = match PciDevice::probe(bus, device, 0) {
+                Some(d) => d,
+                None => continue,
+            };
+            let multifunction = first.is_multifunction();
+            devices.push(first);
+
+            if multifunction {
+                for function in 1..8u8 {
+                    if let Some(d) = PciDevice::probe(bus, device, function) {
+                        devices.push(d);
+                    }
+                }
+            }
+        }
+    }
+    devices
+}
+
+/// enumerates the bus, then hands each device to the first driver that matches its class code
+pub fn init() {
+    let mut devices = scan();
+
+    for device in devices.iter_mut() {
+        if let Some(driver) = DRIVERS.iter().find(|d| d.matches(device)) {
+            match (driver.init)(device) {
+                Ok(()) => device.driver = Some(driver.name),
+                Err(e) => println!(
+                    "{:02x}:{:02x}.{} {} driver failed: {}",
+                    device.bus, device.device, device.function, driver.name, e
+                ),
+            }
+        }
+    }
+
+    *PCI_DEVICES.lock() = devices;
+}
+
+pub fn class_name(class: u8, subclass: u8) -> &'static str {
+    match (class, subclass) {
+        (0x00, _) => "Unclassified device",
+        (0x01, 0x00) => "SCSI controller",
+        (0x01, 0x01) => "IDE controller",
+        (0x01, 0x05) => "ATA controller",
+        (0x01, 0x06) => "SATA controller",
+        (0x01, 0x08) => "NVMe controller",
+        (0x01, _) => "Mass storage controller",
+        (0x02, 0x00) => "Ethernet controller",
+        (0x02, _) => "Network controller",
+        (0x03, 0x00) => "VGA compatible controller",
+        (0x03, _) => "Display controller",
+        (0x04, 0x01) => "Audio device",
+        (0x04, 0x03) => "Audio device",
+        (0x04, _) => "Multimedia controller",
+        (0x05, _) => "Memory controller",
+        (0x06, 0x00) => "Host bridge",
+        (0x06, 0x01) => "ISA bridge",
+        (0x06, 0x04) => "PCI bridge",
+        (0x06, _) => "Bridge",
+        (0x07, _) => "Communication controller",
+        (0x08, _) => "System peripheral",
+        (0x09, _) => "Input device controller",
+        (0x0C, 0x03) => "USB controller",
+        (0x0C, 0x05) => "SMBus",
+        (0x0C, _) => "Serial bus controller",
+        _ => "Unknown device",
+    }
+}
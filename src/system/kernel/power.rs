@@ -0,0 +1,100 @@
+use x86_64::instructions::port::Port;
+use x86_64::instructions::{hlt, interrupts, tables};
+use x86_64::structures::DescriptorTablePointer;
+use x86_64::{PhysAddr, VirtAddr};
+
+use super::acpi;
+use super::memory::map_mmio;
+use crate::println;
+
+// PM1 control register bits
+const SCI_EN: u16 = 1;
+const SLP_TYP: u16 = 0b111 << 10;
+const SLP_EN: u16 = 1 << 13;
+
+/// puts the machine into the S5 soft off state through the PM1 control registers, only returns
+/// if the firmware doesn't describe how to
+fn enter_s5() -> Option<()> {
+    let fadt = acpi::fadt()?;
+    let (typ_a, typ_b) = acpi::s5_sleep_types()?;
+    if fadt.pm1a_control == 0 {
+        return None;
+    }
+
+    unsafe {
+        let mut pm1a = Port::<u16>::new(fadt.pm1a_control);
+        // the sleep registers are ignored until the firmware hands ACPI over to the OS
+        if fadt.smi_command != 0 && fadt.acpi_enable != 0 && pm1a.read() & SCI_EN == 0 {
+            Port::<u8>::new(fadt.smi_command as u16).write(fadt.acpi_enable);
+            for _ in 0..1_000_000 {
+                if pm1a.read() & SCI_EN != 0 {
+                    break;
+                }
+            }
+        }
+
+        let value = pm1a.read() & !SLP_TYP;
+        pm1a.write(value | (typ_a << 10) | SLP_EN);
+        if fadt.pm1b_control != 0 {
+            let mut pm1b = Port::<u16>::new(fadt.pm1b_control);
+            let value = pm1b.read() & !SLP_TYP;
+            pm1b.write(value | (typ_b << 10) | SLP_EN);
+        }
+    }
+    Some(())
+}
+
+/// turns the machine off with ACPI, failing that with QEMU's isa-debug-exit device, and failing
+/// that halts so it can be switched off by hand
+pub fn shutdown() -> ! {
+    interrupts::disable();
+    enter_s5();
+
+    unsafe { Port::<u32>::new(0xf4).write(0x10) };
+    println!("it is now safe to turn off your computer");
+    loop {
+        hlt();
+    }
+}
+
+/// resets the machine through the FADT reset register, then the 8042 keyboard controller, then
+/// by triple faulting
+pub fn reboot() -> ! {
+    interrupts::disable();
+
+    if let Some((register, value)) = acpi::fadt().and_then(|fadt| fadt.reset) {
+        match register.space {
+            0 => {
+                if let Ok(addr) = map_mmio(PhysAddr::new(register.address), 1) {
+                    unsafe { addr.as_mut_ptr::<u8>().write_volatile(value) };
+                }
+            }
+            1 => unsafe { Port::<u8>::new(register.address as u16).write(value) },
+            // PCI configuration space, not worth supporting for this alone
+            _ => {}
+        }
+    }
+
+    unsafe {
+        let mut command = Port::<u8>::new(0x64);
+        // wait for the controller's input buffer to empty, then pulse the reset line
+        for _ in 0..100_000 {
+            if command.read() & 0b10 == 0 {
+                break;
+            }
+        }
+        command.write(0xFE);
+    }
+
+    // with an empty IDT the breakpoint can't be delivered, nor can the double fault after it
+    unsafe {
+        tables::lidt(&DescriptorTablePointer {
+            limit: 0,
+            base: VirtAddr::new(0),
+        });
+        core::arch::asm!("int3");
+    }
+    loop {
+        hlt();
+    }
+}
@@ -0,0 +1,281 @@
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use core::fmt;
+use core::task::Waker;
+use spin::Mutex;
+use x86_64::VirtAddr;
+
+use super::elf::{self, Symbol};
+use super::memory::{AddressSpace, ThreadId};
+use super::multitasking::{self, ThreadError};
+use super::usermode;
+
+// never touched from interrupt handlers, so it is fine to hold while preemptible
+static PROCESSES: Mutex<BTreeMap<Pid, Process>> = Mutex::new(BTreeMap::new());
+
+/// the exit code of a process ended by `kill`, what a unix shell shows for one killed by SIGKILL
+pub const KILLED: i64 = 137;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pid(u64);
+
+impl Pid {
+    /// everything running inside the kernel, the shell included, belongs to this process
+    pub const KERNEL: Pid = Pid(0);
+
+    fn new() -> Pid {
+        use core::sync::atomic::{AtomicU64, Ordering};
+        static NEXT_PID: AtomicU64 = AtomicU64::new(1);
+        Pid(NEXT_PID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    pub fn from_u64(pid: u64) -> Pid {
+        Pid(pid)
+    }
+
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    Running,
+    /// finished with an exit code, kept until its parent waits on it
+    Exited(i64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProcessError {
+    NotFound,
+    /// only the parent of a process can wait on it
+    NotAChild,
+    OutOfMemory,
+    Thread(ThreadError),
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProcessError::NotFound => write!(f, "no such process"),
+            ProcessError::NotAChild => write!(f, "not a child of this process"),
+            ProcessError::OutOfMemory => write!(f, "not enough memory for a new process"),
+            ProcessError::Thread(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+struct Process {
+    name: String,
+    parent: Pid,
+    // owned here so the page tables live exactly as long as the process
+    #[allow(dead_code)]
+    address_space: AddressSpace,
+    main_thread: ThreadId,
+    state: ProcessState,
+    symbols: Vec<Symbol>,
+    /// nothing is going to wait on it, so it is freed as soon as it has exited
+    detached: bool,
+    /// woken when it exits, for `try_wait`
+    waker: Option<Waker>,
+}
+
+/// a snapshot of a process for listing
+#[derive(Debug, Clone)]
+pub struct ProcessInfo {
+    pub pid: u64,
+    pub parent: u64,
+    pub name: String,
+    pub state: ProcessState,
+}
+
+/// starts a process whose single thread enters ring 3 at `entry` inside `address_space`.
+/// `symbols` are only used to name addresses in fault reports and may be empty.
+pub fn spawn(
+    name: &str,
+    address_space: AddressSpace,
+    entry: VirtAddr,
+    stack_pointer: VirtAddr,
+    args: [u64; 3],
+    symbols: Vec<Symbol>,
+) -> Result<Pid, ProcessError> {
+    reap();
+    let pid = Pid::new();
+    let parent = current();
+    let page_table = address_space.level_4_frame();
+
+    // the table stays locked until the process is in it, so its thread cannot exit before then
+    let mut processes = PROCESSES.lock();
+    let main_thread = multitasking::spawn_in(name, pid, page_table, move || unsafe {
+        usermode::enter_user_mode(entry, stack_pointer, args)
+    })
+    .map_err(ProcessError::Thread)?;
+
+    processes.insert(
+        pid,
+        Process {
+            name: String::from(name),
+            parent,
+            address_space,
+            main_thread,
+            state: ProcessState::Running,
+            symbols,
+            detached: false,
+            waker: None,
+        },
+    );
+    Ok(pid)
+}
+
+/// the process the calling thread belongs to
+pub fn current() -> Pid {
+    multitasking::current_process()
+}
+
+/// ends the calling process with `code`. its children are handed to the kernel, which doesn't
+/// wait on them, so they are freed once they exit.
+pub fn exit(code: i64) -> ! {
+    let pid = current();
+    if pid != Pid::KERNEL {
+        set_exited(&mut PROCESSES.lock(), pid, code);
+        reap();
+    }
+    multitasking::exit()
+}
+
+/// ends the process `pid` with the exit code `KILLED`. its thread stops the next time the timer
+/// interrupts its user code or it comes out of a syscall.
+pub fn kill(pid: Pid) -> Result<(), ProcessError> {
+    let thread = {
+        let mut processes = PROCESSES.lock();
+        let process = processes.get(&pid).ok_or(ProcessError::NotFound)?;
+        if process.state != ProcessState::Running {
+            return Ok(());
+        }
+        let thread = process.main_thread;
+        set_exited(&mut processes, pid, KILLED);
+        thread
+    };
+    multitasking::kill(thread).map_err(ProcessError::Thread)
+}
+
+/// records the exit code of `pid` if it hasn't already got one, wakes whatever is waiting on it
+/// and hands its children to the kernel
+fn set_exited(processes: &mut BTreeMap<Pid, Process>, pid: Pid, code: i64) {
+    if let Some(process) = processes.get_mut(&pid) {
+        if process.state == ProcessState::Running {
+            process.state = ProcessState::Exited(code);
+        }
+        if let Some(waker) = process.waker.take() {
+            waker.wake();
+        }
+    }
+    for process in processes.values_mut().filter(|p| p.parent == pid) {
+        process.parent = Pid::KERNEL;
+        process.detached = true;
+    }
+}
+
+/// says nothing is going to wait on `pid`, it is freed as soon as it exits rather than being
+/// kept for its exit code
+pub fn detach(pid: Pid) {
+    if let Some(process) = PROCESSES.lock().get_mut(&pid) {
+        process.detached = true;
+    }
+    reap();
+}
+
+/// frees detached processes that have exited, once their thread is gone too. a process can't
+/// free itself, its page tables and kernel stack are still in use until its thread switches
+/// away for the last time, so this is done by whoever comes along next.
+fn reap() {
+    let exited = PROCESSES
+        .lock()
+        .iter()
+        .filter(|(_, p)| p.detached && matches!(p.state, ProcessState::Exited(_)))
+        .map(|(pid, p)| (*pid, p.main_thread))
+        .collect::<Vec<_>>();
+    for (pid, thread) in exited {
+        // the process is marked as exited just before its thread ends, so it may not be gone yet
+        if multitasking::try_join(thread) != Ok(false) {
+            PROCESSES.lock().remove(&pid);
+        }
+    }
+}
+
+/// blocks until the child `pid` has exited, then removes it and returns its exit code
+pub fn wait(pid: Pid) -> Result<i64, ProcessError> {
+    let thread = {
+        let processes = PROCESSES.lock();
+        let process = processes.get(&pid).ok_or(ProcessError::NotFound)?;
+        if process.parent != current() {
+            return Err(ProcessError::NotAChild);
+        }
+        process.main_thread
+    };
+
+    multitasking::join(thread).map_err(ProcessError::Thread)?;
+    reap();
+    let process = PROCESSES
+        .lock()
+        .remove(&pid)
+        .ok_or(ProcessError::NotFound)?;
+    Ok(match process.state {
+        ProcessState::Exited(code) => code,
+        // the thread ended without going through exit
+        ProcessState::Running => -1,
+    })
+}
+
+/// `wait` for async code, it never blocks. while the child is still running this gives back None
+/// and `waker` is woken once it has exited.
+pub fn try_wait(pid: Pid, waker: &Waker) -> Result<Option<i64>, ProcessError> {
+    let (thread, code) = {
+        let mut processes = PROCESSES.lock();
+        let process = processes.get_mut(&pid).ok_or(ProcessError::NotFound)?;
+        if process.parent != current() {
+            return Err(ProcessError::NotAChild);
+        }
+        match process.state {
+            ProcessState::Running => {
+                process.waker = Some(waker.clone());
+                return Ok(None);
+            }
+            ProcessState::Exited(code) => (process.main_thread, code),
+        }
+    };
+    // the process is marked as exited just before its thread ends, `waker` is woken again once
+    // the thread has gone too
+    if !multitasking::poll_join(thread, waker).map_err(ProcessError::Thread)? {
+        return Ok(None);
+    }
+    PROCESSES.lock().remove(&pid);
+    reap();
+    Ok(Some(code))
+}
+
+pub fn list() -> Vec<ProcessInfo> {
+    reap();
+    PROCESSES
+        .lock()
+        .iter()
+        .map(|(pid, p)| ProcessInfo {
+            pid: pid.as_u64(),
+            parent: p.parent.as_u64(),
+            name: p.name.clone(),
+            state: p.state,
+        })
+        .collect()
+}
+
+/// names `addr` in the current process for fault reports, e.g. "hello (pid 3) at _start+0x5".
+/// runs inside exception handlers, so it gives up instead of waiting on the process table.
+pub fn locate(addr: VirtAddr) -> Option<String> {
+    let pid = current();
+    let processes = PROCESSES.try_lock()?;
+    let process = processes.get(&pid)?;
+    let mut location = format!("{} (pid {})", process.name, pid.as_u64());
+    if let Some((symbol, offset)) = elf::symbol_at(&process.symbols, addr.as_u64()) {
+        location += &format!(" at {}+{:#x}", symbol.name, offset);
+    }
+    Some(location)
+}
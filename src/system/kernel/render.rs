@@ -93,6 +93,7 @@ pub struct Renderer {
     app_buffer: [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT], // this is where applications render their frames to
     application_mode: bool, // if false: term mode; if true: app mode
     temp_colour: Option<ColorCode>,
+    cursor_offset: usize, // how far back from the end of the output the cursor is drawn
 }
 
 lazy_static! {
@@ -103,6 +104,7 @@ lazy_static! {
         app_buffer: [[ScreenChar::null(); BUFFER_WIDTH]; BUFFER_HEIGHT],
         application_mode: false,
         temp_colour: None,
+        cursor_offset: 0,
     });
 }
 
@@ -172,14 +174,18 @@ impl Renderer {
         self.internal_render();
     }
 
-    pub fn backspace(&mut self) -> Result<(), RenderError> {
+    /// backspaces `count` characters and only redraws the screen once
+    pub fn erase(&mut self, count: usize) -> Result<(), RenderError> {
         if self.application_mode {
             return Ok(());
         };
+        self.cursor_offset = 0;
 
-        loop {
-            if self.internal_backspace()? {
-                break;
+        for _ in 0..count {
+            loop {
+                if self.internal_backspace()? {
+                    break;
+                }
             }
         }
 
@@ -204,6 +210,15 @@ impl Renderer {
         self.temp_colour = None;
     }
 
+    /// draws the terminal cursor `offset` characters before the end of the output, for editing
+    /// in the middle of a line. anything written puts it back at the end.
+    pub fn set_cursor_offset(&mut self, offset: usize) {
+        self.cursor_offset = offset;
+        if !self.application_mode {
+            self.internal_render();
+        }
+    }
+
     pub fn cursor_position(&mut self, x: u8, y: u8) -> Result<(), RenderError> {
         // check that x and y are within bounds
         if x >= 80 || y >= 25 {
@@ -296,6 +311,7 @@ impl Renderer {
     }
 
     fn write_byte(&mut self, byte: u8, col: Option<ColorCode>) {
+        self.cursor_offset = 0;
         // default colour if no colour is selected for character
         self.write_screen_char(ScreenChar {
             character: byte,
@@ -327,7 +343,16 @@ impl Renderer {
                     self.screen_ref.chars[i][j].write(*col);
                 }
             }
-            self.internal_set_cursor_position(self.col_pos as u8, BUFFER_HEIGHT as u8 - 1);
+            let back = self
+                .cursor_offset
+                .min(self.col_pos + BUFFER_WIDTH * (BUFFER_HEIGHT - 1));
+            let (mut x, mut y) = (self.col_pos, BUFFER_HEIGHT - 1);
+            if back > x {
+                let lines = (back - x + BUFFER_WIDTH - 1) / BUFFER_WIDTH;
+                x += lines * BUFFER_WIDTH;
+                y -= lines;
+            }
+            self.internal_set_cursor_position((x - back) as u8, y as u8);
         }
     }
 }
@@ -369,6 +394,12 @@ impl fmt::Write for Renderer {
     }
 }
 
+/// locks the renderer with interrupts disabled. it is shared with code that prints from inside
+/// `without_interrupts`, so holding it while preemptible could deadlock against another thread.
+pub fn with_renderer<R>(f: impl FnOnce(&mut Renderer) -> R) -> R {
+    x86_64::instructions::interrupts::without_interrupts(|| f(&mut RENDERER.lock()))
+}
+
 pub fn write(args: fmt::Arguments, cols: (Color, Color)) {
     use core::fmt::Write;
     use x86_64::instructions::interrupts;
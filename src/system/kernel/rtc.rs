@@ -0,0 +1,335 @@
+use conquer_once::spin::OnceCell;
+use core::fmt;
+use core::ops::{Add, Sub};
+use core::sync::atomic::{AtomicI32, Ordering};
+use core::time::Duration;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+use super::{acpi, clock};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+const SECS_PER_DAY: i64 = 86_400;
+
+// CMOS registers
+const RTC_SECONDS: u8 = 0x00;
+const RTC_MINUTES: u8 = 0x02;
+const RTC_HOURS: u8 = 0x04;
+const RTC_DAY: u8 = 0x07;
+const RTC_MONTH: u8 = 0x08;
+const RTC_YEAR: u8 = 0x09;
+const RTC_STATUS_A: u8 = 0x0A;
+const RTC_STATUS_B: u8 = 0x0B;
+const STATUS_A_UPDATING: u8 = 0x80;
+const STATUS_B_24_HOUR: u8 = 0x02;
+const STATUS_B_BINARY: u8 = 0x04;
+const HOUR_PM: u8 = 0x80;
+
+/// the RTC reading taken at boot, as nanoseconds since the epoch, and the monotonic clock at
+/// that moment. the RTC only counts whole seconds so the wall clock runs off the monotonic one.
+static BOOT: OnceCell<(u64, u64)> = OnceCell::uninit();
+/// minutes east of UTC that local time is shown in
+static TIMEZONE_OFFSET: AtomicI32 = AtomicI32::new(0);
+
+/// a point in wall clock time, nanoseconds since 1970-01-01 00:00:00 UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemTime(u64);
+
+pub const UNIX_EPOCH: SystemTime = SystemTime(0);
+
+impl SystemTime {
+    /// the RTC time read at boot moved on by the monotonic clock, just time since boot if the
+    /// RTC couldn't be read
+    pub fn now() -> SystemTime {
+        let (boot, at) = BOOT.try_get().copied().unwrap_or((0, 0));
+        SystemTime(boot + clock::now().saturating_sub(at))
+    }
+
+    pub fn from_unix(secs: u64) -> SystemTime {
+        SystemTime(secs * NANOS_PER_SEC)
+    }
+
+    /// whole seconds since the epoch
+    pub fn unix(&self) -> u64 {
+        self.0 / NANOS_PER_SEC
+    }
+
+    /// None if `earlier` is actually later
+    pub fn duration_since(&self, earlier: SystemTime) -> Option<Duration> {
+        self.0.checked_sub(earlier.0).map(Duration::from_nanos)
+    }
+
+    pub fn elapsed(&self) -> Option<Duration> {
+        SystemTime::now().duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<SystemTime> {
+        u64::try_from(duration.as_nanos())
+            .ok()
+            .and_then(|nanos| self.0.checked_add(nanos))
+            .map(SystemTime)
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<SystemTime> {
+        u64::try_from(duration.as_nanos())
+            .ok()
+            .and_then(|nanos| self.0.checked_sub(nanos))
+            .map(SystemTime)
+    }
+}
+
+impl Add<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn add(self, duration: Duration) -> SystemTime {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to system time")
+    }
+}
+
+impl Sub<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn sub(self, duration: Duration) -> SystemTime {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting duration from system time")
+    }
+}
+
+/// days since 1970-01-01 of a date in the proleptic gregorian calendar
+fn days_from_civil(year: i64, month: u8, day: u8) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_from_march = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// the inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u8;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    } as u8;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// a calendar date and time of day at some offset from UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub nanosecond: u32,
+    /// minutes east of UTC
+    pub offset: i32,
+}
+
+impl DateTime {
+    pub fn with_offset(time: SystemTime, offset: i32) -> DateTime {
+        let secs = time.unix() as i64 + offset as i64 * 60;
+        let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+        let secs_of_day = secs.rem_euclid(SECS_PER_DAY);
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (secs_of_day / 3600) as u8,
+            minute: (secs_of_day / 60 % 60) as u8,
+            second: (secs_of_day % 60) as u8,
+            nanosecond: (time.0 % NANOS_PER_SEC) as u32,
+            offset,
+        }
+    }
+
+    pub fn utc(time: SystemTime) -> DateTime {
+        DateTime::with_offset(time, 0)
+    }
+
+    /// in the timezone set with `set_timezone_offset`
+    pub fn local(time: SystemTime) -> DateTime {
+        DateTime::with_offset(time, timezone_offset())
+    }
+
+    pub fn now() -> DateTime {
+        DateTime::local(SystemTime::now())
+    }
+
+    /// None for dates before the epoch
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let secs = days * SECS_PER_DAY
+            + self.hour as i64 * 3600
+            + self.minute as i64 * 60
+            + self.second as i64
+            - self.offset as i64 * 60;
+        let secs = u64::try_from(secs).ok()?;
+        Some(SystemTime(secs * NANOS_PER_SEC + self.nanosecond as u64))
+    }
+
+    /// three letter name of the day of the week
+    pub fn weekday(&self) -> &'static str {
+        // the epoch was a thursday
+        let days = days_from_civil(self.year, self.month, self.day);
+        WEEKDAYS[(days + 4).rem_euclid(7) as usize]
+    }
+
+    /// three letter name of the month
+    pub fn month_name(&self) -> &'static str {
+        MONTHS[(self.month as usize).clamp(1, 12) - 1]
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} ",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )?;
+        match self.offset {
+            0 => write!(f, "UTC"),
+            offset => write!(
+                f,
+                "{}{:02}:{:02}",
+                if offset < 0 { '-' } else { '+' },
+                offset.abs() / 60,
+                offset.abs() % 60
+            ),
+        }
+    }
+}
+
+pub fn set_timezone_offset(minutes: i32) {
+    TIMEZONE_OFFSET.store(minutes, Ordering::Relaxed);
+}
+
+pub fn timezone_offset() -> i32 {
+    TIMEZONE_OFFSET.load(Ordering::Relaxed)
+}
+
+fn cmos(register: u8) -> u8 {
+    let mut select = Port::<u8>::new(0x70);
+    let mut data = Port::<u8>::new(0x71);
+    unsafe {
+        // the top bit keeps NMIs disabled while the register is selected
+        select.write(0x80 | register);
+        data.read()
+    }
+}
+
+fn read_registers(century: u8) -> [u8; 7] {
+    while cmos(RTC_STATUS_A) & STATUS_A_UPDATING != 0 {}
+    [
+        cmos(RTC_SECONDS),
+        cmos(RTC_MINUTES),
+        cmos(RTC_HOURS),
+        cmos(RTC_DAY),
+        cmos(RTC_MONTH),
+        cmos(RTC_YEAR),
+        if century != 0 { cmos(century) } else { 0 },
+    ]
+}
+
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+/// reads the date and time from the CMOS RTC, which is assumed to keep UTC
+pub fn read() -> Option<DateTime> {
+    // ACPI says which CMOS register holds the century, if any does
+    let century = acpi::fadt().map_or(0, |fadt| fadt.century);
+
+    let (values, status) = interrupts::without_interrupts(|| {
+        // an update can land in the middle of a read, so read until two agree
+        let mut values = read_registers(century);
+        loop {
+            let again = read_registers(century);
+            if again == values {
+                break;
+            }
+            values = again;
+        }
+        (values, cmos(RTC_STATUS_B))
+    });
+
+    let [mut second, mut minute, hour, mut day, mut month, mut year, mut century] = values;
+    let pm = hour & HOUR_PM != 0;
+    let mut hour = hour & !HOUR_PM;
+    if status & STATUS_B_BINARY == 0 {
+        second = from_bcd(second);
+        minute = from_bcd(minute);
+        hour = from_bcd(hour);
+        day = from_bcd(day);
+        month = from_bcd(month);
+        year = from_bcd(year);
+        century = from_bcd(century);
+    }
+    if status & STATUS_B_24_HOUR == 0 {
+        // 12 is midnight in the am and midday in the pm
+        hour = hour % 12 + if pm { 12 } else { 0 };
+    }
+    let century = if century != 0 { century as i64 } else { 20 };
+
+    let date = DateTime {
+        year: century * 100 + year as i64,
+        month,
+        day,
+        hour,
+        minute,
+        second,
+        nanosecond: 0,
+        offset: 0,
+    };
+    let valid = (1..=12).contains(&month) && (1..=31).contains(&day) && hour < 24;
+    valid.then_some(date)
+}
+
+/// reads the RTC once so the wall clock can be kept from the monotonic clock from then on
+pub fn init() -> Option<DateTime> {
+    let date = read()?;
+    let time = date.to_system_time()?;
+    BOOT.try_init_once(|| (time.0, clock::now())).ok()?;
+    Some(date)
+}
+
+#[test_case]
+fn dates_round_trip() {
+    use alloc::string::ToString;
+
+    let date = DateTime {
+        year: 2024,
+        month: 2,
+        day: 29,
+        hour: 23,
+        minute: 59,
+        second: 58,
+        nanosecond: 0,
+        offset: 60,
+    };
+    let time = date.to_system_time().unwrap();
+    assert_eq!(time.unix(), 1_709_247_598);
+    assert_eq!(DateTime::with_offset(time, 60), date);
+    assert_eq!(date.weekday(), "Thu");
+    assert_eq!(DateTime::utc(time).to_string(), "2024-02-29 22:59:58 UTC");
+}
@@ -0,0 +1,240 @@
+use core::arch::global_asm;
+use x86_64::structures::idt::InterruptDescriptorTable;
+use x86_64::{PrivilegeLevel, VirtAddr};
+
+use super::clock;
+use super::memory;
+use super::multitasking;
+use super::process::{self, Pid};
+use super::render::{with_renderer, ScreenChar, BUFFER_HEIGHT, BUFFER_WIDTH};
+use super::tasks::keyboard::KEYBOARD;
+use crate::print;
+
+/// the interrupt vector user code raises to make a syscall
+pub const SYSCALL_VECTOR: usize = 0x80;
+
+/// returned in rax when a syscall number is unknown or its arguments are invalid
+pub const SYSCALL_ERROR: u64 = u64::MAX;
+
+pub type Frame = [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT];
+
+/// syscall numbers, passed in rax. arguments go in rdi, rsi and rdx and the result comes back
+/// in rax, every other register is preserved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u64)]
+pub enum Syscall {
+    /// (ptr, len) prints a utf-8 string, returns the number of bytes written
+    Write = 0,
+    /// () returns the next keystroke packed with `KeyStroke::to_u64`, or SYSCALL_ERROR if none
+    ReadKey = 1,
+    /// (milliseconds) blocks the calling thread
+    Sleep = 2,
+    /// (code) ends the calling process, or just the thread if it belongs to the kernel
+    Exit = 3,
+    /// (ptr) copies a full 80x25 frame into the application buffer
+    Blit = 4,
+    /// () puts the screen back into terminal mode
+    TerminalMode = 5,
+    /// () returns the pid of the calling process
+    GetPid = 6,
+    /// (pid, status ptr) blocks until a child exits and stores its exit code as an i64
+    Wait = 7,
+}
+
+struct Args {
+    from_user: bool,
+    arg0: u64,
+    arg1: u64,
+}
+
+static SYSCALLS: [fn(&Args) -> u64; 8] = [
+    sys_write,
+    sys_read_key,
+    sys_sleep,
+    sys_exit,
+    sys_blit,
+    sys_terminal_mode,
+    sys_get_pid,
+    sys_wait,
+];
+
+/// registers saved by `syscall_entry`, followed by the frame the cpu pushed
+#[allow(dead_code)]
+#[repr(C)]
+struct SyscallFrame {
+    r15: u64,
+    r14: u64,
+    r13: u64,
+    r12: u64,
+    r11: u64,
+    r10: u64,
+    r9: u64,
+    r8: u64,
+    rbp: u64,
+    rdi: u64,
+    rsi: u64,
+    rdx: u64,
+    rcx: u64,
+    rbx: u64,
+    rax: u64,
+    rip: u64,
+    cs: u64,
+    rflags: u64,
+    rsp: u64,
+    ss: u64,
+}
+
+// the cpu aligns the stack before pushing its five words, the fifteen pushed here leave it
+// aligned again for the call
+global_asm!(
+    r#"
+.global syscall_entry
+syscall_entry:
+    push rax
+    push rbx
+    push rcx
+    push rdx
+    push rsi
+    push rdi
+    push rbp
+    push r8
+    push r9
+    push r10
+    push r11
+    push r12
+    push r13
+    push r14
+    push r15
+
+    cld
+    mov rdi, rsp
+    call syscall_dispatch
+
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop r11
+    pop r10
+    pop r9
+    pop r8
+    pop rbp
+    pop rdi
+    pop rsi
+    pop rdx
+    pop rcx
+    pop rbx
+    pop rax
+    iretq
+"#
+);
+
+extern "C" {
+    fn syscall_entry();
+}
+
+/// installs the syscall gate. it can be raised from ring 3 and leaves interrupts enabled, so a
+/// thread inside a syscall can still be preempted.
+pub fn register(idt: &mut InterruptDescriptorTable) {
+    unsafe {
+        idt[SYSCALL_VECTOR]
+            .set_handler_addr(VirtAddr::new(
+                syscall_entry as unsafe extern "C" fn() as u64,
+            ))
+            .set_privilege_level(PrivilegeLevel::Ring3)
+            .disable_interrupts(false);
+    }
+}
+
+#[no_mangle]
+extern "C" fn syscall_dispatch(frame: &mut SyscallFrame) {
+    let args = Args {
+        from_user: frame.cs & 3 == 3,
+        arg0: frame.rdi,
+        arg1: frame.rsi,
+    };
+    frame.rax = match SYSCALLS.get(frame.rax as usize) {
+        Some(syscall) => syscall(&args),
+        None => SYSCALL_ERROR,
+    };
+    // a process killed during the syscall doesn't go back to user code
+    multitasking::exit_if_killed();
+}
+
+/// a pointer handed over by the caller, checked against the page tables if it came from ring 3.
+/// `writable` is for syscalls that write through it, a read only page would fault in ring 0
+fn user_ptr(args: &Args, ptr: u64, len: u64, writable: bool) -> Option<*const u8> {
+    if ptr == 0
+        || (args.from_user
+            && !memory::is_user_accessible(VirtAddr::try_new(ptr).ok()?, len, writable))
+    {
+        return None;
+    }
+    Some(ptr as *const u8)
+}
+
+fn sys_write(args: &Args) -> u64 {
+    let ptr = match user_ptr(args, args.arg0, args.arg1, false) {
+        Some(ptr) => ptr,
+        None => return SYSCALL_ERROR,
+    };
+    let bytes = unsafe { core::slice::from_raw_parts(ptr, args.arg1 as usize) };
+    match core::str::from_utf8(bytes) {
+        Ok(s) => {
+            print!("{}", s);
+            args.arg1
+        }
+        Err(_) => SYSCALL_ERROR,
+    }
+}
+
+fn sys_read_key(_args: &Args) -> u64 {
+    // the shell keeps the keyboard locked while it waits for a line, nothing is read then
+    KEYBOARD
+        .try_lock()
+        .and_then(|mut keyboard| keyboard.try_keystroke())
+        .map_or(SYSCALL_ERROR, |key| key.to_u64())
+}
+
+fn sys_sleep(args: &Args) -> u64 {
+    let wake = clock::now().saturating_add(args.arg0.saturating_mul(1_000_000));
+    multitasking::sleep_until(clock::tick_at(wake));
+    0
+}
+
+fn sys_exit(args: &Args) -> u64 {
+    process::exit(args.arg0 as i64)
+}
+
+fn sys_blit(args: &Args) -> u64 {
+    let ptr = match user_ptr(args, args.arg0, core::mem::size_of::<Frame>() as u64, false) {
+        Some(ptr) => ptr as *const Frame,
+        None => return SYSCALL_ERROR,
+    };
+    let frame = unsafe { ptr.read_unaligned() };
+    with_renderer(|r| r.render_frame(frame));
+    0
+}
+
+fn sys_terminal_mode(_args: &Args) -> u64 {
+    with_renderer(|r| r.terminal_mode_force());
+    0
+}
+
+fn sys_get_pid(_args: &Args) -> u64 {
+    process::current().as_u64()
+}
+
+fn sys_wait(args: &Args) -> u64 {
+    let ptr = match user_ptr(args, args.arg1, core::mem::size_of::<i64>() as u64, true) {
+        Some(ptr) => ptr as *mut i64,
+        None => return SYSCALL_ERROR,
+    };
+    match process::wait(Pid::from_u64(args.arg0)) {
+        Ok(code) => {
+            unsafe { ptr.write_unaligned(code) };
+            0
+        }
+        Err(_) => SYSCALL_ERROR,
+    }
+}
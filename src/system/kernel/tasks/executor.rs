@@ -1,11 +1,27 @@
-use super::{Task, TaskId};
+use super::{Header, Task, TaskId, TaskState, TASKS};
 use alloc::{collections::BTreeMap, sync::Arc, task::Wake};
+use core::fmt;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
 use core::task::{Context, Poll, Waker};
-use crossbeam_queue::ArrayQueue;
+use crossbeam_queue::SegQueue;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+// there is only ever one executor, so the queues are global and anything can spawn onto it.
+// wakers push from interrupt handlers too, which is why these are lock free.
+lazy_static! {
+    static ref READY: SegQueue<TaskId> = SegQueue::new();
+    static ref SPAWNED: SegQueue<Task> = SegQueue::new();
+}
+
+/// the id of the task being polled, NO_TASK between polls
+static CURRENT: AtomicU64 = AtomicU64::new(NO_TASK);
+const NO_TASK: u64 = u64::MAX;
 
 pub struct Executor {
     tasks: BTreeMap<TaskId, Task>,
-    task_queue: Arc<ArrayQueue<TaskId>>,
     waker_cache: BTreeMap<TaskId, Waker>,
 }
 
@@ -13,7 +29,6 @@ impl Executor {
     pub fn new() -> Self {
         Executor {
             tasks: BTreeMap::new(),
-            task_queue: Arc::new(ArrayQueue::new(100)),
             waker_cache: BTreeMap::new(),
         }
     }
@@ -23,26 +38,44 @@ impl Executor {
         if self.tasks.insert(task.id, task).is_some() {
             panic!("a task with this id has already been allocated");
         }
-        self.task_queue.push(task_id).expect("task queue is full");
+        READY.push(task_id);
     }
 
-    fn run_ready_tasks(&mut self) {
-        let Self {
-            tasks,
-            task_queue,
-            waker_cache,
-        } = self;
+    /// takes on the tasks spawned through `spawn` since the last time round
+    fn adopt_spawned(&mut self) {
+        while let Ok(task) = SPAWNED.pop() {
+            self.spawn(task);
+        }
+    }
 
-        while let Ok(task_id) = task_queue.pop() {
+    fn run_ready_tasks(&mut self) {
+        self.adopt_spawned();
+        while let Ok(task_id) = READY.pop() {
+            self.adopt_spawned();
+            let Self { tasks, waker_cache } = self;
             let task = match tasks.get_mut(&task_id) {
                 Some(task) => task,
                 None => continue,
             };
+            // cancelling a task just drops it the next time it comes up
+            if task.header.cancelled.load(Ordering::Acquire) {
+                tasks.remove(&task_id);
+                waker_cache.remove(&task_id);
+                continue;
+            }
+            // a suspended task stays parked until `resume` queues it again
+            if task.header.suspended.load(Ordering::Acquire) {
+                task.header.set_state(TaskState::Stopped);
+                continue;
+            }
             let waker = waker_cache
                 .entry(task_id)
-                .or_insert_with(|| TaskWaker::new(task_id, task_queue.clone()));
+                .or_insert_with(|| TaskWaker::new(task_id, task.header.clone()));
             let mut context = Context::from_waker(waker);
-            match task.poll(&mut context) {
+            CURRENT.store(task_id.as_u64(), Ordering::Relaxed);
+            let poll = task.poll(&mut context);
+            CURRENT.store(NO_TASK, Ordering::Relaxed);
+            match poll {
                 Poll::Ready(()) => {
                     tasks.remove(&task_id);
                     waker_cache.remove(&task_id);
@@ -61,7 +94,7 @@ impl Executor {
         use x86_64::instructions::interrupts::{self, enable_and_hlt};
 
         interrupts::disable();
-        if self.task_queue.is_empty() {
+        if READY.is_empty() && SPAWNED.is_empty() {
             enable_and_hlt();
         } else {
             interrupts::enable();
@@ -69,23 +102,25 @@ impl Executor {
     }
 }
 
+/// puts a task back in the ready queue, only once however many times it is woken before it runs
+fn enqueue(task_id: TaskId, header: &Header) {
+    if header.state.swap(TaskState::Ready as u8, Ordering::AcqRel) != TaskState::Ready as u8 {
+        READY.push(task_id);
+    }
+}
+
 struct TaskWaker {
     task_id: TaskId,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+    header: Arc<Header>,
 }
 
 impl TaskWaker {
-    fn new(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
-        Waker::from(Arc::new(TaskWaker {
-            task_id,
-            task_queue,
-        }))
+    fn new(task_id: TaskId, header: Arc<Header>) -> Waker {
+        Waker::from(Arc::new(TaskWaker { task_id, header }))
     }
 
     fn wake_task(&self) {
-        self.task_queue
-            .push(self.task_id)
-            .expect("task queue is full")
+        enqueue(self.task_id, &self.header);
     }
 }
 
@@ -97,3 +132,166 @@ impl Wake for TaskWaker {
         self.wake_task();
     }
 }
+
+/// why a task didn't give back its output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinError {
+    Cancelled,
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JoinError::Cancelled => write!(f, "the task was cancelled"),
+        }
+    }
+}
+
+struct Slot<T> {
+    output: Option<T>,
+    finished: bool,
+    waiter: Option<Waker>,
+}
+
+/// owned by the spawned future, finishes the slot however the future goes away so a cancelled
+/// task still wakes whoever is joining it
+struct Completion<T>(Arc<Mutex<Slot<T>>>);
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        let mut slot = self.0.lock();
+        slot.finished = true;
+        if let Some(waiter) = slot.waiter.take() {
+            waiter.wake();
+        }
+    }
+}
+
+/// awaits the output of a spawned task, dropping it leaves the task running
+pub struct JoinHandle<T> {
+    id: TaskId,
+    header: Arc<Header>,
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.slot.lock().finished
+    }
+
+    /// stops the task before its next poll, awaiting the handle then gives `JoinError::Cancelled`
+    pub fn cancel(&self) {
+        cancel_task(self.id, &self.header);
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock();
+        if let Some(output) = slot.output.take() {
+            return Poll::Ready(Ok(output));
+        }
+        if slot.finished {
+            return Poll::Ready(Err(JoinError::Cancelled));
+        }
+        slot.waiter = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// runs `future` as its own task on the executor alongside the shell
+pub fn spawn<F>(name: &str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let slot = Arc::new(Mutex::new(Slot {
+        output: None,
+        finished: false,
+        waiter: None,
+    }));
+    let completion = Completion(slot.clone());
+    let task = Task::new(name, async move {
+        let output = future.await;
+        completion.0.lock().output = Some(output);
+        drop(completion);
+    });
+    let handle = JoinHandle {
+        id: task.id,
+        header: task.header.clone(),
+        slot,
+    };
+    SPAWNED.push(task);
+    handle
+}
+
+fn cancel_task(id: TaskId, header: &Header) {
+    header.cancelled.store(true, Ordering::Release);
+    // the executor only looks at the flag when the task comes out of the queue
+    enqueue(id, header);
+}
+
+fn with_header(id: TaskId, f: impl FnOnce(&Header)) -> bool {
+    let header = TASKS.lock().get(&id).cloned();
+    match header {
+        Some(header) => {
+            f(&header);
+            true
+        }
+        None => false,
+    }
+}
+
+/// cancels a task by id, false if there is no such task
+pub fn cancel(id: TaskId) -> bool {
+    with_header(id, |header| cancel_task(id, header))
+}
+
+/// stops polling a task until it is resumed, false if there is no such task
+pub fn suspend(id: TaskId) -> bool {
+    with_header(id, |header| {
+        header.suspended.store(true, Ordering::Release);
+        // queued so the executor marks it as stopped straight away
+        enqueue(id, header);
+    })
+}
+
+/// lets a suspended task carry on, it is polled once in case it missed a wake while stopped
+pub fn resume(id: TaskId) -> bool {
+    with_header(id, |header| {
+        header.suspended.store(false, Ordering::Release);
+        enqueue(id, header);
+    })
+}
+
+/// the task that is being polled right now
+pub fn current() -> Option<TaskId> {
+    match CURRENT.load(Ordering::Relaxed) {
+        NO_TASK => None,
+        id => Some(TaskId(id)),
+    }
+}
+
+#[test_case]
+fn spawned_tasks_can_be_joined_and_cancelled() {
+    let mut executor = Executor::new();
+    let answer = spawn("answer", async { 42 });
+    let stuck = spawn("stuck", core::future::pending::<()>());
+    let result = Arc::new(Mutex::new(None));
+    let output = result.clone();
+    executor.spawn(Task::new("join", async move {
+        stuck.cancel();
+        *output.lock() = Some((answer.await, stuck.await));
+    }));
+
+    while result.lock().is_none() {
+        executor.try_run();
+    }
+    assert_eq!(*result.lock(), Some((Ok(42), Err(JoinError::Cancelled))));
+}
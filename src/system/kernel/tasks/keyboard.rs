@@ -1,26 +1,45 @@
 use lazy_static::lazy_static;
 use spin::Mutex;
-use x86_64::instructions::interrupts;
 
 use crate::{println, serial_print, serial_println, system::kernel::serial};
 use conquer_once::spin::OnceCell;
 use crossbeam_queue::ArrayQueue;
 
-use crate::print;
-use crate::system::kernel::render::RENDERER;
-use alloc::string::String;
+use alloc::vec::Vec;
 use core::{
+    future::Future,
     pin::Pin,
-    task::{Context, Poll},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering},
+    task::{Context, Poll, Waker},
 };
 use futures_util::stream::Stream;
-use futures_util::stream::StreamExt;
 use futures_util::task::AtomicWaker;
+
+use super::{executor, TaskId};
 use pc_keyboard::{layouts, DecodedKey, HandleControl, KeyCode, Keyboard, ScancodeSet1};
 
 static WAKER: AtomicWaker = AtomicWaker::new();
 static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
 
+// scancode set 1 codes the interrupt handler looks at before anything is decoded
+const SCANCODE_EXTENDED: u8 = 0xE0;
+const SCANCODE_CTRL_DOWN: u8 = 0x1D;
+const SCANCODE_CTRL_UP: u8 = 0x9D;
+const SCANCODE_C: u8 = 0x2E;
+const SCANCODE_Z: u8 = 0x2C;
+
+static CTRL_HELD: AtomicBool = AtomicBool::new(false);
+static EXTENDED: AtomicBool = AtomicBool::new(false);
+/// the signal waiting to be picked up plus one, zero if there is none
+static PENDING_SIGNAL: AtomicU8 = AtomicU8::new(0);
+static SIGNAL_WAKER: AtomicWaker = AtomicWaker::new();
+
+/// the task that keyboard input goes to, NO_FOREGROUND lets any task read it
+static FOREGROUND: AtomicU64 = AtomicU64::new(NO_FOREGROUND);
+const NO_FOREGROUND: u64 = u64::MAX;
+/// background tasks waiting for their turn at the keyboard
+static FOREGROUND_WAITERS: Mutex<Vec<Waker>> = Mutex::new(Vec::new());
+
 lazy_static! {
     pub static ref KEYBOARD: Mutex<KeyboardHandler> = Mutex::new(KeyboardHandler::new());
 }
@@ -55,6 +74,8 @@ pub enum KeyStroke {
     Enter,
     Escape,
     Del,
+    Home,
+    End,
 }
 
 impl KeyStroke {
@@ -76,11 +97,56 @@ impl KeyStroke {
             KeyCode::Enter => KeyStroke::Enter,
             KeyCode::Escape => KeyStroke::Escape,
             KeyCode::Delete => KeyStroke::Del,
+            KeyCode::Home => KeyStroke::Home,
+            KeyCode::End => KeyStroke::End,
             _ => KeyStroke::None,
         }
     }
 }
 
+/// every keystroke that is not a character, in the order they are numbered across syscalls
+const SPECIAL_KEYS: [KeyStroke; 19] = [
+    KeyStroke::Ctrl,
+    KeyStroke::RCtrl,
+    KeyStroke::Alt,
+    KeyStroke::RAlt,
+    KeyStroke::Shift,
+    KeyStroke::RShift,
+    KeyStroke::Meta,
+    KeyStroke::RMeta,
+    KeyStroke::Backspace,
+    KeyStroke::Left,
+    KeyStroke::Right,
+    KeyStroke::Up,
+    KeyStroke::Down,
+    KeyStroke::None,
+    KeyStroke::Enter,
+    KeyStroke::Escape,
+    KeyStroke::Del,
+    KeyStroke::Home,
+    KeyStroke::End,
+];
+
+const SPECIAL_KEY_FLAG: u64 = 1 << 32;
+
+impl KeyStroke {
+    /// packs a keystroke into a register, characters keep their code point
+    pub fn to_u64(&self) -> u64 {
+        match self {
+            KeyStroke::Char(c) => *c as u64,
+            key => SPECIAL_KEY_FLAG | SPECIAL_KEYS.iter().position(|k| k == key).unwrap_or(0) as u64,
+        }
+    }
+
+    pub fn from_u64(value: u64) -> Option<KeyStroke> {
+        if value & SPECIAL_KEY_FLAG != 0 {
+            SPECIAL_KEYS.get((value & !SPECIAL_KEY_FLAG) as usize).copied()
+        } else {
+            char::from_u32(value as u32).map(KeyStroke::Char)
+        }
+    }
+}
+
 impl core::fmt::Display for KeyStroke {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
@@ -102,6 +168,8 @@ impl core::fmt::Display for KeyStroke {
             KeyStroke::Escape => write!(f, "ESCAPE"),
             KeyStroke::None => write!(f, "NONE"),
             KeyStroke::Del => write!(f, "DEL"),
+            KeyStroke::Home => write!(f, "HOME"),
+            KeyStroke::End => write!(f, "END"),
         }
     }
 }
@@ -110,15 +178,12 @@ impl KeyboardHandler {
     pub fn new() -> KeyboardHandler {
         KeyboardHandler {
             scancodes: ScanCodeStream::new(),
-            keyboard: Keyboard::new(layouts::Uk105Key, ScancodeSet1, HandleControl::Ignore),
-        }
-    }
-
-    pub async fn get_keystroke_inner(&mut self) -> Option<KeyStroke> {
-        loop {
-            if let Some(scancode) = self.scancodes.next().await {
-                return self.process_keystroke(scancode);
-            }
+            // ctrl+letter comes through as a control character, e.g. ctrl+r is '\x12'
+            keyboard: Keyboard::new(
+                layouts::Uk105Key,
+                ScancodeSet1,
+                HandleControl::MapLettersToUnicode,
+            ),
         }
     }
 
@@ -126,17 +191,7 @@ impl KeyboardHandler {
         if let Ok(Some(key_event)) = self.keyboard.add_byte(scancode) {
             if let Some(key) = self.keyboard.process_keyevent(key_event) {
                 match key {
-                    DecodedKey::Unicode(character) => {
-                        if character == b'\x08' as char {
-                            // checks if the character is a backspace
-                            interrupts::without_interrupts(|| {
-                                RENDERER.lock().backspace(); // runs the backspace function of the vga buffer to remove the last character
-                            });
-                            return Some(KeyStroke::Char(character));
-                        } else {
-                            return Some(KeyStroke::Char(character));
-                        }
-                    }
+                    DecodedKey::Unicode(character) => return Some(KeyStroke::Char(character)),
                     DecodedKey::RawKey(key) => match KeyStroke::from_keycode(key) {
                         KeyStroke::None => (),
                         key => return Some(key),
@@ -147,16 +202,6 @@ impl KeyboardHandler {
         None
     }
 
-    pub async fn get_keystroke(&mut self) -> KeyStroke {
-        loop {
-            if let Some(c) = self.scancodes.next().await {
-                if let Some(key) = self.process_keystroke(c) {
-                    return key;
-                }
-            }
-        }
-    }
-
     pub fn try_keystroke(&mut self) -> Option<KeyStroke> {
         if let Some(scancode) = self.scancodes.try_next() {
             self.process_keystroke(scancode)
@@ -180,35 +225,15 @@ impl KeyboardHandler {
             None
         }
     }
-
-    pub async fn get_string(&mut self) -> String {
-        let mut val = String::new();
-        loop {
-            let character = match self.get_keystroke_inner().await {
-                Some(c) => c,
-                None => {
-                    continue;
-                }
-            };
-
-            if let KeyStroke::Char(c) = character {
-                if c == '\x08' {
-                    val.pop();
-                    continue;
-                }
-
-                print!("{}", c);
-                val.push(c);
-
-                if c == '\n' {
-                    return val;
-                }
-            }
-        }
-    }
 }
 
 pub(crate) fn add_scancode(scancode: u8) {
+    // ctrl+c and ctrl+z are taken out here so they work whatever the foreground app is doing
+    if let Some(signal) = control_signal(scancode) {
+        PENDING_SIGNAL.store(signal as u8 + 1, Ordering::Release);
+        SIGNAL_WAKER.wake();
+        return;
+    }
     if let Ok(queue) = SCANCODE_QUEUE.try_get() {
         if let Err(_) = queue.push(scancode) {
             println!("WARNING: queue is full - ignoring input");
@@ -220,6 +245,101 @@ pub(crate) fn add_scancode(scancode: u8) {
     }
 }
 
+/// sent from the keyboard to the foreground job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Signal {
+    /// ctrl+c
+    Interrupt,
+    /// ctrl+z
+    Suspend,
+}
+
+/// tracks ctrl from the raw scancodes, the decoder only sees keys once someone reads them
+fn control_signal(scancode: u8) -> Option<Signal> {
+    // the second byte of an extended key can share a code with an ordinary one
+    let extended = EXTENDED.swap(scancode == SCANCODE_EXTENDED, Ordering::Relaxed);
+    match scancode {
+        SCANCODE_CTRL_DOWN => CTRL_HELD.store(true, Ordering::Relaxed),
+        SCANCODE_CTRL_UP => CTRL_HELD.store(false, Ordering::Relaxed),
+        SCANCODE_C if !extended && CTRL_HELD.load(Ordering::Relaxed) => {
+            return Some(Signal::Interrupt)
+        }
+        SCANCODE_Z if !extended && CTRL_HELD.load(Ordering::Relaxed) => {
+            return Some(Signal::Suspend)
+        }
+        _ => {}
+    }
+    None
+}
+
+/// takes the last ctrl+c or ctrl+z if there has been one since this was last called
+pub fn take_signal() -> Option<Signal> {
+    match PENDING_SIGNAL.swap(0, Ordering::AcqRel) {
+        0 => None,
+        1 => Some(Signal::Interrupt),
+        _ => Some(Signal::Suspend),
+    }
+}
+
+/// completes at the next ctrl+c or ctrl+z, only one task can wait for signals at a time
+pub fn signal() -> impl Future<Output = Signal> + Unpin {
+    futures_util::future::poll_fn(|cx| {
+        if let Some(signal) = take_signal() {
+            return Poll::Ready(signal);
+        }
+        SIGNAL_WAKER.register(cx.waker());
+        match take_signal() {
+            Some(signal) => Poll::Ready(signal),
+            None => Poll::Pending,
+        }
+    })
+}
+
+/// completes once there is a scancode waiting, without holding the keyboard lock meanwhile
+pub fn scancode_ready() -> impl Future<Output = ()> {
+    futures_util::future::poll_fn(|cx| {
+        let queue = SCANCODE_QUEUE.try_get().expect("not initialised");
+        if !queue.is_empty() {
+            return Poll::Ready(());
+        }
+        WAKER.register(cx.waker());
+        match queue.is_empty() {
+            true => Poll::Pending,
+            false => Poll::Ready(()),
+        }
+    })
+}
+
+/// gives the keyboard to one task, None lets every task read it
+pub fn set_foreground(task: Option<TaskId>) {
+    let id = task.map_or(NO_FOREGROUND, |task| task.as_u64());
+    FOREGROUND.store(id, Ordering::Release);
+    for waker in FOREGROUND_WAITERS.lock().drain(..) {
+        waker.wake();
+    }
+}
+
+/// whether the running task is allowed to read the keyboard
+pub fn is_foreground() -> bool {
+    match FOREGROUND.load(Ordering::Acquire) {
+        NO_FOREGROUND => true,
+        id => executor::current().map(|task| task.as_u64()) == Some(id),
+    }
+}
+
+/// completes once the running task has the keyboard, background jobs wait here until they are
+/// brought to the foreground
+pub fn wait_for_foreground() -> impl Future<Output = ()> {
+    futures_util::future::poll_fn(|cx| {
+        if is_foreground() {
+            return Poll::Ready(());
+        }
+        FOREGROUND_WAITERS.lock().push(cx.waker().clone());
+        Poll::Pending
+    })
+}
+
 pub struct ScanCodeStream {
     _private: (),
 }
@@ -1,34 +1,162 @@
-use alloc::boxed::Box;
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
 use core::task::{Context, Poll};
-use core::{future::Future, pin::Pin};
+use core::{fmt, future::Future, pin::Pin};
+use lazy_static::lazy_static;
+use spin::Mutex;
 pub mod executor;
 pub mod keyboard;
-use core::sync::atomic::{AtomicU64, Ordering};
+pub mod timer;
+
+lazy_static! {
+    /// every task that has been spawned and hasn't finished, so they can be listed
+    static ref TASKS: Mutex<BTreeMap<TaskId, Arc<Header>>> = Mutex::new(BTreeMap::new());
+}
 
 pub struct Task {
     id: TaskId,
-    future: Pin<Box<dyn Future<Output = ()>>>,
+    header: Arc<Header>,
+    future: Pin<Box<dyn Future<Output = ()> + Send>>,
 }
 
 impl Task {
-    pub fn new(future: impl Future<Output = ()> + 'static) -> Self {
+    pub fn new(name: &str, future: impl Future<Output = ()> + Send + 'static) -> Self {
+        let id = TaskId::new();
+        let header = Arc::new(Header {
+            name: String::from(name),
+            state: AtomicU8::new(TaskState::Ready as u8),
+            polls: AtomicU64::new(0),
+            cancelled: AtomicBool::new(false),
+            suspended: AtomicBool::new(false),
+        });
+        TASKS.lock().insert(id, header.clone());
         Self {
-            id: TaskId::new(),
+            id,
+            header,
             future: Box::pin(future),
         }
     }
 
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
     fn poll(&mut self, context: &mut Context) -> Poll<()> {
-        self.future.as_mut().poll(context)
+        self.header.polls.fetch_add(1, Ordering::Relaxed);
+        self.header.set_state(TaskState::Running);
+        let poll = self.future.as_mut().poll(context);
+        // a task that woke itself while running is already back in the queue
+        self.header
+            .state
+            .compare_exchange(
+                TaskState::Running as u8,
+                TaskState::Waiting as u8,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            )
+            .ok();
+        poll
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct TaskId(u64);
+impl Drop for Task {
+    fn drop(&mut self) {
+        TASKS.lock().remove(&self.id);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
 
 impl TaskId {
     fn new() -> Self {
         static NEXT_ID: AtomicU64 = AtomicU64::new(0);
         TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
     }
+
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TaskState {
+    /// woken and waiting in the executor's queue
+    Ready,
+    Running,
+    /// waiting for something to wake it
+    Waiting,
+    /// suspended, it isn't polled again until it is resumed
+    Stopped,
+}
+
+impl TaskState {
+    fn from_u8(state: u8) -> TaskState {
+        match state {
+            0 => TaskState::Ready,
+            1 => TaskState::Running,
+            2 => TaskState::Waiting,
+            _ => TaskState::Stopped,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TaskState::Ready => "ready",
+            TaskState::Running => "running",
+            TaskState::Waiting => "waiting",
+            TaskState::Stopped => "stopped",
+        }
+    }
+}
+
+/// the parts of a task that are shared with its wakers and join handle
+struct Header {
+    name: String,
+    state: AtomicU8,
+    polls: AtomicU64,
+    cancelled: AtomicBool,
+    suspended: AtomicBool,
+}
+
+impl Header {
+    fn state(&self) -> TaskState {
+        TaskState::from_u8(self.state.load(Ordering::Acquire))
+    }
+
+    fn set_state(&self, state: TaskState) {
+        self.state.store(state as u8, Ordering::Release);
+    }
+}
+
+/// a snapshot of a task for listings
+#[derive(Debug, Clone)]
+pub struct TaskInfo {
+    pub id: TaskId,
+    pub name: String,
+    pub state: TaskState,
+    pub polls: u64,
+    pub cancelled: bool,
+}
+
+/// every task that hasn't finished yet
+pub fn tasks() -> Vec<TaskInfo> {
+    TASKS
+        .lock()
+        .iter()
+        .map(|(id, header)| TaskInfo {
+            id: *id,
+            name: header.name.clone(),
+            state: header.state(),
+            polls: header.polls.load(Ordering::Relaxed),
+            cancelled: header.cancelled.load(Ordering::Relaxed),
+        })
+        .collect()
 }
@@ -0,0 +1,164 @@
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+use crate::system::kernel::clock;
+
+/// one slot per tick, a deadline further out than this goes round the wheel more than once
+const WHEEL_SLOTS: u64 = 64;
+
+// locked by the timer interrupt, so only ever taken with interrupts disabled
+static WHEEL: Mutex<Wheel> = Mutex::new(Wheel {
+    slots: [const { Vec::new() }; WHEEL_SLOTS as usize],
+    next_id: 0,
+    current: 0,
+});
+
+struct Entry {
+    id: u64,
+    tick: u64,
+    waker: Waker,
+}
+
+/// wakers waiting for a tick, hashed into slots by tick number
+struct Wheel {
+    slots: [Vec<Entry>; WHEEL_SLOTS as usize],
+    next_id: u64,
+    /// the last tick that has been processed
+    current: u64,
+}
+
+/// where a registered waker is, so it can be taken out again
+#[derive(Debug, Clone, Copy)]
+struct TimerHandle {
+    id: u64,
+    slot: usize,
+}
+
+impl Wheel {
+    fn insert(&mut self, tick: u64, waker: Waker) -> TimerHandle {
+        // a tick that has already been processed would never come round again
+        let tick = tick.max(self.current + 1);
+        let slot = (tick % WHEEL_SLOTS) as usize;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.slots[slot].push(Entry { id, tick, waker });
+        TimerHandle { id, slot }
+    }
+
+    fn remove(&mut self, handle: TimerHandle) {
+        let slot = &mut self.slots[handle.slot];
+        if let Some(i) = slot.iter().position(|e| e.id == handle.id) {
+            slot.swap_remove(i);
+        }
+    }
+
+    fn advance(&mut self, now: u64) {
+        // normally one tick, more if interrupts were held off for a while
+        while self.current < now {
+            self.current += 1;
+            let current = self.current;
+            let slot = &mut self.slots[(current % WHEEL_SLOTS) as usize];
+            let mut i = 0;
+            while i < slot.len() {
+                if slot[i].tick <= current {
+                    slot.swap_remove(i).waker.wake();
+                } else {
+                    i += 1;
+                }
+            }
+        }
+    }
+}
+
+/// wakes every timer that is due, called by the timer interrupt
+pub(crate) fn wake_expired() {
+    WHEEL.lock().advance(clock::ticks());
+}
+
+/// completes once the monotonic clock reaches its deadline, without holding up the executor in
+/// the meantime
+pub struct Sleep {
+    /// nanoseconds since boot
+    deadline: u64,
+    handle: Option<TimerHandle>,
+}
+
+impl Sleep {
+    pub fn until(deadline: u64) -> Sleep {
+        Sleep {
+            deadline,
+            handle: None,
+        }
+    }
+
+    pub fn deadline(&self) -> u64 {
+        self.deadline
+    }
+
+    /// moves the deadline, the next poll registers it again
+    pub fn reset(&mut self, deadline: u64) {
+        self.cancel();
+        self.deadline = deadline;
+    }
+
+    fn cancel(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            interrupts::without_interrupts(|| WHEEL.lock().remove(handle));
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        // the wheel has either fired or this is a spurious poll, both ways the old entry goes
+        self.cancel();
+        if clock::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        let tick = clock::tick_at(self.deadline);
+        let waker = cx.waker().clone();
+        self.handle = Some(interrupts::without_interrupts(|| {
+            WHEEL.lock().insert(tick, waker)
+        }));
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+#[test_case]
+fn sleep_wakes_its_task() {
+    use alloc::sync::Arc;
+    use alloc::task::Wake;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    struct Flag(AtomicBool);
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    let flag = Arc::new(Flag(AtomicBool::new(false)));
+    let waker = Waker::from(flag.clone());
+    let mut cx = Context::from_waker(&waker);
+    let deadline = clock::now() + 3 * clock::TICK_NANOS;
+    let mut sleep = Sleep::until(deadline);
+
+    while Pin::new(&mut sleep).poll(&mut cx).is_pending() {
+        while !flag.0.swap(false, Ordering::SeqCst) {
+            x86_64::instructions::hlt();
+        }
+    }
+    assert!(clock::now() >= deadline);
+}
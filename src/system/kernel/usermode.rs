@@ -0,0 +1,152 @@
+use alloc::vec::Vec;
+use core::arch::asm;
+use x86_64::structures::paging::{Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+use super::gdt;
+use super::memory::{AddressSpace, USER_END, USER_START};
+use super::process::{self, Pid, ProcessError};
+
+/// 64 KiB of stack for ring 3 code
+pub const USER_STACK_PAGES: u64 = 16;
+/// the stack sits at the very top of user space with an unmapped page left above it
+pub const USER_STACK_TOP: u64 = USER_END - Page::<Size4KiB>::SIZE;
+
+/// rflags ring 3 code starts with, only the interrupt flag is set
+const USER_RFLAGS: u64 = 0x200;
+
+/// drops the current thread into ring 3 at `entry` with `args` in rdi, rsi and rdx. interrupts
+/// and syscalls come back in on the thread's own kernel stack, which the scheduler loads into
+/// the TSS.
+pub unsafe fn enter_user_mode(entry: VirtAddr, stack_top: VirtAddr, args: [u64; 3]) -> ! {
+    let (code, data) = gdt::user_selectors();
+    asm!(
+        "mov ds, {data:x}",
+        "mov es, {data:x}",
+        "push {data}",
+        "push {stack}",
+        "push {rflags}",
+        "push {code}",
+        "push {entry}",
+        "iretq",
+        data = in(reg) data.0 as u64,
+        stack = in(reg) stack_top.as_u64(),
+        rflags = in(reg) USER_RFLAGS,
+        code = in(reg) code.0 as u64,
+        entry = in(reg) entry.as_u64(),
+        in("rdi") args[0],
+        in("rsi") args[1],
+        in("rdx") args[2],
+        options(noreturn),
+    );
+}
+
+/// maps the user stack just below `USER_STACK_TOP` into `space`
+pub fn map_stack(space: &mut AddressSpace) -> Result<(), ProcessError> {
+    let bottom = Page::<Size4KiB>::containing_address(VirtAddr::new(
+        USER_STACK_TOP - USER_STACK_PAGES * Page::<Size4KiB>::SIZE,
+    ));
+    for page in Page::range(bottom, bottom + USER_STACK_PAGES) {
+        space
+            .map_user(page, PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE)
+            .map_err(|_| ProcessError::OutOfMemory)?;
+    }
+    Ok(())
+}
+
+/// copies a flat binary to the start of a new address space and runs it as a process,
+/// starting at its first byte
+pub fn spawn(name: &str, image: &[u8]) -> Result<Pid, ProcessError> {
+    let mut space = AddressSpace::new().ok_or(ProcessError::OutOfMemory)?;
+    let pages = (image.len() as u64 + Page::<Size4KiB>::SIZE - 1) / Page::<Size4KiB>::SIZE;
+    let start = Page::<Size4KiB>::containing_address(VirtAddr::new(USER_START));
+    // there are no sections to go by, so the whole image is writable and executable
+    for page in Page::range(start, start + pages.max(1)) {
+        space
+            .map_user(page, PageTableFlags::WRITABLE)
+            .map_err(|_| ProcessError::OutOfMemory)?;
+    }
+    space
+        .write(VirtAddr::new(USER_START), image)
+        .map_err(|_| ProcessError::OutOfMemory)?;
+    map_stack(&mut space)?;
+
+    process::spawn(
+        name,
+        space,
+        VirtAddr::new(USER_START),
+        VirtAddr::new(USER_STACK_TOP),
+        [0; 3],
+        Vec::new(),
+    )
+}
+
+// mov edi, 7; mov eax, 3 (exit); int 0x80; jmp $
+#[cfg(test)]
+const EXIT_7: [u8; 14] = [
+    0xBF, 0x07, 0x00, 0x00, 0x00, 0xB8, 0x03, 0x00, 0x00, 0x00, 0xCD, 0x80, 0xEB, 0xFE,
+];
+
+#[test_case]
+fn user_code_exits_through_syscall() {
+    let pid = spawn("user test", &EXIT_7).unwrap();
+    assert_eq!(process::wait(pid), Ok(7));
+    assert_eq!(process::wait(pid), Err(ProcessError::NotFound));
+}
+
+#[test_case]
+fn detached_processes_are_freed_once_they_exit() {
+    let pid = spawn("detached test", &EXIT_7).unwrap();
+    process::detach(pid);
+    // listing reaps it once its thread has had a chance to run and exit
+    let freed = (0..1000).any(|_| {
+        super::multitasking::yield_now();
+        !process::list().iter().any(|p| p.pid == pid.as_u64())
+    });
+    assert!(freed);
+}
+
+#[test_case]
+fn killed_processes_stop_running_user_code() {
+    // jmp $
+    let pid = spawn("kill test", &[0xEB, 0xFE]).unwrap();
+    process::kill(pid).unwrap();
+    assert_eq!(process::wait(pid), Ok(process::KILLED));
+}
+
+#[test_case]
+fn waiting_on_a_killed_process_is_only_woken_by_it() {
+    use alloc::{sync::Arc, task::Wake};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use core::task::Waker;
+    use x86_64::instructions::interrupts;
+
+    struct Wakes(AtomicUsize);
+    impl Wake for Wakes {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    let wakes = Arc::new(Wakes(AtomicUsize::new(0)));
+    let waker = Waker::from(wakes.clone());
+
+    // jmp $
+    let pid = spawn("kill wait test", &[0xEB, 0xFE]).unwrap();
+    process::kill(pid).unwrap();
+    let code = loop {
+        // nothing else runs in between, so any wake would have come from try_wait itself
+        let (before, polled) = interrupts::without_interrupts(|| {
+            let before = wakes.0.load(Ordering::Relaxed);
+            let polled = process::try_wait(pid, &waker).unwrap();
+            assert!(polled.is_some() || wakes.0.load(Ordering::Relaxed) == before);
+            (before, polled)
+        });
+        if let Some(code) = polled {
+            break code;
+        }
+        while wakes.0.load(Ordering::Relaxed) == before {
+            super::multitasking::yield_now();
+        }
+    };
+    assert_eq!(code, process::KILLED);
+}
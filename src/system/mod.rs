@@ -1,4 +1,4 @@
-use crate::system::kernel::memory::BootInfoFrameAllocator;
+use crate::system::kernel::memory::BitmapFrameAllocator;
 use crate::system::kernel::{allocator, memory};
 use bootloader::BootInfo;
 use x86_64::VirtAddr;
@@ -8,15 +8,32 @@ pub mod std;
 
 pub fn init(boot_info: &'static BootInfo) {
     kernel::gdt::init();
-    kernel::interrupts::init_idt();
     unsafe { kernel::interrupts::PICS.lock().initialize() };
+    kernel::interrupts::init_idt();
+    kernel::clock::init();
     x86_64::instructions::interrupts::enable();
 
     kernel::sysinit::init().unwrap();
 
     let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
     let mut mapper = unsafe { memory::init(physical_memory_offset) };
-    let mut frame_allocator = unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) };
+    let mut frame_allocator =
+        unsafe { BitmapFrameAllocator::init(&boot_info.memory_map, physical_memory_offset) };
 
     allocator::init_heap(&mut mapper, &mut frame_allocator).expect("heap initialisation failed");
+    memory::install(physical_memory_offset, mapper, frame_allocator);
+    if let Err(e) = kernel::acpi::init() {
+        crate::println!("{}", e);
+    }
+    kernel::interrupts::enable_apic();
+    kernel::clock::calibrate();
+    if kernel::rtc::init().is_none() {
+        crate::println!("could not read the real time clock, the date will be wrong");
+    }
+    kernel::multitasking::scheduler::init();
+
+    kernel::fs::init().expect("failed to mount the root filesystem");
+    kernel::block::init();
+    kernel::pci::init();
+    kernel::fs::mount_disks();
 }
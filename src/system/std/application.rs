@@ -1,10 +1,24 @@
-use alloc::{boxed::Box, string::String, vec::Vec};
+use alloc::{boxed::Box, format, string::String, vec::Vec};
 use async_trait::async_trait;
 
 #[async_trait]
 pub trait Application {
+    /// what it is typed as at the prompt
+    const NAME: &'static str;
+    /// other names it answers to
+    const ALIASES: &'static [&'static str] = &[];
+    /// one line for `help`
+    const DESCRIPTION: &'static str;
+    /// how to call it, for `help <name>`
+    const USAGE: &'static str;
+
     fn new() -> Self;
 
+    /// the error for being called the wrong way, which shows USAGE
+    fn usage() -> Error {
+        Error::CommandFailed(format!("usage: {}", Self::USAGE))
+    }
+
     async fn run(&mut self, _: Vec<String>) -> Result<(), Error> {
         Ok(())
     }
@@ -16,6 +30,28 @@ pub enum Error {
     CommandFailed(String),
     ApplicationError(String),
     EmptyCommand,
+    /// failed without anything more to say than its exit status
+    Exit(i32),
+}
+
+impl Error {
+    /// the exit status a command that failed like this gives, like a unix shell would
+    pub fn status(&self) -> i32 {
+        match self {
+            Error::UnknownCommand(_) => 127,
+            Error::EmptyCommand => 2,
+            Error::Exit(code) => *code,
+            _ => 1,
+        }
+    }
+}
+
+/// 0 if the command succeeded, otherwise the status of its error
+pub fn status(result: &Result<(), Error>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(e) => e.status(),
+    }
 }
 
 pub enum Exit {
@@ -0,0 +1,42 @@
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use spin::Mutex;
+
+// there is only one shell, so one set of environment variables is shared by everything
+static VARS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// the value of an environment variable
+pub fn var(name: &str) -> Option<String> {
+    VARS.lock().get(name).cloned()
+}
+
+pub fn set_var(name: &str, value: &str) {
+    VARS.lock().insert(String::from(name), String::from(value));
+}
+
+pub fn remove_var(name: &str) {
+    VARS.lock().remove(name);
+}
+
+/// every variable and its value, sorted by name
+pub fn vars() -> Vec<(String, String)> {
+    VARS.lock()
+        .iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+/// every variable as `NAME=value`, the way a process gets them
+pub fn vars_os() -> Vec<String> {
+    vars()
+        .into_iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect()
+}
+
+/// whether `name` can be used as a variable name, letters, digits and `_` not starting with a
+/// digit
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -1,5 +1,265 @@
-use crate::system::kernel::ahci::init_ahci;
+use crate::std::application::Error;
+use crate::system::kernel::fs::{VNode, VFS};
+use crate::system::kernel::{self, block};
+use alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
 
-pub fn check_ahci() {
+pub use crate::system::kernel::fs::{DirEntry, FileType, FsError, Metadata};
 
-}
\ No newline at end of file
+/// an open file, reads and writes happen at the current offset which moves forward as data
+/// is read or written. files do not need to be closed, dropping the handle is enough.
+pub struct File {
+    node: VNode,
+    offset: usize,
+    append: bool,
+}
+
+pub enum SeekFrom {
+    Start(usize),
+    End(i64),
+    Current(i64),
+}
+
+impl File {
+    /// opens an existing file for reading and writing
+    pub fn open(path: &str) -> Result<File, FsError> {
+        let node = VFS.lock().resolve(path)?;
+        if node.metadata()?.is_dir() {
+            return Err(FsError::IsADirectory);
+        }
+        Ok(File {
+            node,
+            offset: 0,
+            append: false,
+        })
+    }
+
+    /// opens a file for writing, creating it if it does not exist and truncating it if it does
+    pub fn create(path: &str) -> Result<File, FsError> {
+        let file = File::open_or_create(path)?;
+        file.node.fs.truncate(file.node.inode, 0)?;
+        Ok(file)
+    }
+
+    /// opens a file so that every write goes to the end of it, creating it if it does not exist
+    pub fn append(path: &str) -> Result<File, FsError> {
+        let mut file = File::open_or_create(path)?;
+        file.append = true;
+        Ok(file)
+    }
+
+    fn open_or_create(path: &str) -> Result<File, FsError> {
+        match File::open(path) {
+            Err(FsError::NotFound) => {
+                let (parent, name) = VFS.lock().resolve_parent(path)?;
+                let inode = parent.fs.create(parent.inode, &name, FileType::File)?;
+                Ok(File {
+                    node: VNode {
+                        fs: parent.fs,
+                        inode,
+                    },
+                    offset: 0,
+                    append: false,
+                })
+            }
+            res => res,
+        }
+    }
+
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        let n = self.node.fs.read(self.node.inode, self.offset, buf)?;
+        self.offset += n;
+        Ok(n)
+    }
+
+    pub fn read_to_end(&mut self) -> Result<Vec<u8>, FsError> {
+        let size = self.metadata()?.size;
+        let mut buf = vec![0u8; size.saturating_sub(self.offset)];
+        let mut read = 0;
+        while read < buf.len() {
+            match self.read(&mut buf[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        buf.truncate(read);
+        Ok(buf)
+    }
+
+    pub fn read_to_string(&mut self) -> Result<String, FsError> {
+        let bytes = self.read_to_end()?;
+        String::from_utf8(bytes).map_err(|_| FsError::IoError)
+    }
+
+    pub fn write(&mut self, buf: &[u8]) -> Result<usize, FsError> {
+        if self.append {
+            self.offset = self.metadata()?.size;
+        }
+        let n = self.node.fs.write(self.node.inode, self.offset, buf)?;
+        self.offset += n;
+        Ok(n)
+    }
+
+    /// writes the whole of `buf`, a write that stops short is out of space
+    pub fn write_all(&mut self, mut buf: &[u8]) -> Result<(), FsError> {
+        while !buf.is_empty() {
+            match self.write(buf)? {
+                0 => return Err(FsError::NoSpace),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+
+    /// moves the offset of the file, returning the new offset from the start of the file
+    pub fn seek(&mut self, pos: SeekFrom) -> Result<usize, FsError> {
+        let new = match pos {
+            SeekFrom::Start(x) => x as i64,
+            SeekFrom::End(x) => self.metadata()?.size as i64 + x,
+            SeekFrom::Current(x) => self.offset as i64 + x,
+        };
+        if new < 0 {
+            return Err(FsError::InvalidArgument);
+        }
+        self.offset = new as usize;
+        Ok(self.offset)
+    }
+
+    pub fn set_len(&mut self, size: usize) -> Result<(), FsError> {
+        self.node.fs.truncate(self.node.inode, size)
+    }
+
+    pub fn metadata(&self) -> Result<Metadata, FsError> {
+        self.node.metadata()
+    }
+
+    pub fn sync(&self) -> Result<(), FsError> {
+        self.node.fs.sync()
+    }
+}
+
+/// reads the whole file at `path` into a string
+pub fn read_to_string(path: &str) -> Result<String, FsError> {
+    File::open(path)?.read_to_string()
+}
+
+/// reads the whole file at `path` into memory
+pub fn read(path: &str) -> Result<Vec<u8>, FsError> {
+    File::open(path)?.read_to_end()
+}
+
+/// replaces the contents of the file at `path`, creating it if needed
+pub fn write(path: &str, contents: &[u8]) -> Result<(), FsError> {
+    File::create(path)?.write_all(contents)
+}
+
+pub fn metadata(path: &str) -> Result<Metadata, FsError> {
+    VFS.lock().resolve(path)?.metadata()
+}
+
+pub fn exists(path: &str) -> bool {
+    metadata(path).is_ok()
+}
+
+pub fn read_dir(path: &str) -> Result<Vec<DirEntry>, FsError> {
+    let node = VFS.lock().resolve(path)?;
+    node.fs.readdir(node.inode)
+}
+
+pub fn create_dir(path: &str) -> Result<(), FsError> {
+    let (parent, name) = VFS.lock().resolve_parent(path)?;
+    parent
+        .fs
+        .create(parent.inode, &name, FileType::Directory)
+        .map(|_| ())
+}
+
+/// creates a directory along with any missing parent directories
+pub fn create_dir_all(path: &str) -> Result<(), FsError> {
+    let path = VFS.lock().absolute(path)?;
+    let mut current = String::new();
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        current.push('/');
+        current.push_str(component);
+        match create_dir(&current) {
+            Ok(()) => {}
+            Err(FsError::AlreadyExists) if metadata(&current)?.is_dir() => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// removes a file or an empty directory
+pub fn remove(path: &str) -> Result<(), FsError> {
+    let (parent, name) = VFS.lock().resolve_parent(path)?;
+    parent.fs.remove(parent.inode, &name)
+}
+
+pub fn rename(from: &str, to: &str) -> Result<(), FsError> {
+    let (from, to) = {
+        let vfs = VFS.lock();
+        (vfs.resolve_parent(from)?, vfs.resolve_parent(to)?)
+    };
+    if !alloc::sync::Arc::ptr_eq(&from.0.fs, &to.0.fs) {
+        return Err(FsError::CrossDevice);
+    }
+    from.0.fs.rename(from.0.inode, &from.1, to.0.inode, &to.1)
+}
+
+pub fn current_dir() -> String {
+    VFS.lock().cwd()
+}
+
+pub fn set_current_dir(path: &str) -> Result<(), FsError> {
+    VFS.lock().set_cwd(path)
+}
+
+/// turns a path relative to the current directory into an absolute one
+pub fn canonicalize(path: &str) -> Result<String, FsError> {
+    VFS.lock().absolute(path)
+}
+
+/// returns (mount path, filesystem name) for every mounted filesystem
+pub fn mounts() -> Vec<(String, String)> {
+    VFS.lock().mounts()
+}
+
+/// mounts the filesystem on a block device such as "sda" onto an existing directory
+pub fn mount(device: &str, path: &str) -> Result<(), FsError> {
+    let device = block::get(device).ok_or(FsError::NotFound)?;
+    kernel::fs::mount_device(path, device)
+}
+
+/// detaches the filesystem mounted at `path`, flushing it to its device first
+pub fn unmount(path: &str) -> Result<(), FsError> {
+    VFS.lock().unmount(path).map(|_| ())
+}
+
+/// writes every cached change out to disk, first from the filesystems and then from the block caches
+pub fn sync() -> Result<(), FsError> {
+    VFS.lock().sync()?;
+    Ok(block::sync_all()?)
+}
+
+impl From<FsError> for Error {
+    fn from(e: FsError) -> Self {
+        Error::CommandFailed(e.to_string())
+    }
+}
+
+#[test_case]
+fn files_seek_within_what_was_written() {
+    write("/fs_seek_test", b"hello world").unwrap();
+    let mut file = File::open("/fs_seek_test").unwrap();
+    assert_eq!(
+        file.seek(SeekFrom::Current(-1)),
+        Err(FsError::InvalidArgument)
+    );
+    assert_eq!(file.seek(SeekFrom::End(-5)), Ok(6));
+    assert_eq!(file.read_to_string().unwrap(), "world");
+    remove("/fs_seek_test").unwrap();
+}
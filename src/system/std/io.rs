@@ -1,43 +1,205 @@
 use crate::system::kernel::{
-    render::{self, RenderError, RENDERER},
+    render::{self, with_renderer, RenderError},
     serial::serial_reply,
-    tasks::keyboard::KEYBOARD,
+    tasks::keyboard::{self, KEYBOARD},
 };
 
 pub use crate::system::kernel::{
     render::{Color, ColorCode},
     serial::_serial_print,
-    tasks::keyboard::KeyStroke,
+    tasks::keyboard::{set_foreground, KeyStroke, Signal},
 };
 
+pub use super::pipe::{pipe, PipeReader, PipeWriter};
 pub use crate::{print, println, serial_print, serial_println};
-use alloc::string::String;
+
+use crate::system::kernel::memory::ThreadId;
+use crate::system::kernel::multitasking;
+use crate::system::kernel::tasks::{executor, TaskId};
+use alloc::{string::String, sync::Arc};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_util::future::poll_fn;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// where a command reads from and prints to, None is the terminal
+#[derive(Default)]
+pub struct Stdio {
+    pub stdin: Option<PipeReader>,
+    pub stdout: Option<PipeWriter>,
+}
+
+struct Redirect {
+    stdin: Option<Arc<Mutex<PipeReader>>>,
+    stdout: Option<PipeWriter>,
+}
+
+/// whatever is polling a redirected future, the thread and the task on it
+type Owner = (Option<ThreadId>, Option<TaskId>);
+
+fn owner() -> Owner {
+    (multitasking::current(), executor::current())
+}
+
+// the redirect of whatever future is being polled and who is polling it, so prints from other
+// threads and tasks still reach the screen. print! can run in an interrupt handler so this is
+// only ever locked with interrupts disabled.
+static REDIRECT: Mutex<Option<(Owner, Arc<Redirect>)>> = Mutex::new(None);
+
+fn current_redirect() -> Option<Arc<Redirect>> {
+    // interrupt and exception handlers run with interrupts off, whatever they print is for the
+    // screen and not for the command they happened to interrupt
+    if !interrupts::are_enabled() {
+        return None;
+    }
+    interrupts::without_interrupts(|| {
+        let redirect = REDIRECT.lock();
+        let (polling, redirect) = redirect.as_ref()?;
+        (*polling == owner()).then(|| redirect.clone())
+    })
+}
+
+/// runs `future` with its input and output going to the pipes in `stdio`, anything left as None
+/// stays wherever it goes for the caller. the pipes are dropped, and so closed, when the future
+/// finishes.
+pub fn with_stdio<F: Future>(stdio: Stdio, future: F) -> WithStdio<F> {
+    let outer = current_redirect();
+    WithStdio {
+        redirect: Arc::new(Redirect {
+            stdin: match stdio.stdin {
+                Some(stdin) => Some(Arc::new(Mutex::new(stdin))),
+                None => outer.as_ref().and_then(|r| r.stdin.clone()),
+            },
+            stdout: stdio
+                .stdout
+                .or_else(|| outer.as_ref().and_then(|r| r.stdout.clone())),
+        }),
+        future: alloc::boxed::Box::pin(future),
+    }
+}
+
+pub struct WithStdio<F> {
+    redirect: Arc<Redirect>,
+    future: Pin<alloc::boxed::Box<F>>,
+}
+
+impl<F: Future> Future for WithStdio<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        let this = &mut *self;
+        // a command that has filled the pipe it prints to waits for the reader to catch up
+        if let Some(stdout) = &this.redirect.stdout {
+            if stdout.poll_ready(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+        let own = Some((owner(), this.redirect.clone()));
+        let outer =
+            interrupts::without_interrupts(|| core::mem::replace(&mut *REDIRECT.lock(), own));
+        let poll = this.future.as_mut().poll(cx);
+        interrupts::without_interrupts(|| *REDIRECT.lock() = outer);
+        poll
+    }
+}
 
 pub struct Stdin {}
 impl Stdin {
     pub const BACKSPACE: char = b'\x08' as char;
     /// waits for the user to type in a string and press enter | blocking
+    /// reads the next line instead when stdin is a pipe, an empty string means it has run out
     pub async fn readline() -> String {
-        let string = KEYBOARD.lock().get_string().await;
-        string
+        if let Some(redirect) = current_redirect().filter(|r| r.stdin.is_some()) {
+            let stdin = redirect.stdin.as_ref().unwrap();
+            return poll_fn(|cx| stdin.lock().poll_read_line(cx))
+                .await
+                .unwrap_or_default();
+        }
+        let mut string = String::new();
+        loop {
+            if let KeyStroke::Char(c) = Stdin::keystroke().await {
+                if c == Stdin::BACKSPACE {
+                    if string.pop().is_some() {
+                        Screen::backspace(1);
+                    }
+                    continue;
+                }
+                // ctrl+letter and friends, the shell prompt has its own editor that uses them
+                if c.is_control() && c != '\n' {
+                    continue;
+                }
+
+                print!("{}", c);
+                string.push(c);
+
+                if c == '\n' {
+                    return string;
+                }
+            }
+        }
     }
 
     /// waits for a keystroke | blocking
+    /// background jobs wait here until they are brought to the foreground
     pub async fn keystroke() -> KeyStroke {
-        let chr = KEYBOARD.lock().get_keystroke().await;
-        chr
+        loop {
+            keyboard::wait_for_foreground().await;
+            // the lock is never held across an await, a suspended job would keep it forever
+            if let Some(key) = KEYBOARD.lock().try_keystroke() {
+                return key;
+            }
+            keyboard::scancode_ready().await;
+        }
     }
 
     /// gets the next keystroke if any is present | non blocking
     pub fn try_keystroke() -> Option<KeyStroke> {
+        if !keyboard::is_foreground() {
+            return None;
+        }
         let chr = KEYBOARD.lock().try_keystroke();
         chr
     }
 
     pub fn last_keystroke() -> Option<KeyStroke> {
+        if !keyboard::is_foreground() {
+            return None;
+        }
         let chr = KEYBOARD.lock().last_keystroke();
         chr
     }
+
+    /// whether stdin is a pipe or a file rather than the keyboard
+    pub fn is_piped() -> bool {
+        current_redirect().map_or(false, |r| r.stdin.is_some())
+    }
+
+    /// everything left in a piped stdin, nothing when stdin is the keyboard
+    pub async fn read_to_string() -> String {
+        match current_redirect().filter(|r| r.stdin.is_some()) {
+            Some(redirect) => {
+                let stdin = redirect.stdin.as_ref().unwrap();
+                let mut bytes = alloc::vec::Vec::new();
+                while let Some(chunk) = poll_fn(|cx| stdin.lock().poll_read(cx)).await {
+                    bytes.extend(chunk);
+                }
+                String::from_utf8_lossy(&bytes).into_owned()
+            }
+            None => String::new(),
+        }
+    }
+
+    /// waits for ctrl+c or ctrl+z
+    pub fn signal() -> impl core::future::Future<Output = Signal> + Unpin {
+        keyboard::signal()
+    }
+
+    /// takes a ctrl+c or ctrl+z that nobody has picked up yet
+    pub fn take_signal() -> Option<Signal> {
+        keyboard::take_signal()
+    }
 }
 
 pub struct Serial {}
@@ -57,16 +219,16 @@ pub enum Screen {
 /// DEPRECATED - STOP USING THIS SOON
 impl Screen {
     /// mode can be set for the kernel using this method
-    // pub fn set_mode(&self) -> Result<(), RenderError> {
-    //     Ok(match self {
-    //         Screen::Terminal => RENDERER.lock().terminal_mode(),
-    //         Screen::Application => RENDERER.lock().application_mode(),
-    //     })
-    // }
+    pub fn set_mode(&self) {
+        match self {
+            Screen::Terminal => with_renderer(|r| r.terminal_mode()),
+            Screen::Application => with_renderer(|r| r.application_mode()),
+        }
+    }
 
     /// returns the current display mode
     pub fn get_mode() -> Screen {
-        match RENDERER.lock().mode_is_app() {
+        match with_renderer(|r| r.mode_is_app()) {
             true => Screen::Application,
             false => Screen::Terminal,
         }
@@ -74,14 +236,24 @@ impl Screen {
 
     /// switches between modes
     pub fn switch(&self) {
-        if RENDERER.lock().mode_is_app() == true {
-            RENDERER.lock().terminal_mode();
+        if with_renderer(|r| r.mode_is_app()) == true {
+            with_renderer(|r| r.terminal_mode());
         } else {
-            RENDERER.lock().application_mode();
+            with_renderer(|r| r.application_mode());
         }
     }
     pub fn clear() {
-        RENDERER.lock().clear();
+        with_renderer(|r| r.clear());
+    }
+
+    /// rubs out the last `n` characters printed to the terminal
+    pub fn backspace(n: usize) {
+        with_renderer(|r| r.erase(n)).ok();
+    }
+
+    /// draws the cursor `n` characters back from the end of the terminal output
+    pub fn set_cursor_offset(n: usize) {
+        with_renderer(|r| r.set_cursor_offset(n));
     }
 }
 
@@ -92,25 +264,25 @@ pub struct Display;
 
 impl Display {
     pub fn borrow() -> Display {
-        RENDERER.lock().application_mode();
+        with_renderer(|r| r.application_mode());
         Display
     }
 
     pub fn mv_cursor(&self, x: u8, y: u8) -> Result<(), RenderError> {
-        RENDERER.lock().cursor_position(x, y)
+        with_renderer(|r| r.cursor_position(x, y))
     }
 }
 
 impl Drop for Display {
     fn drop(&mut self) {
-        RENDERER.lock().terminal_mode();
+        with_renderer(|r| r.terminal_mode());
     }
 }
 
 #[macro_export]
 macro_rules! println_log {
 	() => ($crate::print_log!("/n"));
-	($($arg:tt)*) => ($crate::print_log!("{}\n", format_args!($($arg)*)));
+	($($arg:tt)*) => ($crate::std::io::_logln(format_args!($($arg)*)));
 }
 
 #[macro_export]
@@ -154,7 +326,7 @@ macro_rules! serial_println {
 
 #[doc(hidden)]
 pub fn _print(args: core::fmt::Arguments) {
-    render::write(args, (Color::White, Color::Black));
+    write(args, (Color::White, Color::Black));
 }
 
 #[doc(hidden)]
@@ -167,6 +339,23 @@ pub fn _log(args: core::fmt::Arguments) {
     render::write(args, (Color::White, Color::Black));
 }
 
+/// a log line stamped with the local time
+#[doc(hidden)]
+pub fn _logln(args: core::fmt::Arguments) {
+    let now = super::time::DateTime::now();
+    _log(format_args!(
+        "[{:02}:{:02}:{:02}] {}\n",
+        now.hour, now.minute, now.second, args
+    ));
+}
+
+/// prints to stdout, the colour is lost when that is a pipe
 pub fn write(args: core::fmt::Arguments, color: (Color, Color)) {
-    render::write(args, color);
+    match current_redirect().filter(|r| r.stdout.is_some()) {
+        Some(redirect) => {
+            let text = alloc::format!("{}", args);
+            redirect.stdout.as_ref().unwrap().write(text.as_bytes());
+        }
+        None => render::write(args, color),
+    }
 }
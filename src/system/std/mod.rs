@@ -1,10 +1,15 @@
 pub mod application;
+pub mod env;
+pub mod fs;
 pub mod io;
 pub mod os;
+pub mod pipe;
+pub mod process;
 pub mod random;
 pub mod render;
 pub mod syscall;
 pub mod tasks;
+pub mod thread;
 pub mod time;
 
 // this is where the standard library for the operating system will be defined
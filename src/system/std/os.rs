@@ -1,7 +1,21 @@
+use crate::println;
+use crate::system::kernel::ahci::AHCI_DEVICES;
+use crate::system::kernel::allocator;
+use crate::system::kernel::block;
+use crate::system::kernel::clock;
+use crate::system::kernel::memory;
+use crate::system::kernel::pci::PCI_DEVICES;
+use crate::system::kernel::power;
 use alloc::string::String;
+use alloc::vec::Vec;
 use lazy_static::lazy_static;
 use spin::Mutex;
 
+pub use crate::system::kernel::allocator::HeapStats;
+pub use crate::system::kernel::block::{BlockError, PartitionInfo, PartitionKind};
+pub use crate::system::kernel::memory::FrameStats;
+pub use crate::system::kernel::pci::{Bar, PciDevice};
+
 lazy_static! {
     pub static ref OS: Mutex<SysInfo> = Mutex::new(SysInfo {
         os: String::from("CrystalOS Alpha"),
@@ -13,3 +27,105 @@ pub struct SysInfo {
     pub os: String,
     pub version: String,
 }
+
+#[derive(Debug, Clone)]
+pub struct DiskInfo {
+    pub name: String,
+    pub port: usize,
+    pub model: String,
+    pub serial: String,
+    pub sectors: u64,
+    pub sector_size: usize,
+}
+
+/// lists the SATA drives that were brought up by the AHCI driver at boot
+pub fn check_ahci() -> Vec<DiskInfo> {
+    AHCI_DEVICES
+        .lock()
+        .iter()
+        .map(|disk| {
+            let disk = disk.lock();
+            DiskInfo {
+                name: String::from(disk.name()),
+                port: disk.port(),
+                model: String::from(disk.model()),
+                serial: String::from(disk.serial()),
+                sectors: disk.sectors(),
+                sector_size: disk.sector_size(),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockDeviceInfo {
+    pub name: String,
+    pub size: u64,
+    pub block_size: usize,
+    pub partition: Option<PartitionInfo>, // None for a whole disk
+}
+
+/// every disk and partition that can be read with `read_disk`, each disk is followed by its partitions
+pub fn block_devices() -> Vec<BlockDeviceInfo> {
+    block::devices()
+        .into_iter()
+        .map(|d| BlockDeviceInfo {
+            size: d.device.size(),
+            block_size: d.device.block_size(),
+            name: d.name,
+            partition: d.partition,
+        })
+        .collect()
+}
+
+/// reads whole blocks from a registered block device such as "sda", going through its cache
+pub fn read_disk(name: &str, lba: u64, buf: &mut [u8]) -> Result<(), BlockError> {
+    block::get(name)
+        .ok_or(BlockError::OutOfRange)?
+        .read_blocks(lba, buf)
+}
+
+/// writes whole blocks to a registered block device and flushes them out to the disk
+pub fn write_disk(name: &str, lba: u64, buf: &[u8]) -> Result<(), BlockError> {
+    let disk = block::get(name).ok_or(BlockError::OutOfRange)?;
+    disk.write_blocks(lba, buf)?;
+    disk.flush()
+}
+
+/// every device found on the PCI bus at boot, in bus/device/function order
+pub fn pci_devices() -> Vec<PciDevice> {
+    PCI_DEVICES.lock().clone()
+}
+
+/// physical memory usage from the frame allocator
+pub fn memory() -> Option<FrameStats> {
+    memory::frame_stats()
+}
+
+/// how much of the kernel heap is mapped and in use
+pub fn heap() -> HeapStats {
+    allocator::stats()
+}
+
+/// the measured TSC frequency in Hz, None if it couldn't be calibrated
+pub fn tsc_frequency() -> Option<u64> {
+    clock::tsc_frequency()
+}
+
+fn sync_before_power_off() {
+    if let Err(e) = super::fs::sync() {
+        println!("failed to sync filesystems: {}", e);
+    }
+}
+
+/// writes the filesystems back to disk and turns the machine off
+pub fn shutdown() -> ! {
+    sync_before_power_off();
+    power::shutdown()
+}
+
+/// writes the filesystems back to disk and restarts the machine
+pub fn reboot() -> ! {
+    sync_before_power_off();
+    power::reboot()
+}
@@ -0,0 +1,236 @@
+use alloc::{collections::VecDeque, string::String, sync::Arc, vec::Vec};
+use core::task::{Context, Poll, Waker};
+use futures_util::future::poll_fn;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+/// how many bytes a pipe holds before whatever writes to it has to wait for the reader
+pub const PIPE_CAPACITY: usize = 4096;
+
+// written to by print!, which can run inside an interrupt handler, so the lock is only ever taken
+// with interrupts disabled
+struct Inner {
+    buffer: VecDeque<u8>,
+    writers: usize,
+    reader: Option<Waker>,
+    /// writers waiting for room in the buffer
+    blocked: Vec<Waker>,
+    /// the reader has been dropped, so nothing will ever make room again
+    reader_gone: bool,
+}
+
+/// the sending end of a pipe, the pipe is closed once every clone of it has been dropped
+pub struct PipeWriter(Arc<Mutex<Inner>>);
+
+/// the receiving end of a pipe
+pub struct PipeReader(Arc<Mutex<Inner>>);
+
+/// a byte stream from one task to another. it holds PIPE_CAPACITY bytes, once it is full the
+/// writer is expected to wait on `poll_ready` until the reader catches up
+pub fn pipe() -> (PipeWriter, PipeReader) {
+    let inner = Arc::new(Mutex::new(Inner {
+        buffer: VecDeque::new(),
+        writers: 1,
+        reader: None,
+        blocked: Vec::new(),
+        reader_gone: false,
+    }));
+    (PipeWriter(inner.clone()), PipeReader(inner))
+}
+
+fn locked<R>(inner: &Mutex<Inner>, f: impl FnOnce(&mut Inner) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut inner.lock()))
+}
+
+impl PipeWriter {
+    /// never waits, print! can't, so a write can take the pipe past its capacity. bytes written
+    /// after the reader has gone are thrown away.
+    pub fn write(&self, bytes: &[u8]) {
+        let reader = locked(&self.0, |inner| {
+            if inner.reader_gone {
+                return None;
+            }
+            inner.buffer.extend(bytes);
+            inner.reader.take()
+        });
+        if let Some(reader) = reader {
+            reader.wake();
+        }
+    }
+
+    /// ready once there is room in the pipe, or once there is nothing left to read it
+    pub fn poll_ready(&self, cx: &mut Context) -> Poll<()> {
+        locked(&self.0, |inner| {
+            if inner.buffer.len() < PIPE_CAPACITY || inner.reader_gone {
+                return Poll::Ready(());
+            }
+            if !inner.blocked.iter().any(|w| w.will_wake(cx.waker())) {
+                inner.blocked.push(cx.waker().clone());
+            }
+            Poll::Pending
+        })
+    }
+}
+
+impl Clone for PipeWriter {
+    fn clone(&self) -> Self {
+        locked(&self.0, |inner| inner.writers += 1);
+        PipeWriter(self.0.clone())
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        let reader = locked(&self.0, |inner| {
+            inner.writers -= 1;
+            match inner.writers {
+                0 => inner.reader.take(),
+                _ => None,
+            }
+        });
+        if let Some(reader) = reader {
+            reader.wake();
+        }
+    }
+}
+
+impl PipeReader {
+    /// a pipe that already holds `bytes` and has nothing writing to it
+    pub fn from_bytes(bytes: &[u8]) -> PipeReader {
+        let (writer, reader) = pipe();
+        writer.write(bytes);
+        reader
+    }
+
+    /// takes bytes out of the buffer once `take` says how many, None when the pipe is closed
+    /// and `take` never said yes
+    fn poll_take(
+        &mut self,
+        cx: &mut Context,
+        take: impl Fn(&VecDeque<u8>, bool) -> Option<usize>,
+    ) -> Poll<Option<Vec<u8>>> {
+        let (poll, blocked) = locked(&self.0, |inner| {
+            let closed = inner.writers == 0;
+            if let Some(n) = take(&inner.buffer, closed) {
+                let bytes = inner.buffer.drain(..n).collect();
+                let blocked = match inner.buffer.len() < PIPE_CAPACITY {
+                    true => core::mem::take(&mut inner.blocked),
+                    false => Vec::new(),
+                };
+                return (Poll::Ready(Some(bytes)), blocked);
+            }
+            if closed {
+                return (Poll::Ready(None), Vec::new());
+            }
+            inner.reader = Some(cx.waker().clone());
+            (Poll::Pending, Vec::new())
+        });
+        for writer in blocked {
+            writer.wake();
+        }
+        poll
+    }
+
+    pub fn poll_read(&mut self, cx: &mut Context) -> Poll<Option<Vec<u8>>> {
+        self.poll_take(cx, |buffer, _| (!buffer.is_empty()).then_some(buffer.len()))
+    }
+
+    /// a line that doesn't fit in the pipe comes back in pieces, the writer couldn't finish it
+    /// otherwise
+    pub fn poll_read_line(&mut self, cx: &mut Context) -> Poll<Option<String>> {
+        self.poll_take(cx, |buffer, closed| {
+            match buffer.iter().position(|b| *b == b'\n') {
+                Some(end) => Some(end + 1),
+                None if closed && !buffer.is_empty() => Some(buffer.len()),
+                None if buffer.len() >= PIPE_CAPACITY => Some(buffer.len()),
+                None => None,
+            }
+        })
+        .map(|line| line.map(|line| String::from_utf8_lossy(&line).into_owned()))
+    }
+
+    /// waits for whatever has been written since the last read, None once the pipe is closed
+    /// and empty
+    pub async fn read(&mut self) -> Option<Vec<u8>> {
+        poll_fn(|cx| self.poll_read(cx)).await
+    }
+
+    /// the next line including its newline, the last line of a closed pipe may not have one
+    pub async fn read_line(&mut self) -> Option<String> {
+        poll_fn(|cx| self.poll_read_line(cx)).await
+    }
+
+    /// everything up to the point the pipe is closed
+    pub async fn read_to_string(&mut self) -> String {
+        let mut bytes = Vec::new();
+        while let Some(chunk) = self.read().await {
+            bytes.extend(chunk);
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        let blocked = locked(&self.0, |inner| {
+            inner.reader_gone = true;
+            inner.buffer.clear();
+            core::mem::take(&mut inner.blocked)
+        });
+        for writer in blocked {
+            writer.wake();
+        }
+    }
+}
+
+#[test_case]
+fn pipes_close_when_the_writers_are_gone() {
+    use futures_util::FutureExt;
+
+    let (writer, mut reader) = pipe();
+    let second = writer.clone();
+    writer.write(b"one\ntw");
+    drop(writer);
+    assert_eq!(
+        reader.read_line().now_or_never(),
+        Some(Some(String::from("one\n")))
+    );
+    assert_eq!(reader.read_line().now_or_never(), None);
+    second.write(b"o");
+    drop(second);
+    assert_eq!(
+        reader.read_line().now_or_never(),
+        Some(Some(String::from("two")))
+    );
+    assert_eq!(reader.read_line().now_or_never(), Some(None));
+}
+
+#[test_case]
+fn full_pipes_hold_up_their_writers() {
+    use alloc::task::Wake;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    struct Flag(AtomicBool);
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+    let flag = Arc::new(Flag(AtomicBool::new(false)));
+    let waker = Waker::from(flag.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    let (writer, mut reader) = pipe();
+    writer.write(&[b'y'; PIPE_CAPACITY]);
+    assert_eq!(writer.poll_ready(&mut cx), Poll::Pending);
+    // a line as long as the pipe is handed over as it is
+    assert!(
+        matches!(reader.poll_read_line(&mut cx), Poll::Ready(Some(line)) if line.len() == PIPE_CAPACITY)
+    );
+    assert!(flag.0.load(Ordering::Relaxed));
+    assert_eq!(writer.poll_ready(&mut cx), Poll::Ready(()));
+
+    writer.write(&[b'y'; PIPE_CAPACITY]);
+    drop(reader);
+    assert_eq!(writer.poll_ready(&mut cx), Poll::Ready(()));
+}
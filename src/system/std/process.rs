@@ -0,0 +1,84 @@
+use alloc::string::{String, ToString};
+use core::future::poll_fn;
+use core::task::Poll;
+
+use crate::std::application::Error;
+use crate::std::fs;
+use crate::system::kernel::process::{self, Pid};
+use crate::system::kernel::{elf, usermode};
+
+pub use crate::system::kernel::elf::ElfError;
+pub use crate::system::kernel::process::{ProcessError, ProcessInfo, ProcessState, KILLED};
+
+/// a process started by `exec`. dropping it leaves the process running, but it is freed when it
+/// exits instead of being kept around for `wait`
+pub struct Child {
+    pid: Pid,
+}
+
+impl Child {
+    pub fn id(&self) -> u64 {
+        self.pid.as_u64()
+    }
+
+    /// waits for the process to exit and returns its exit code, other tasks carry on meanwhile
+    pub async fn wait(&self) -> Result<i64, ProcessError> {
+        poll_fn(
+            |context| match process::try_wait(self.pid, context.waker()) {
+                Ok(Some(code)) => Poll::Ready(Ok(code)),
+                Ok(None) => Poll::Pending,
+                Err(e) => Poll::Ready(Err(e)),
+            },
+        )
+        .await
+    }
+
+    /// ends the process, its exit code is `KILLED`
+    pub fn kill(&self) -> Result<(), ProcessError> {
+        process::kill(self.pid)
+    }
+}
+
+impl Drop for Child {
+    fn drop(&mut self) {
+        // after `wait` the process is already gone and this does nothing
+        process::detach(self.pid);
+    }
+}
+
+/// runs the program at `path` as a new process with its own address space. static ELF
+/// executables get `args` and `env` on their stack, anything else is treated as a flat binary
+/// that starts at its first byte. `args` should include the program name first, like argv.
+pub fn exec(path: &str, args: &[String], env: &[String]) -> Result<Child, Error> {
+    let data = fs::read(path)?;
+    let pid = if elf::is_elf(&data) {
+        elf::spawn(path, &data, args, env)?
+    } else if data.is_empty() {
+        return Err(Error::CommandFailed(String::from("file is empty")));
+    } else {
+        usermode::spawn(path, &data)?
+    };
+    Ok(Child { pid })
+}
+
+/// the pid of the calling process, 0 for the kernel
+pub fn id() -> u64 {
+    process::current().as_u64()
+}
+
+/// every process that is running or has exited without being waited on
+pub fn list() -> alloc::vec::Vec<ProcessInfo> {
+    process::list()
+}
+
+impl From<ElfError> for Error {
+    fn from(e: ElfError) -> Self {
+        Error::CommandFailed(e.to_string())
+    }
+}
+
+impl From<ProcessError> for Error {
+    fn from(e: ProcessError) -> Self {
+        Error::CommandFailed(e.to_string())
+    }
+}
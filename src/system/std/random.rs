@@ -2,22 +2,16 @@ use alloc::{
     string::{String, ToString},
     vec::Vec,
 };
-use cmos_rtc::{ReadRTC, Time};
 use lazy_static::lazy_static;
 use rand::{rngs::SmallRng, RngCore, SeedableRng};
 use spin::Mutex;
 
+use super::time::{Instant, SystemTime};
+
 lazy_static! {
-    pub static ref RANDOM: Mutex<SmallRng> = Mutex::new(SmallRng::seed_from_u64({
-        let mut cmos = ReadRTC::new(0x00, 0x00);
-        let time: Time = cmos.read();
-        time.second as u64
-            + time.minute as u64
-            + time.hour as u64
-            + time.day as u64
-            + time.month as u64
-            + time.year as u64
-    }));
+    pub static ref RANDOM: Mutex<SmallRng> = Mutex::new(SmallRng::seed_from_u64(
+        SystemTime::now().unix() ^ Instant::now().since_boot().as_nanos() as u64
+    ));
 }
 
 pub struct Random;
@@ -1,5 +1,6 @@
 use crate::std::io::Color;
-use crate::system::kernel::render::{ScreenChar, RENDERER};
+use crate::std::syscall;
+use crate::system::kernel::render::ScreenChar;
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
@@ -148,7 +149,7 @@ impl Frame {
                 frame[i + self.position.y][j + self.position.x] = col.as_screen_char();
             }
         }
-        RENDERER.lock().render_frame(frame);
+        syscall::blit(&frame);
         Ok(())
     }
     pub fn get_position(&self) -> Position<usize> {
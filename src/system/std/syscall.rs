@@ -1,6 +1,68 @@
-/// THIS FILE IS ONLY FOR SPECIFIC CASES WHERE THE MAIN FUNCTION NEEDS DIRECT KERNEL INTERACTION
-use crate::system::kernel::render::RENDERER;
+//! thin wrappers around the kernel's syscall table. they work the same from ring 0 and ring 3,
+//! so anything written against them can be moved into user mode.
+
+use core::arch::asm;
+use core::time::Duration;
+
+use crate::system::kernel::syscall::{Frame, Syscall, SYSCALL_ERROR};
+use crate::system::kernel::tasks::keyboard::KeyStroke;
+
+fn syscall(number: Syscall, arg0: u64, arg1: u64) -> u64 {
+    let result;
+    unsafe {
+        asm!(
+            "int 0x80",
+            inlateout("rax") number as u64 => result,
+            in("rdi") arg0,
+            in("rsi") arg1,
+        );
+    }
+    result
+}
+
+/// prints a string, returns how many bytes were written
+pub fn write(s: &str) -> usize {
+    match syscall(Syscall::Write, s.as_ptr() as u64, s.len() as u64) {
+        SYSCALL_ERROR => 0,
+        n => n as usize,
+    }
+}
+
+/// the next keystroke if one is waiting | non blocking
+pub fn read_key() -> Option<KeyStroke> {
+    KeyStroke::from_u64(syscall(Syscall::ReadKey, 0, 0))
+}
+
+/// blocks the calling thread without spinning
+pub fn sleep(duration: Duration) {
+    let millis = duration.as_millis() + (duration.subsec_nanos() % 1_000_000 != 0) as u128;
+    syscall(Syscall::Sleep, millis as u64, 0);
+}
+
+/// ends the calling process with `code`
+pub fn exit(code: i64) -> ! {
+    syscall(Syscall::Exit, code as u64, 0);
+    unreachable!("exit syscall returned")
+}
+
+/// sends a whole frame to the application buffer
+pub fn blit(frame: &Frame) {
+    syscall(Syscall::Blit, frame as *const Frame as u64, 0);
+}
 
 pub fn terminal_mode_force() {
-    RENDERER.lock().terminal_mode_force();
+    syscall(Syscall::TerminalMode, 0, 0);
+}
+
+pub fn getpid() -> u64 {
+    syscall(Syscall::GetPid, 0, 0)
+}
+
+/// blocks until the child `pid` exits and returns its exit code, None if it is not a child
+pub fn wait(pid: u64) -> Option<i64> {
+    let mut status = 0i64;
+    match syscall(Syscall::Wait, pid, &mut status as *mut i64 as u64) {
+        SYSCALL_ERROR => None,
+        _ => Some(status),
+    }
 }
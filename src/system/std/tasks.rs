@@ -1,7 +1,42 @@
-pub use crate::system::kernel::tasks::{executor::Executor, Task};
+pub use crate::system::kernel::tasks::executor::{
+    cancel, current, resume, spawn, suspend, Executor, JoinError, JoinHandle,
+};
+pub use crate::system::kernel::tasks::{Task, TaskId, TaskInfo, TaskState};
+
+use super::application::Error;
+use crate::system::kernel::tasks;
+use alloc::string::ToString;
+use core::future::poll_fn;
+use core::task::Poll;
 
 pub fn stop() -> ! {
     loop {
         x86_64::instructions::hlt();
     }
 }
+
+/// every task on the executor that hasn't finished yet
+pub fn list() -> alloc::vec::Vec<TaskInfo> {
+    tasks::tasks()
+}
+
+/// lets every other task that is ready have a turn before carrying on, for loops that might
+/// otherwise never wait on anything
+pub async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|context| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        context.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+impl From<JoinError> for Error {
+    fn from(e: JoinError) -> Self {
+        Error::CommandFailed(e.to_string())
+    }
+}
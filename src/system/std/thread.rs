@@ -0,0 +1,81 @@
+use super::time::{Duration, Instant};
+use crate::std::application::Error;
+use crate::system::kernel::memory::ThreadId;
+use crate::system::kernel::{clock, multitasking};
+use alloc::string::ToString;
+
+pub use crate::system::kernel::multitasking::{ThreadError, ThreadInfo, ThreadState};
+
+/// a running kernel thread, dropping the handle leaves the thread running
+pub struct JoinHandle {
+    id: ThreadId,
+}
+
+impl JoinHandle {
+    pub fn id(&self) -> u64 {
+        self.id.as_u64()
+    }
+
+    /// waits for the thread to finish
+    pub fn join(self) -> Result<(), ThreadError> {
+        multitasking::join(self.id)
+    }
+}
+
+/// runs `f` on its own kernel thread, it is preempted like every other thread
+pub fn spawn<F>(name: &str, f: F) -> Result<JoinHandle, ThreadError>
+where
+    F: FnOnce() + Send + 'static,
+{
+    multitasking::spawn(name, f).map(|id| JoinHandle { id })
+}
+
+pub fn yield_now() {
+    multitasking::yield_now()
+}
+
+/// blocks the current thread until `deadline` without spinning, other threads keep running in
+/// the meantime
+pub fn sleep_until(deadline: Instant) {
+    // the tick count and the TSC can drift apart a little, so check again after waking
+    while Instant::now() < deadline {
+        if !multitasking::sleep_until(clock::tick_at(deadline.since_boot().as_nanos() as u64)) {
+            // threads are not running yet, so there is nothing to hand the cpu to
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// blocks the current thread for at least `duration`
+pub fn sleep(duration: Duration) {
+    sleep_until(Instant::now() + duration)
+}
+
+/// ends the current thread
+pub fn exit() -> ! {
+    multitasking::exit()
+}
+
+pub fn current() -> Option<u64> {
+    multitasking::current().map(|id| id.as_u64())
+}
+
+/// every thread the scheduler knows about, including exited threads that have not been joined
+pub fn list() -> alloc::vec::Vec<ThreadInfo> {
+    multitasking::threads()
+}
+
+impl From<ThreadError> for Error {
+    fn from(e: ThreadError) -> Self {
+        Error::CommandFailed(e.to_string())
+    }
+}
+
+#[test_case]
+fn sleep_lasts_at_least_as_long_as_asked() {
+    let start = Instant::now();
+    sleep(Duration::from_millis(30));
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(30));
+    assert!(elapsed < Duration::from_millis(200));
+}
@@ -1,61 +1,184 @@
-use super::super::kernel::interrupts::GLOBALTIMER;
+use super::super::kernel::clock;
+use super::thread;
 use crate::println;
-use x86_64::instructions::interrupts;
+use core::future::Future;
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_util::stream::{Stream, StreamExt};
 
+pub use super::super::kernel::rtc::{
+    set_timezone_offset, timezone_offset, DateTime, SystemTime, UNIX_EPOCH,
+};
+pub use super::super::kernel::tasks::timer::Sleep;
+pub use core::time::Duration;
+
+/// a point on the monotonic clock, which starts at boot and never goes backwards. nanosecond
+/// resolution once the TSC has been calibrated, a timer tick before that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
+
+impl Instant {
+    pub fn now() -> Instant {
+        Instant(clock::now())
+    }
+
+    /// zero if `earlier` is actually later
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    /// how long after boot this is
+    pub fn since_boot(&self) -> Duration {
+        Duration::from_nanos(self.0)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        u64::try_from(duration.as_nanos())
+            .ok()
+            .and_then(|nanos| self.0.checked_add(nanos))
+            .map(Instant)
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+        u64::try_from(duration.as_nanos())
+            .ok()
+            .and_then(|nanos| self.0.checked_sub(nanos))
+            .map(Instant)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to instant")
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, duration: Duration) -> Instant {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting duration from instant")
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// completes at `deadline`. awaiting it lets the executor run other tasks in the meantime,
+/// use `thread::sleep_until` to block the whole thread instead.
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep::until(deadline.0)
+}
+
+/// completes after `duration`, see `sleep_until`
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+/// blocks the calling thread for `seconds`
 pub fn wait(seconds: f64) {
-    let mut start = 0;
-    interrupts::without_interrupts(|| {
-        start = GLOBALTIMER.lock().val;
-    });
-
-    loop {
-        let mut new = 0;
-        interrupts::without_interrupts(|| {
-            new = GLOBALTIMER.lock().val;
-        });
-        if new as f64 > start as f64 + seconds * 16.0 {
-            return;
+    thread::sleep(Duration::from_secs_f64(seconds))
+}
+
+/// a stream that yields once every `period`, starting one period from now
+pub fn interval(period: Duration) -> Interval {
+    let next = Instant::now() + period;
+    Interval {
+        period,
+        next,
+        sleep: sleep_until(next),
+    }
+}
+
+pub struct Interval {
+    period: Duration,
+    next: Instant,
+    sleep: Sleep,
+}
+
+impl Interval {
+    /// waits for the next tick and returns when it was due
+    pub async fn tick(&mut self) -> Instant {
+        self.next().await.expect("intervals never end")
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+}
+
+impl Stream for Interval {
+    type Item = Instant;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Instant>> {
+        if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+            return Poll::Pending;
         }
+        let due = self.next;
+        // a consumer that falls behind skips the ticks it missed rather than getting a burst
+        self.next = (due + self.period).max(Instant::now());
+        let next = self.next.0;
+        self.sleep.reset(next);
+        Poll::Ready(Some(due))
     }
 }
 
 pub fn timer() {
-    interrupts::without_interrupts(|| {
-        println!("{}", GLOBALTIMER.lock().val);
-    });
+    let uptime = Instant::now().since_boot();
+    println!(
+        "{}.{:03}s since boot ({} ticks)",
+        uptime.as_secs(),
+        uptime.subsec_millis(),
+        clock::ticks()
+    );
 }
 
+/// fires every `duration`, checked by polling `is_done`
 pub struct Timer {
-    duration: f64,
-    end: f64,
+    duration: Duration,
+    end: Instant,
 }
 
 impl Timer {
     pub(crate) fn new(seconds: f64) -> Self {
-        let mut start = 0;
-        interrupts::without_interrupts(|| {
-            start = GLOBALTIMER.lock().val;
-        });
-
+        let duration = Duration::from_secs_f64(seconds);
         Timer {
-            duration: seconds,
-            end: start as f64 + seconds * 16.0,
+            duration,
+            end: Instant::now() + duration,
         }
     }
 
     pub(crate) fn is_done(&self) -> bool {
-        let mut done = false;
-        interrupts::without_interrupts(|| {
-            done = GLOBALTIMER.lock().val as f64 > self.end;
-        });
-        done
+        Instant::now() >= self.end
     }
 
     pub(crate) fn reset(&mut self) {
-        let mut start = 0;
-        interrupts::without_interrupts(|| {
-            start = GLOBALTIMER.lock().val;
-        });
-        self.end = start as f64 + self.duration * 16.0
+        self.end = Instant::now() + self.duration;
     }
 }
@@ -27,7 +27,7 @@ impl Interpreter {
             Node::Number(_) => return self.visit_number(node),
             Node::Operator(_) => return self.visit_operator(node),
             Node::Function(_) => return self.visit_function(node),
-            Node::Variable => panic!("substitution not used!"),
+            Node::Variable => return Err(Error::Other(String::from("substitution not used!"))),
         }
     }
 
@@ -290,7 +290,7 @@ impl Parser {
             Token::Operator(Operator::Add) | Token::Operator(Operator::Sub) => {
                 self.advance()?;
                 let operator = mknode!(token).expect("mknode returned none");
-                let other = self.factor().expect("holup");
+                let other = self.factor()?;
                 return Ok(Node::UnaryOperation(Box::new(UnaryOperation {
                     operator,
                     other,
@@ -357,6 +357,11 @@ pub struct Calculator {}
 
 #[async_trait]
 impl Application for Calculator {
+    const NAME: &'static str = "calc";
+    const ALIASES: &'static [&'static str] = &["calculate", "solve"];
+    const DESCRIPTION: &'static str = "works out a sum, or keeps asking for them without one";
+    const USAGE: &'static str = "calc [expression]";
+
     fn new() -> Self {
         Self {}
     }
@@ -516,7 +521,11 @@ fn tokenise(equation: &str) -> Result<Vec<Token>, Error> {
             '.' => current_num.push(character),
             _ => {
                 if current_num.len() != 0 {
-                    tokens.push(Token::Number(current_num.parse::<f64>().unwrap()));
+                    tokens.push(Token::Number(
+                        current_num
+                            .parse::<f64>()
+                            .map_err(|_| Error::InvalidSyntax(x))?,
+                    ));
                     current_num = "".to_string();
                 } else if current_string.len() != 0 {
                 }
@@ -525,18 +534,18 @@ fn tokenise(equation: &str) -> Result<Vec<Token>, Error> {
                     '-' => tokens.push(Token::Operator(Operator::Sub)),
                     '%' => tokens.push(Token::Operator(Operator::Mod)),
                     '*' => {
-                        if &equation.chars().nth(x - 1).unwrap() == &'*' {
+                        if x > 0 && equation.chars().nth(x - 1) == Some('*') {
                             tokens.push(Token::Operator(Operator::Exp));
-                        } else if &equation.chars().nth(x + 1).unwrap() == &'*' {
+                        } else if equation.chars().nth(x + 1) == Some('*') {
                             ()
                         } else {
                             tokens.push(Token::Operator(Operator::Mul));
                         }
                     }
                     '/' => {
-                        if &equation.chars().nth(x - 1).unwrap() == &'/' {
+                        if x > 0 && equation.chars().nth(x - 1) == Some('/') {
                             tokens.push(Token::Operator(Operator::Qot));
-                        } else if &equation.chars().nth(x + 1).unwrap() == &'/' {
+                        } else if equation.chars().nth(x + 1) == Some('/') {
                             ()
                         } else {
                             tokens.push(Token::Operator(Operator::Div));
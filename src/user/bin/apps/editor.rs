@@ -1,4 +1,5 @@
 use crate::std::application::{self, Application};
+use crate::std::fs::{self, FsError};
 use crate::std::io::{Color, ColorCode, Display, KeyStroke};
 use crate::std::render::{ColouredChar, Frame, Position, RenderError};
 use crate::user::lib::libgui::cg_core::CgComponent;
@@ -22,6 +23,7 @@ pub struct Editor {
     unsaved: bool,
     display: Display,
     lineno_width: i32,
+    path: Option<String>,
 }
 
 enum Mode {
@@ -124,6 +126,43 @@ impl Editor {
             .unwrap();
     }
 
+    /// runs a command typed after ':', returns true if the editor should exit
+    fn execute_command(&mut self, command: &str) -> Result<bool, application::Error> {
+        let (cmd, arg) = match command.split_once(' ') {
+            Some((cmd, arg)) => (cmd, Some(arg.trim())),
+            None => (command, None),
+        };
+
+        if let Some(path) = arg {
+            self.path = Some(path.to_string());
+        }
+
+        match cmd {
+            "w" => {
+                self.save()?;
+                Ok(false)
+            }
+            "wq" => {
+                self.save()?;
+                Ok(true)
+            }
+            "q" => Ok(true),
+            _ => Ok(false),
+        }
+    }
+
+    fn save(&mut self) -> Result<(), application::Error> {
+        let path = self
+            .path
+            .clone()
+            .ok_or(application::Error::CommandFailed(String::from(
+                "no file name, use :w <path>",
+            )))?;
+        fs::write(&path, self.to_string().as_bytes())?;
+        self.unsaved = false;
+        Ok(())
+    }
+
     fn delete_char(&mut self) {
         self.unsaved = true;
         // if the cursor is at the end of the line
@@ -192,6 +231,10 @@ impl ToString for Editor {
 
 #[async_trait]
 impl Application for Editor {
+    const NAME: &'static str = "editor";
+    const DESCRIPTION: &'static str = "a text editor";
+    const USAGE: &'static str = "editor [file]";
+
     fn new() -> Editor {
         Editor {
             buffer: Vec::new(),
@@ -202,16 +245,25 @@ impl Application for Editor {
             unsaved: false,
             display: Display::borrow(),
             lineno_width: 0,
+            path: None,
         }
     }
 
-    async fn run(&mut self, _args: Vec<String>) -> Result<(), application::Error> {
-        // if let Some(s) = args.get(0) {
-        //     self.buffer = s.lines().map(|l| l.chars().collect()).collect::<Vec<Vec<char>>>()
-        // }
-
-        self.buffer = String::from(
-            "
+    async fn run(&mut self, args: Vec<String>) -> Result<(), application::Error> {
+        if let Some(path) = args.get(0) {
+            let contents = match fs::read_to_string(path) {
+                Ok(s) => s,
+                Err(FsError::NotFound) => String::new(),
+                Err(e) => return Err(e.into()),
+            };
+            self.path = Some(path.clone());
+            self.buffer = contents
+                .lines()
+                .map(|l| l.chars().collect())
+                .collect::<Vec<Vec<char>>>();
+        } else {
+            self.buffer = String::from(
+                "
     /$$ /$$$$$$$$ /$$   /$$  /$$$$$$  /$$$$$$$       /$$ /$$  /$$
    /$$/|_____ $$ | $$  / $$ /$$__  $$| $$____/      /$$/|  $$|  $$
   /$$/      /$$/ |  $$/ $$/| $$  \\ $$| $$          /$$/  \\  $$\\  $$
@@ -222,10 +274,11 @@ impl Application for Editor {
    \\__/|________/|__/  |__/ \\____ $$$ \\______/|__/      |__/ |__/
                                  \\__/
     ",
-        )
-        .lines()
-        .map(|l| l.chars().collect())
-        .collect::<Vec<Vec<char>>>();
+            )
+            .lines()
+            .map(|l| l.chars().collect())
+            .collect::<Vec<Vec<char>>>();
+        }
 
         loop {
             // start by rendering the screen
@@ -293,23 +346,29 @@ impl Application for Editor {
                         }
                     }
                 }
-                Mode::Command => {
-                    match keystroke {
-                        KeyStroke::Enter => {
-                            // TODO: execute command
+                Mode::Command => match keystroke {
+                    KeyStroke::Enter | KeyStroke::Char('\n') => {
+                        let command = core::mem::take(&mut self.command);
+                        self.mode = Mode::Normal;
+                        if self.execute_command(command.trim())? {
+                            return Ok(());
                         }
-                        KeyStroke::Char(c) => {
-                            if c == '\x1B' {
-                                self.mode = Mode::Normal;
-                                self.command.clear();
-                                continue;
-                            }
-
-                            self.command.push(c);
+                    }
+                    KeyStroke::Char(c) => {
+                        if c == '\x1B' {
+                            self.mode = Mode::Normal;
+                            self.command.clear();
+                            continue;
                         }
-                        _ => {}
+                        if c == '\x08' {
+                            self.command.pop();
+                            continue;
+                        }
+
+                        self.command.push(c);
                     }
-                }
+                    _ => {}
+                },
                 Mode::Diff => {}
             }
         }
@@ -356,10 +415,16 @@ impl CgComponent for Editor {
         // line and col (variable width)
         let line_and_col = format!("[{}:{}] ", self.cursor_pos.y + 1, self.cursor_pos.x + 1);
 
+        // command being typed (variable width)
+        let command = match self.mode {
+            Mode::Command => format!(":{}", self.command),
+            _ => String::new(),
+        };
+
         // write to screen
-        let toolbar = line_and_col + " " + &mode + " " + &unsaved;
+        let toolbar = line_and_col + " " + &mode + " " + &unsaved + " " + &command;
 
-        for (i, c) in toolbar.chars().enumerate() {
+        for (i, c) in toolbar.chars().take(80).enumerate() {
             frame.write(Position::new(i, 24), ColouredChar::new(c))?;
         }
 
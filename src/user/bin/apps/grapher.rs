@@ -39,6 +39,10 @@ struct PointI64 {
 
 #[async_trait]
 impl Application for Grapher {
+    const NAME: &'static str = "graph";
+    const DESCRIPTION: &'static str = "plots an equation";
+    const USAGE: &'static str = "graph [equation]";
+
     fn new() -> Self {
         Self {
             points: Vec::new(),
@@ -16,6 +16,10 @@ pub struct Tasks;
 
 #[async_trait]
 impl Application for Tasks {
+    const NAME: &'static str = "tasks";
+    const DESCRIPTION: &'static str = "a todo list";
+    const USAGE: &'static str = "tasks <add <task> | remove <n> | select random | priority | list>";
+
     fn new() -> Self {
         Self {}
     }
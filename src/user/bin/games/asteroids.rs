@@ -13,6 +13,10 @@ use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use async_trait::async_trait;
 use core::any::Any;
+use std::time::{Duration, Instant};
+
+/// the game moves on one step per frame, so this sets its speed
+const FRAME_TIME: Duration = Duration::from_millis(50);
 
 #[derive(Clone)]
 pub struct Player {
@@ -41,6 +45,10 @@ pub struct Game {
 
 #[async_trait]
 impl Application for Game {
+    const NAME: &'static str = "games/asteroids";
+    const DESCRIPTION: &'static str = "asteroids";
+    const USAGE: &'static str = "games/asteroids";
+
     fn new() -> Self {
         Self {
             player: Player::new(),
@@ -67,8 +75,11 @@ impl Application for Game {
         let self_ref = container_data.fetch("app").unwrap();
         let score_ref = container_data.fetch("score_label").unwrap();
 
+        let mut next_frame = Instant::now();
         loop {
-            std::time::wait(0.01);
+            // a slow frame pushes the next ones back rather than making them catch up
+            next_frame = (next_frame + FRAME_TIME).max(Instant::now());
+            std::time::sleep_until(next_frame).await;
 
             if self.gameloop_iteration() {
                 break;
@@ -7,7 +7,7 @@ use crate::{
         io::{Color, Display, KeyStroke, Stdin},
         random::Random,
         render::{ColorCode, ColouredChar, Dimensions, Frame, Position, RenderError},
-        time,
+        time::{self, Duration},
     },
     user::lib::libgui::cg_core::CgComponent,
 };
@@ -30,6 +30,10 @@ pub enum Cell {
 
 #[async_trait]
 impl Application for Game {
+    const NAME: &'static str = "games/connect4";
+    const DESCRIPTION: &'static str = "connect 4";
+    const USAGE: &'static str = "games/connect4";
+
     fn new() -> Self {
         Game {
             board: [[Cell::Empty; 7]; 6],
@@ -91,7 +95,7 @@ impl Application for Game {
 
             self.apply_gravity();
             self.check_victory();
-            time::wait(0.1);
+            time::sleep(Duration::from_millis(100)).await;
         }
 
         Ok(())
@@ -1,7 +1,7 @@
 use crate::std::application::{Application, Error};
 use crate::std::io::{Color, ColorCode, Display, KeyStroke, Stdin};
 use crate::std::render::{ColouredChar, Dimensions, Frame, Position, RenderError};
-use crate::std::time::wait;
+use crate::std::time::{sleep, Duration};
 use alloc::boxed::Box;
 use alloc::string::String;
 use alloc::vec;
@@ -12,10 +12,14 @@ pub struct GameOfLife {
     frame: Frame,
 }
 
-const LOOP_SPEED: f64 = 0.1;
+const LOOP_SPEED: Duration = Duration::from_millis(100);
 
 #[async_trait]
 impl Application for GameOfLife {
+    const NAME: &'static str = "games/gameoflife";
+    const DESCRIPTION: &'static str = "conway's game of life";
+    const USAGE: &'static str = "games/gameoflife";
+
     fn new() -> Self {
         Self {
             frame: Frame::new(Position::new(0, 0), Dimensions::new(80, 25)).unwrap(),
@@ -52,7 +56,7 @@ impl Application for GameOfLife {
         self.activate(1 + xoffset, 12 + yoffset);
         self.activate(2 + xoffset, 12 + yoffset);
 
-        self.mainloop()?;
+        self.mainloop().await?;
 
         Ok(())
     }
@@ -63,11 +67,11 @@ impl GameOfLife {
         self.frame[24 - y as usize][x as usize] =
             ColouredChar::coloured('#', ColorCode::new(Color::Green, Color::Black));
     }
-    fn mainloop(&mut self) -> Result<(), Error> {
+    async fn mainloop(&mut self) -> Result<(), Error> {
         'mainloop: loop {
             // render element previous frame before resetting.
 
-            wait(LOOP_SPEED);
+            sleep(LOOP_SPEED).await;
 
             self.render().map_err(|_| {
                 Error::ApplicationError(String::from("failed to render game screen"))
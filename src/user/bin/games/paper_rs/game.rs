@@ -9,7 +9,7 @@ use crate::{
         application::{Application, Error},
         io::{Color, ColorCode, Display, KeyStroke, Stdin},
         render::{ColouredChar, Dimensions, Frame, Position, RenderError},
-        time,
+        time::{self, Duration},
     },
     user::lib::libgui::cg_core::CgComponent,
 };
@@ -33,6 +33,10 @@ pub struct GameBoard {
 
 #[async_trait]
 impl Application for GameBoard {
+    const NAME: &'static str = "games/paper.rs";
+    const DESCRIPTION: &'static str = "paper.io";
+    const USAGE: &'static str = "games/paper.rs";
+
     fn new() -> GameBoard {
         GameBoard {
             board: [[Cell::Empty; 80]; 25],
@@ -64,7 +68,7 @@ impl Application for GameBoard {
             // player controls player 1.
             loop {
                 self.render().unwrap().write_to_screen().unwrap();
-                time::wait(0.1);
+                time::sleep(Duration::from_millis(100)).await;
 
                 // first get player input
                 if let Some(keystroke) = Stdin::try_keystroke() {
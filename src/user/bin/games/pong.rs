@@ -19,6 +19,10 @@ pub(crate) struct Game {
 
 #[async_trait]
 impl Application for Game {
+    const NAME: &'static str = "games/pong";
+    const DESCRIPTION: &'static str = "pong";
+    const USAGE: &'static str = "games/pong";
+
     fn new() -> Self {
         Game {
             ball: Ball::new(),
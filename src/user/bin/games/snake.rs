@@ -3,12 +3,15 @@ use crate::std::application::{Application, Error};
 use crate::std::io::{Color, Display, KeyStroke, Stdin};
 use crate::std::random::Random;
 use crate::std::render::{ColorCode, ColouredChar, Dimensions, Frame, RenderError};
-use crate::std::time;
+use crate::std::time::{self, Duration, Instant};
 use crate::system::std::render;
 use alloc::string::String;
 use alloc::{boxed::Box, format, vec, vec::Vec};
 use async_trait::async_trait;
 
+/// the snakes move one square per frame
+const FRAME_TIME: Duration = Duration::from_millis(100);
+
 #[derive(PartialEq)]
 enum Gamemode {
     SinglePlayer,
@@ -33,6 +36,10 @@ pub struct Game {
 
 #[async_trait]
 impl Application for Game {
+    const NAME: &'static str = "games/snake";
+    const DESCRIPTION: &'static str = "snake";
+    const USAGE: &'static str = "games/snake";
+
     fn new() -> Self {
         Self {
             snakes: Vec::new(),
@@ -106,8 +113,10 @@ impl Game {
         // main gameloop
         let mut _all_points: Vec<Position>;
 
+        let mut next_frame = Instant::now();
         'gameloop: loop {
-            time::wait(0.1);
+            next_frame = (next_frame + FRAME_TIME).max(Instant::now());
+            time::sleep_until(next_frame).await;
 
             let mut _points: Vec<Position>;
             let length = self.snakes.len();
@@ -19,6 +19,10 @@
 //
 // #[async_trait]
 // impl Application for TetrisEngine {
+//     const NAME: &'static str = "games/tetris";
+//     const DESCRIPTION: &'static str = "tetris";
+//     const USAGE: &'static str = "games/tetris";
+//
 //     fn new() -> Self {
 //         Self {
 //             score: 0,
@@ -0,0 +1,246 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use vga::writers::{GraphicsWriter, PrimitiveDrawing};
+
+use super::commands::{self, COMMANDS};
+use super::{jobs, script};
+use crate::{
+    println,
+    std::{
+        application::Error,
+        env as vars, fs,
+        io::{Display, Screen, Serial, Stdin},
+        os,
+        time::timer,
+    },
+};
+
+// commands that are part of the shell rather than applications
+
+pub async fn echo(args: Vec<String>) -> Result<(), Error> {
+    // `calc 2+2 | echo` echoes whatever came down the pipe
+    let text = match args.is_empty() && Stdin::is_piped() {
+        true => Stdin::read_to_string().await.trim_end().to_string(),
+        false => args
+            .into_iter()
+            .map(|mut s| {
+                s.push_str(" ");
+                s
+            })
+            .collect::<String>(),
+    };
+    println!("Crystal: '{}'", text);
+    Ok(())
+}
+
+pub async fn clear(_args: Vec<String>) -> Result<(), Error> {
+    Screen::clear();
+    // not sure why this code was here but leaving it in case weird bugs happen so i remember to add it back if so
+    //interrupts::without_interrupts(|| {});
+    Ok(())
+}
+
+pub async fn time(_args: Vec<String>) -> Result<(), Error> {
+    timer();
+    Ok(())
+}
+
+pub async fn cd(args: Vec<String>) -> Result<(), Error> {
+    let path = args.get(0).map(|s| s.as_str()).unwrap_or("/");
+    fs::set_current_dir(path)?;
+    Ok(())
+}
+
+pub async fn pwd(_args: Vec<String>) -> Result<(), Error> {
+    println!("{}", fs::current_dir());
+    Ok(())
+}
+
+pub async fn sync(_args: Vec<String>) -> Result<(), Error> {
+    fs::sync()?;
+    Ok(())
+}
+
+fn print_vars() {
+    for (name, value) in vars::vars() {
+        println!("{}={}", name, value);
+    }
+}
+
+pub async fn export(args: Vec<String>) -> Result<(), Error> {
+    if args.is_empty() {
+        print_vars();
+    }
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !vars::is_valid_name(name) {
+            return Err(Error::CommandFailed(format!(
+                "'{}' is not a valid variable name",
+                name
+            )));
+        }
+        // `export NAME` on its own makes sure the variable exists
+        match value {
+            Some(value) => vars::set_var(name, value),
+            None if vars::var(name).is_none() => vars::set_var(name, ""),
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+pub async fn unset(args: Vec<String>) -> Result<(), Error> {
+    if args.is_empty() {
+        return Err(usage("unset"));
+    }
+    for name in args {
+        vars::remove_var(&name);
+    }
+    Ok(())
+}
+
+pub async fn env(_args: Vec<String>) -> Result<(), Error> {
+    print_vars();
+    Ok(())
+}
+
+pub async fn jobs(_args: Vec<String>) -> Result<(), Error> {
+    jobs::list()
+}
+
+/// `fg` and `bg` take over the prompt, so they can't run as part of a pipeline
+pub async fn prompt_only(name: &str, _args: Vec<String>) -> Result<(), Error> {
+    Err(Error::CommandFailed(format!(
+        "{} only works on its own at the prompt",
+        name
+    )))
+}
+
+/// the error for a builtin called the wrong way, with the usage from its entry in `COMMANDS`
+fn usage(name: &str) -> Error {
+    let usage = commands::find(name).map_or(name, |command| command.usage);
+    Error::CommandFailed(format!("usage: {}", usage))
+}
+
+pub async fn help(args: Vec<String>) -> Result<(), Error> {
+    let name = match args.first() {
+        Some(name) => name,
+        None => {
+            let width = COMMANDS.iter().map(|c| c.name.len()).max().unwrap_or(0);
+            for command in COMMANDS {
+                println!("  {:<width$}  {}", command.name, command.description);
+            }
+            println!("\n`help <command>` says more about one of them");
+            return Ok(());
+        }
+    };
+    let command = commands::find(name).ok_or(Error::UnknownCommand(name.clone()))?;
+    println!("{}\n  {}", command.usage, command.description);
+    if !command.aliases.is_empty() {
+        println!("  also called: {}", command.aliases.join(", "));
+    }
+    Ok(())
+}
+
+/// `true` and `false`, they only give an exit status
+pub async fn status(code: i32, _args: Vec<String>) -> Result<(), Error> {
+    match code {
+        0 => Ok(()),
+        code => Err(Error::Exit(code)),
+    }
+}
+
+/// whether the expression given to `test` holds
+fn check(args: &[&str]) -> Result<bool, Error> {
+    let number = |s: &str| {
+        s.trim()
+            .parse::<f64>()
+            .map_err(|_| Error::CommandFailed(format!("'{}' is not a number", s)))
+    };
+    Ok(match args {
+        [] => false,
+        ["!", rest @ ..] => !check(rest)?,
+        [text] => !text.is_empty(),
+        ["-z", text] => text.is_empty(),
+        ["-n", text] => !text.is_empty(),
+        ["-e", path] => fs::exists(path),
+        ["-f", path] => fs::metadata(path).map_or(false, |m| m.is_file()),
+        ["-d", path] => fs::metadata(path).map_or(false, |m| m.is_dir()),
+        [a, "=", b] => a == b,
+        [a, "!=", b] => a != b,
+        [a, op, b] => {
+            let (a, b) = (number(a)?, number(b)?);
+            match *op {
+                "-eq" => a == b,
+                "-ne" => a != b,
+                "-lt" => a < b,
+                "-le" => a <= b,
+                "-gt" => a > b,
+                "-ge" => a >= b,
+                op => return Err(Error::CommandFailed(format!("unknown test '{}'", op))),
+            }
+        }
+        _ => return Err(Error::CommandFailed(String::from("too many arguments"))),
+    })
+}
+
+/// `test`, fails without saying anything when the expression doesn't hold so it can go in an
+/// `if` or `while`
+pub async fn test(args: Vec<String>) -> Result<(), Error> {
+    let args = args.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+    match check(&args)? {
+        true => Ok(()),
+        false => Err(Error::Exit(1)),
+    }
+}
+
+/// `[ ... ]` is `test` with a `]` on the end
+pub async fn bracket(mut args: Vec<String>) -> Result<(), Error> {
+    if args.pop().as_deref() != Some("]") {
+        return Err(Error::CommandFailed(String::from("missing ']'")));
+    }
+    test(args).await
+}
+
+pub async fn sh(args: Vec<String>) -> Result<(), Error> {
+    let mut args = args.into_iter();
+    let path = args.next().ok_or_else(|| usage("sh"))?;
+    script::run_file(&path, args.collect()).await
+}
+
+pub async fn serial(_args: Vec<String>) -> Result<(), Error> {
+    let c = Serial::reply_char('e');
+    println!("{}", c);
+    Ok(())
+}
+
+pub async fn shutdown(_args: Vec<String>) -> Result<(), Error> {
+    os::shutdown()
+}
+
+pub async fn reboot(_args: Vec<String>) -> Result<(), Error> {
+    os::reboot()
+}
+
+pub async fn test_features(_args: Vec<String>) -> Result<(), Error> {
+    let _d = Display::borrow();
+    super::setup_ui().await;
+    Ok(())
+}
+
+pub async fn vga(_args: Vec<String>) -> Result<(), Error> {
+    use vga::colors::Color16;
+    use vga::writers::Graphics640x480x16;
+
+    let mode = Graphics640x480x16::new();
+    mode.set_mode();
+    mode.clear_screen(Color16::Black);
+    mode.draw_line((80, 60), (120, 420), Color16::Cyan);
+    Ok(())
+}
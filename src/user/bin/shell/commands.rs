@@ -0,0 +1,324 @@
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{future::Future, pin::Pin};
+
+use super::builtins;
+use crate::{
+    std::application::{Application, Error},
+    user::bin::{
+        apps::{calc::Calculator, editor::Editor, grapher::Grapher, tasks::Tasks},
+        games::{
+            asteroids::Game as AsteroidsGame,
+            connect4::Game as Connect4Game,
+            gameoflife::GameOfLife,
+            paper_rs::GameBoard,
+            pong::Game as PongGame,
+            snake::Game as SnakeGame,
+            // tetris::TetrisEngine,
+        },
+        utils::{
+            crystalfetch::CrystalFetch,
+            date::{Date, Uptime},
+            disks::Disks,
+            files::{Cat, Ls, Mkdir, Mount, Mounts, Mv, Rm, Touch, Umount},
+            gigachad_detector::GigachadDetector,
+            lsblk::Lsblk,
+            lspci::Lspci,
+            ps::Ps,
+            rickroll::Rickroll,
+            run::Run,
+            threads::Threads,
+        },
+    },
+};
+
+pub type CommandFuture = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+/// something that can be typed at the prompt
+pub struct Command {
+    pub name: &'static str,
+    /// other names it answers to
+    pub aliases: &'static [&'static str],
+    /// one line for `help`
+    pub description: &'static str,
+    /// how to call it, for `help <name>`
+    pub usage: &'static str,
+    /// starts it with its arguments, the shell runs the future as a job
+    pub run: fn(Vec<String>) -> CommandFuture,
+}
+
+/// the command for an application, from the name and help it gives itself, so adding one is just
+/// an entry in `COMMANDS`
+const fn app<A: Application + Send + 'static>() -> Command {
+    Command {
+        name: A::NAME,
+        aliases: A::ALIASES,
+        description: A::DESCRIPTION,
+        usage: A::USAGE,
+        run: run_app::<A>,
+    }
+}
+
+fn run_app<A: Application + Send + 'static>(args: Vec<String>) -> CommandFuture {
+    Box::pin(async move { A::new().run(args).await })
+}
+
+/// every command the shell knows, `help` lists them in this order. the builtins are the ones the
+/// shell runs itself
+pub static COMMANDS: &[Command] = &[
+    // files
+    app::<Ls>(),
+    app::<Cat>(),
+    Command {
+        name: "cd",
+        aliases: &[],
+        description: "changes the current directory",
+        usage: "cd [dir]",
+        run: |args| Box::pin(builtins::cd(args)),
+    },
+    Command {
+        name: "pwd",
+        aliases: &[],
+        description: "prints the current directory",
+        usage: "pwd",
+        run: |args| Box::pin(builtins::pwd(args)),
+    },
+    app::<Mkdir>(),
+    app::<Rm>(),
+    app::<Mv>(),
+    app::<Touch>(),
+    app::<Mount>(),
+    app::<Umount>(),
+    app::<Mounts>(),
+    Command {
+        name: "sync",
+        aliases: &[],
+        description: "writes every filesystem back to its disk",
+        usage: "sync",
+        run: |args| Box::pin(builtins::sync(args)),
+    },
+    app::<Editor>(),
+    // the shell
+    Command {
+        name: "help",
+        aliases: &[],
+        description: "lists commands or explains one",
+        usage: "help [command]",
+        run: |args| Box::pin(builtins::help(args)),
+    },
+    Command {
+        name: "echo",
+        aliases: &[],
+        description: "prints its arguments, or stdin when it is piped",
+        usage: "echo [text]...",
+        run: |args| Box::pin(builtins::echo(args)),
+    },
+    Command {
+        name: "clear",
+        aliases: &[],
+        description: "clears the screen",
+        usage: "clear",
+        run: |args| Box::pin(builtins::clear(args)),
+    },
+    Command {
+        name: "export",
+        aliases: &[],
+        description: "sets environment variables",
+        usage: "export [name[=value]]...",
+        run: |args| Box::pin(builtins::export(args)),
+    },
+    Command {
+        name: "unset",
+        aliases: &[],
+        description: "removes environment variables",
+        usage: "unset <name>...",
+        run: |args| Box::pin(builtins::unset(args)),
+    },
+    Command {
+        name: "env",
+        aliases: &[],
+        description: "lists the environment variables",
+        usage: "env",
+        run: |args| Box::pin(builtins::env(args)),
+    },
+    Command {
+        name: "sh",
+        aliases: &["source"],
+        description: "runs a script, a file of commands",
+        usage: "sh <file> [args...]",
+        run: |args| Box::pin(builtins::sh(args)),
+    },
+    Command {
+        name: "test",
+        aliases: &[],
+        description: "checks files, text or numbers, for `if` and `while`",
+        usage: "test [!] <-e|-f|-d|-z|-n> <text> | <a> <=|!=|-eq|-ne|-lt|-le|-gt|-ge> <b>",
+        run: |args| Box::pin(builtins::test(args)),
+    },
+    Command {
+        name: "[",
+        aliases: &[],
+        description: "`test` with a `]` on the end",
+        usage: "[ <expression> ]",
+        run: |args| Box::pin(builtins::bracket(args)),
+    },
+    Command {
+        name: "true",
+        aliases: &[],
+        description: "does nothing, successfully",
+        usage: "true",
+        run: |args| Box::pin(builtins::status(0, args)),
+    },
+    Command {
+        name: "false",
+        aliases: &[],
+        description: "does nothing, unsuccessfully",
+        usage: "false",
+        run: |args| Box::pin(builtins::status(1, args)),
+    },
+    Command {
+        name: "jobs",
+        aliases: &[],
+        description: "lists background and stopped jobs",
+        usage: "jobs",
+        run: |args| Box::pin(builtins::jobs(args)),
+    },
+    Command {
+        name: "fg",
+        aliases: &[],
+        description: "brings a job to the foreground",
+        usage: "fg [job]",
+        run: |args| Box::pin(builtins::prompt_only("fg", args)),
+    },
+    Command {
+        name: "bg",
+        aliases: &[],
+        description: "carries on a stopped job in the background",
+        usage: "bg [job]",
+        run: |args| Box::pin(builtins::prompt_only("bg", args)),
+    },
+    // the system
+    app::<Run>(),
+    app::<Ps>(),
+    app::<Threads>(),
+    app::<Date>(),
+    app::<Uptime>(),
+    Command {
+        name: "time",
+        aliases: &[],
+        description: "prints the time since boot in timer ticks",
+        usage: "time",
+        run: |args| Box::pin(builtins::time(args)),
+    },
+    app::<Disks>(),
+    app::<Lsblk>(),
+    app::<Lspci>(),
+    app::<CrystalFetch>(),
+    Command {
+        name: "serial",
+        aliases: &[],
+        description: "sends a character down the serial port and prints the reply",
+        usage: "serial",
+        run: |args| Box::pin(builtins::serial(args)),
+    },
+    Command {
+        name: "shutdown",
+        aliases: &[],
+        description: "turns the computer off",
+        usage: "shutdown",
+        run: |args| Box::pin(builtins::shutdown(args)),
+    },
+    Command {
+        name: "reboot",
+        aliases: &[],
+        description: "restarts the computer",
+        usage: "reboot",
+        run: |args| Box::pin(builtins::reboot(args)),
+    },
+    // apps
+    app::<Calculator>(),
+    app::<Grapher>(),
+    app::<Tasks>(),
+    app::<Rickroll>(),
+    app::<GigachadDetector>(),
+    Command {
+        name: "test_features",
+        aliases: &[],
+        description: "shows off the gui widgets",
+        usage: "test_features",
+        run: |args| Box::pin(builtins::test_features(args)),
+    },
+    Command {
+        name: "VGA",
+        aliases: &[],
+        description: "draws a line in 640x480 graphics mode",
+        usage: "VGA",
+        run: |args| Box::pin(builtins::vga(args)),
+    },
+    // games
+    app::<SnakeGame>(),
+    app::<PongGame>(),
+    app::<AsteroidsGame>(),
+    app::<Connect4Game>(),
+    app::<GameBoard>(),
+    app::<GameOfLife>(),
+    // app::<TetrisEngine>(),
+];
+
+/// the command called `name` or with `name` as an alias
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS
+        .iter()
+        .find(|c| c.name == name || c.aliases.contains(&name))
+}
+
+/// every name and alias, for completion
+pub fn names() -> impl Iterator<Item = &'static str> {
+    COMMANDS
+        .iter()
+        .flat_map(|c| core::iter::once(c.name).chain(c.aliases.iter().copied()))
+}
+
+/// how many single letter edits, or swaps of two letters next to each other, turn `a` into `b`
+fn distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+    // d[i][j] is the distance between the first i letters of a and the first j of b
+    let mut d = alloc::vec![alloc::vec![0; b.len() + 1]; a.len() + 1];
+    for i in 0..=a.len() {
+        for j in 0..=b.len() {
+            d[i][j] = match (i, j) {
+                (0, j) => j,
+                (i, 0) => i,
+                (i, j) => {
+                    let cost = (a[i - 1] != b[j - 1]) as usize;
+                    let mut best = (d[i - 1][j] + 1)
+                        .min(d[i][j - 1] + 1)
+                        .min(d[i - 1][j - 1] + cost);
+                    if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                        best = best.min(d[i - 2][j - 2] + 1);
+                    }
+                    best
+                }
+            };
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// the command `name` was most likely meant to be, if any are close enough
+pub fn closest(name: &str) -> Option<&'static str> {
+    names()
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2 && *distance < name.chars().count())
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+#[test_case]
+fn typos_suggest_the_closest_command() {
+    assert_eq!(find("solve").map(|c| c.name), Some("calc"));
+    assert_eq!(closest("sl"), Some("ls"));
+    assert_eq!(closest("lsbkl"), Some("lsblk"));
+    assert_eq!(closest("uptiem"), Some("uptime"));
+    assert_eq!(closest("xyzzy"), None);
+}
@@ -0,0 +1,189 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::future::Future;
+use futures_util::future::{select, Either, FutureExt};
+use spin::Mutex;
+
+use crate::println;
+use crate::std::application::Error;
+use crate::std::io::{self, Screen, Signal, Stdin};
+use crate::std::tasks::{self, JoinHandle};
+
+/// jobs that are running in the background or have been stopped with ctrl+z
+static JOBS: Mutex<Vec<Job>> = Mutex::new(Vec::new());
+
+/// a command running as its own task
+pub struct Job {
+    /// what `fg` and `bg` call it, 0 until it goes into the table
+    number: usize,
+    command: String,
+    handle: JoinHandle<Result<(), Error>>,
+    stopped: bool,
+    /// whether the screen was in application mode when it was stopped
+    app_mode: bool,
+}
+
+/// starts a command as a task, it runs in the background until `foreground` is called on it
+pub fn spawn<F>(command: &str, future: F) -> Job
+where
+    F: Future<Output = Result<(), Error>> + Send + 'static,
+{
+    let name = command.split(' ').next().unwrap_or(command);
+    Job {
+        number: 0,
+        command: command.to_string(),
+        handle: tasks::spawn(name, future),
+        stopped: false,
+        app_mode: false,
+    }
+}
+
+/// puts a job in the table so it can be found again, numbers are reused once a job is done
+fn add(mut job: Job) -> usize {
+    let mut jobs = JOBS.lock();
+    if job.number == 0 {
+        job.number = (1..)
+            .find(|n| !jobs.iter().any(|j| j.number == *n))
+            .unwrap();
+    }
+    let number = job.number;
+    jobs.push(job);
+    number
+}
+
+/// `fg 2` and `fg %2` both mean job 2, no argument means the newest job
+fn take(args: &[String]) -> Result<Job, Error> {
+    let mut jobs = JOBS.lock();
+    let index = match args.first() {
+        None => jobs.len().checked_sub(1),
+        Some(arg) => {
+            let number = arg
+                .trim_start_matches('%')
+                .parse::<usize>()
+                .map_err(|_| Error::CommandFailed(format!("'{}' is not a job number", arg)))?;
+            jobs.iter().position(|j| j.number == number)
+        }
+    };
+    match index {
+        Some(index) => Ok(jobs.remove(index)),
+        None => Err(Error::CommandFailed(String::from("no such job"))),
+    }
+}
+
+/// leaves a job running and goes straight back to the prompt
+pub fn background(job: Job) {
+    let id = job.handle.id();
+    let number = add(job);
+    println!("[{}] {}", number, id);
+}
+
+/// hands the keyboard to a job and waits for it to finish, ctrl+c cancels it and ctrl+z stops
+/// it and puts it in the table
+pub async fn foreground(mut job: Job) -> Result<(), Error> {
+    let shell = tasks::current();
+    // a ctrl+c pressed at the prompt is not meant for this job
+    Stdin::take_signal();
+    io::set_foreground(Some(job.handle.id()));
+
+    let outcome = match select(&mut job.handle, Stdin::signal()).await {
+        Either::Left((result, _)) => Ok(result),
+        Either::Right((signal, _)) => Err(signal),
+    };
+    let result = match outcome {
+        // cancelled from somewhere else, there is nothing to report
+        Ok(result) => result.unwrap_or(Ok(())),
+        Err(Signal::Interrupt) => {
+            job.handle.cancel();
+            // the executor drops the task, and any Display it holds, before this wakes up
+            (&mut job.handle).await.ok();
+            println!("^C");
+            Ok(())
+        }
+        Err(Signal::Suspend) => {
+            tasks::suspend(job.handle.id());
+            job.stopped = true;
+            job.app_mode = matches!(Screen::get_mode(), Screen::Application);
+            Screen::Terminal.set_mode();
+            let command = job.command.clone();
+            let number = add(job);
+            println!("^Z\n[{}]  stopped  {}", number, command);
+            Ok(())
+        }
+    };
+
+    io::set_foreground(shell);
+    result
+}
+
+/// continues a stopped job and gives it back the screen the way it left it
+fn resume(job: &mut Job) {
+    if job.stopped {
+        job.stopped = false;
+        if job.app_mode {
+            Screen::Application.set_mode();
+        }
+        tasks::resume(job.handle.id());
+    }
+}
+
+/// `fg [job]`, brings a job to the foreground
+pub async fn fg(args: &[String]) -> Result<(), Error> {
+    let mut job = take(args)?;
+    println!("{}", job.command);
+    resume(&mut job);
+    foreground(job).await
+}
+
+/// `bg [job]`, lets a stopped job carry on in the background
+pub fn bg(args: &[String]) -> Result<(), Error> {
+    let mut job = take(args)?;
+    if !job.stopped {
+        let number = job.number;
+        add(job);
+        return Err(Error::CommandFailed(format!(
+            "job {} is already running",
+            number
+        )));
+    }
+    // a job in the background doesn't get the screen
+    job.app_mode = false;
+    resume(&mut job);
+    println!("[{}]  {} &", job.number, job.command);
+    add(job);
+    Ok(())
+}
+
+/// `jobs`, lists the jobs in the table
+pub fn list() -> Result<(), Error> {
+    for job in JOBS.lock().iter() {
+        let state = match (job.stopped, job.handle.is_finished()) {
+            (_, true) => "done",
+            (true, false) => "stopped",
+            (false, false) => "running",
+        };
+        println!("[{}]  {:<8} {}", job.number, state, job.command);
+    }
+    Ok(())
+}
+
+/// reports background jobs that have finished since the last prompt
+pub fn reap() {
+    let finished: Vec<Job> = {
+        let mut jobs = JOBS.lock();
+        let (finished, running) = jobs.drain(..).partition(|j| j.handle.is_finished());
+        *jobs = running;
+        finished
+    };
+    for mut job in finished {
+        match (&mut job.handle).now_or_never() {
+            Some(Ok(Err(e))) => {
+                println!("[{}]  failed   {}", job.number, job.command);
+                super::handle_error(e);
+            }
+            _ => println!("[{}]  done     {}", job.number, job.command),
+        }
+    }
+}
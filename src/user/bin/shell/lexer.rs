@@ -0,0 +1,365 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::iter::Peekable;
+use core::ops::Range;
+use core::str::CharIndices;
+
+use super::parser::ParseError;
+use super::script;
+use crate::std::{application::Error, env};
+
+/// a piece of a word, variables are only looked up when the command runs so `$?` and anything
+/// set earlier on the same line are up to date
+#[derive(Debug, Clone, PartialEq)]
+pub enum Part {
+    Literal(String),
+    /// `$NAME`, `${NAME}`, `$?`, or `$1`, `$#` and `$@` for the arguments of a script or function
+    Var(String),
+    /// `$((...))`, the sum as it was typed
+    Arithmetic(String),
+}
+
+/// the value of a variable, the special ones come from the shell rather than the environment
+pub fn var(name: &str, args: &[String]) -> String {
+    match name {
+        "?" => super::last_status().to_string(),
+        "#" => args.len().to_string(),
+        "@" => args.join(" "),
+        name => match name.parse::<usize>() {
+            Ok(n) => n
+                .checked_sub(1)
+                .and_then(|i| args.get(i))
+                .cloned()
+                .unwrap_or_default(),
+            Err(_) => env::var(name).unwrap_or_default(),
+        },
+    }
+}
+
+/// `?`, `#`, `@` and numbers are variables the shell fills in itself
+fn is_special(name: &str) -> bool {
+    matches!(name, "?" | "#" | "@")
+        || (!name.is_empty() && name.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// a word as it was typed, with the quotes and escapes already taken out
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Word {
+    parts: Vec<Part>,
+}
+
+impl Word {
+    fn push(&mut self, c: char) {
+        match self.parts.last_mut() {
+            Some(Part::Literal(text)) => text.push(c),
+            _ => self.parts.push(Part::Literal(String::from(c))),
+        }
+    }
+
+    /// the word with its variables and sums filled in, `args` are what `$1` onwards refer to.
+    /// unlike a unix shell the value of a variable is never split into more words, so it doesn't
+    /// need quoting
+    pub fn expand(&self, args: &[String]) -> Result<String, Error> {
+        let mut text = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => text.push_str(literal),
+                Part::Var(name) => text.push_str(&var(name, args)),
+                Part::Arithmetic(sum) => text.push_str(&script::arithmetic(sum, args)?),
+            }
+        }
+        Ok(text)
+    }
+
+    /// the word if nothing in it needs expanding
+    pub fn literal(&self) -> Option<&str> {
+        match self.parts.as_slice() {
+            [] => Some(""),
+            [Part::Literal(text)] => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// expands every word, `$@` on its own is the exception to words never being split and gives
+/// one word for each argument
+pub fn expand_words(words: &[Word], args: &[String]) -> Result<Vec<String>, Error> {
+    let mut expanded = Vec::new();
+    for word in words {
+        match word.parts.as_slice() {
+            [Part::Var(name)] if name == "@" => expanded.extend(args.iter().cloned()),
+            _ => expanded.push(word.expand(args)?),
+        }
+    }
+    Ok(expanded)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(Word),
+    /// `|`
+    Pipe,
+    /// `&&`
+    And,
+    /// `||`
+    Or,
+    /// `;`
+    Semicolon,
+    /// `&`
+    Background,
+    /// `>`
+    Write,
+    /// `>>`
+    Append,
+    /// `<`
+    Read,
+    /// the end of a line in a script, which ends a statement like `;` does
+    Newline,
+}
+
+struct Lexer<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    end: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn next_if_eq(&mut self, expected: char) -> bool {
+        self.chars.next_if(|(_, c)| *c == expected).is_some()
+    }
+
+    fn position(&mut self) -> usize {
+        self.chars.peek().map_or(self.end, |(i, _)| *i)
+    }
+
+    /// reads a variable after a `$`, a `$` that isn't followed by a name is just a `$`
+    fn variable(&mut self, word: &mut Word) -> Result<(), ParseError> {
+        if let Some((_, c)) = self
+            .chars
+            .next_if(|(_, c)| "?#@".contains(*c) || c.is_ascii_digit())
+        {
+            word.parts.push(Part::Var(String::from(c)));
+            return Ok(());
+        }
+        if self.next_if_eq('(') {
+            return self.arithmetic(word);
+        }
+        if self.next_if_eq('{') {
+            let mut name = String::new();
+            loop {
+                match self.chars.next() {
+                    Some((_, '}')) => break,
+                    Some((_, c)) => name.push(c),
+                    None => return Err(ParseError::Unterminated('{')),
+                }
+            }
+            if !is_special(&name) && !env::is_valid_name(&name) {
+                return Err(ParseError::BadSubstitution(name));
+            }
+            word.parts.push(Part::Var(name));
+            return Ok(());
+        }
+        let mut name = String::new();
+        while let Some((_, c)) = self
+            .chars
+            .next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+        {
+            name.push(c);
+        }
+        match env::is_valid_name(&name) {
+            true => word.parts.push(Part::Var(name)),
+            false => {
+                word.push('$');
+                name.chars().for_each(|c| word.push(c));
+            }
+        }
+        Ok(())
+    }
+
+    /// the rest of a `$((...))` after the `$(`, the brackets inside it have to match up
+    fn arithmetic(&mut self, word: &mut Word) -> Result<(), ParseError> {
+        // there is no `$(command)`, only sums
+        if !self.next_if_eq('(') {
+            return Err(ParseError::Unexpected(String::from("$(")));
+        }
+        let mut sum = String::new();
+        let mut depth = 0;
+        loop {
+            match self.chars.next() {
+                Some((_, ')')) if depth == 0 && self.next_if_eq(')') => break,
+                Some((_, c)) => {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    sum.push(c);
+                }
+                None => return Err(ParseError::Unterminated('(')),
+            }
+        }
+        word.parts.push(Part::Arithmetic(sum));
+        Ok(())
+    }
+
+    /// the rest of a double quoted string, only `$` and a few escapes mean anything in one
+    fn double_quoted(&mut self, word: &mut Word) -> Result<(), ParseError> {
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(()),
+                Some((_, '$')) => self.variable(word)?,
+                Some((_, '\\')) => match self.chars.next_if(|(_, c)| "\"\\$`".contains(*c)) {
+                    Some((_, c)) => word.push(c),
+                    None => word.push('\\'),
+                },
+                Some((_, c)) => word.push(c),
+                None => return Err(ParseError::Unterminated('"')),
+            }
+        }
+    }
+
+    /// reads a word up to the next space or operator
+    fn word(&mut self) -> Result<Word, ParseError> {
+        let mut word = Word::default();
+        while let Some((_, c)) = self
+            .chars
+            .next_if(|(_, c)| !c.is_whitespace() && !"|&;<>".contains(*c))
+        {
+            match c {
+                '\'' => loop {
+                    match self.chars.next() {
+                        Some((_, '\'')) => break,
+                        Some((_, c)) => word.push(c),
+                        None => return Err(ParseError::Unterminated('\'')),
+                    }
+                },
+                '"' => self.double_quoted(&mut word)?,
+                '\\' => match self.chars.next() {
+                    Some((_, c)) => word.push(c),
+                    None => return Err(ParseError::UnexpectedEnd),
+                },
+                '$' => self.variable(&mut word)?,
+                c => word.push(c),
+            }
+        }
+        Ok(word)
+    }
+
+    fn token(&mut self) -> Result<Option<(Token, Range<usize>)>, ParseError> {
+        while self
+            .chars
+            .next_if(|(_, c)| c.is_whitespace() && *c != '\n')
+            .is_some()
+        {}
+        // a `#` at the start of a word comments out the rest of the line
+        if self.next_if_eq('#') {
+            while self.chars.next_if(|(_, c)| *c != '\n').is_some() {}
+        }
+        let start = self.position();
+        let token = match self.chars.peek().map(|(_, c)| *c) {
+            None => return Ok(None),
+            Some('\n') => {
+                self.chars.next();
+                Token::Newline
+            }
+            Some('|') | Some('&') | Some(';') | Some('<') | Some('>') => {
+                let (_, c) = self.chars.next().unwrap();
+                match c {
+                    '|' if self.next_if_eq('|') => Token::Or,
+                    '|' => Token::Pipe,
+                    '&' if self.next_if_eq('&') => Token::And,
+                    '&' => Token::Background,
+                    '>' if self.next_if_eq('>') => Token::Append,
+                    '>' => Token::Write,
+                    '<' => Token::Read,
+                    _ => Token::Semicolon,
+                }
+            }
+            Some(_) => Token::Word(self.word()?),
+        };
+        Ok(Some((token, start..self.position())))
+    }
+}
+
+/// splits a line, or a whole script, into words and operators, along with where each one is in
+/// it. operators don't need spaces around them, quote or escape them to use them in a word.
+pub fn tokenise(line: &str) -> Result<Vec<(Token, Range<usize>)>, ParseError> {
+    let mut lexer = Lexer {
+        chars: line.char_indices().peekable(),
+        end: line.len(),
+    };
+    let mut tokens = Vec::new();
+    while let Some(token) = lexer.token()? {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+#[test_case]
+fn quotes_escapes_and_variables_are_lexed() {
+    let words = |line: &str| -> Vec<Word> {
+        tokenise(line)
+            .unwrap()
+            .into_iter()
+            .filter_map(|(token, _)| match token {
+                Token::Word(word) => Some(word),
+                _ => None,
+            })
+            .collect()
+    };
+    let literal = |text: &str| Word {
+        parts: Vec::from([Part::Literal(String::from(text))]),
+    };
+
+    assert_eq!(
+        words(r#"tasks add "buy  milk" 'a|b' c\ d """#),
+        Vec::from([
+            literal("tasks"),
+            literal("add"),
+            literal("buy  milk"),
+            literal("a|b"),
+            literal("c d"),
+            Word::default(),
+        ])
+    );
+    assert_eq!(
+        words(r#"x$HOME/"${A}b" '$B' \$C "\$D" $?"#),
+        Vec::from([
+            Word {
+                parts: Vec::from([
+                    Part::Literal(String::from("x")),
+                    Part::Var(String::from("HOME")),
+                    Part::Literal(String::from("/")),
+                    Part::Var(String::from("A")),
+                    Part::Literal(String::from("b")),
+                ])
+            },
+            literal("$B"),
+            literal("$C"),
+            literal("$D"),
+            Word {
+                parts: Vec::from([Part::Var(String::from("?"))])
+            },
+        ])
+    );
+    assert_eq!(tokenise("echo 'oops"), Err(ParseError::Unterminated('\'')));
+    assert_eq!(
+        tokenise("echo ${1x}"),
+        Err(ParseError::BadSubstitution(String::from("1x")))
+    );
+    assert_eq!(
+        words("$1${10} $((i * (2 + 1))) # a comment"),
+        Vec::from([
+            Word {
+                parts: Vec::from([Part::Var(String::from("1")), Part::Var(String::from("10")),])
+            },
+            Word {
+                parts: Vec::from([Part::Arithmetic(String::from("i * (2 + 1)"))])
+            },
+        ])
+    );
+    let tokens = tokenise("a # b\nc").unwrap();
+    assert_eq!(tokens.len(), 3);
+    assert_eq!(tokens[1], (Token::Newline, 5..6));
+}
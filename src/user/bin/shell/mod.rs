@@ -0,0 +1,430 @@
+pub mod builtins;
+pub mod commands;
+pub mod jobs;
+pub mod lexer;
+pub mod parser;
+pub mod readline;
+pub mod script;
+
+// External crates
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+// Standard library
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicI32, Ordering},
+};
+use futures_util::future::{join, join_all};
+
+// Internal crates
+use crate::{
+    printerr, println,
+    std::{
+        application::{self, Error, Exit},
+        env, fs,
+        io::{self, write, Color, KeyStroke, PipeReader, Stdio},
+        tasks,
+    },
+    user::lib::libgui::{
+        cg_core::{CgComponent, CgKeyboardCapture},
+        cg_widgets::CgDialog,
+    },
+};
+
+use commands::CommandFuture;
+use parser::{Connector, List, Node, Pipeline, Statement};
+use script::Scope;
+
+lazy_static! {
+    pub static ref CMD: Mutex<CommandHandler> = Mutex::new(CommandHandler::new());
+}
+
+/// boilerplate function
+/// may provide other interfacing options later on idk.
+pub async fn command_handler() {
+    eventloop().await;
+}
+
+/// this function starts the shell running, the function will loop repeatedly until the command to shutdown
+/// TODO: implement shutdown command
+pub async fn eventloop() {
+    println!("running!");
+
+    // keyboard input only goes to the shell and whichever job is in the foreground
+    io::set_foreground(tasks::current());
+    init().await;
+    CMD.lock().prompt();
+
+    loop {
+        let history = CMD.lock().history.history.clone();
+        let line = readline::read_line(&history, || CMD.lock().prompt()).await;
+        CMD.lock().current = line;
+        match exec().await {
+            Ok(_) => {
+                ();
+            }
+            Err(e) => {
+                handle_error(e);
+            }
+        };
+        jobs::reap();
+        CMD.lock().prompt();
+    }
+}
+
+/// the script run at boot
+const INIT_SCRIPT: &str = "/etc/init.sh";
+
+const DEFAULT_INIT_SCRIPT: &str = "\
+# run by the shell every time it starts
+crystalfetch
+";
+
+/// runs the init script. the root is a new ramfs every boot, so the default one is written out
+/// first if nothing has put one there
+async fn init() {
+    if !fs::exists(INIT_SCRIPT) {
+        let written = fs::create_dir_all("/etc")
+            .and_then(|_| fs::write(INIT_SCRIPT, DEFAULT_INIT_SCRIPT.as_bytes()));
+        if let Err(e) = written {
+            printerr!("couldn't write {}: {}", INIT_SCRIPT, e);
+            return;
+        }
+    }
+    let job = jobs::spawn(
+        &format!("sh {}", INIT_SCRIPT),
+        script::run_file(INIT_SCRIPT, Vec::new()),
+    );
+    if let Err(e) = jobs::foreground(job).await {
+        handle_error(e);
+    }
+}
+
+fn handle_error(e: Error) {
+    match e {
+        Error::EmptyCommand => {
+            printerr!("empty command");
+        }
+        Error::UnknownCommand(cmd_str) => {
+            printerr!("unknown command: '{}'", cmd_str);
+            if let Some(name) = commands::closest(&cmd_str) {
+                printerr!("did you mean '{}'?", name);
+            }
+        }
+        Error::ApplicationError(e) => {
+            printerr!("application returned error:\n{}", e);
+        }
+        Error::CommandFailed(e) => {
+            printerr!("command failed:\n{}", e);
+        }
+        // the status is in `$?`, there is nothing else to say
+        Error::Exit(_) => {}
+    }
+}
+
+async fn exec() -> Result<(), Error> {
+    let current = core::mem::take(&mut CMD.lock().current);
+    CMD.lock().history.add(&current);
+
+    let nodes = parser::parse(&current)?;
+    if nodes.is_empty() {
+        return Err(Error::EmptyCommand);
+    }
+    for node in nodes {
+        let result = match node {
+            Node::Statement(statement) => exec_statement(statement).await,
+            // a block is one job, so ctrl+c stops the whole loop
+            node => {
+                let scope = Arc::new(Scope {
+                    args: Vec::new(),
+                    foreground: true,
+                });
+                let job = jobs::spawn(current.trim(), script::run_nodes(Vec::from([node]), scope));
+                jobs::foreground(job).await
+            }
+        };
+        LAST_STATUS.store(application::status(&result), Ordering::Relaxed);
+        if let Err(e) = result {
+            handle_error(e);
+        }
+    }
+    Ok(())
+}
+
+/// the exit status of the last command run from the prompt
+pub fn last_status() -> i32 {
+    LAST_STATUS.load(Ordering::Relaxed)
+}
+
+static LAST_STATUS: AtomicI32 = AtomicI32::new(0);
+
+async fn exec_statement(statement: Statement) -> Result<(), Error> {
+    // job control has to run in the shell itself rather than as a job
+    if let Some(command) = statement.list.single() {
+        let args = lexer::expand_words(&command.args, &[])?;
+        match command.name.expand(&[])?.as_str() {
+            "jobs" => return jobs::list(),
+            "fg" => return jobs::fg(&args).await,
+            "bg" => return jobs::bg(&args),
+            _ => {}
+        }
+    }
+
+    let scope = Arc::new(Scope {
+        args: Vec::new(),
+        foreground: !statement.background,
+    });
+    let job = jobs::spawn(&statement.text, run_list(statement.list, scope));
+    if statement.background {
+        jobs::background(job);
+        return Ok(());
+    }
+    jobs::foreground(job).await
+}
+
+/// runs pipelines joined with `&&` and `||`, the result is that of the last one that ran.
+/// `$?` only follows along in the foreground, like in a unix shell.
+async fn run_list(list: List, scope: Arc<Scope>) -> Result<(), Error> {
+    let mut result = run_pipeline(list.first, &scope).await;
+    for (connector, pipeline) in list.rest {
+        if scope.foreground {
+            LAST_STATUS.store(application::status(&result), Ordering::Relaxed);
+        }
+        let run = match connector {
+            Connector::And => result.is_ok(),
+            Connector::Or => result.is_err(),
+        };
+        if run {
+            // `||` carries on after a failure, it should still be seen
+            if let Err(e) = result {
+                handle_error(e);
+            }
+            result = run_pipeline(pipeline, &scope).await;
+        }
+    }
+    result
+}
+
+type Stage = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+/// runs every command in a pipeline at once, the result is that of the last command
+async fn run_pipeline(pipeline: Pipeline, scope: &Scope) -> Result<(), Error> {
+    let count = pipeline.commands.len();
+    let mut stages: Vec<Stage> = Vec::new();
+    let mut files: Vec<Stage> = Vec::new();
+    let mut piped = None;
+
+    for (i, command) in pipeline.commands.into_iter().enumerate() {
+        let mut stdio = Stdio::default();
+        stdio.stdin = piped.take();
+        if i + 1 < count {
+            let (writer, reader) = io::pipe();
+            stdio.stdout = Some(writer);
+            piped = Some(reader);
+        }
+        // redirections win over the pipes either side, like in a unix shell
+        if let Some(path) = &command.stdin {
+            stdio.stdin = Some(PipeReader::from_bytes(&fs::read(
+                &path.expand(&scope.args)?,
+            )?));
+        }
+        if let Some(output) = &command.stdout {
+            let path = output.path.expand(&scope.args)?;
+            let mut file = match output.append {
+                true => fs::File::append(&path)?,
+                false => fs::File::create(&path)?,
+            };
+            let (writer, mut reader) = io::pipe();
+            stdio.stdout = Some(writer);
+            files.push(Box::pin(async move {
+                while let Some(bytes) = reader.read().await {
+                    file.write_all(&bytes)?;
+                }
+                Ok(())
+            }));
+        }
+        let name = command.name.expand(&scope.args)?;
+        let args = lexer::expand_words(&command.args, &scope.args)?;
+        let stage: CommandFuture = match script::function(&name) {
+            Some(body) => script::run_nodes(
+                body,
+                Arc::new(Scope {
+                    args,
+                    foreground: scope.foreground,
+                }),
+            ),
+            None => Box::pin(run(name, args)),
+        };
+        stages.push(Box::pin(io::with_stdio(stdio, stage)));
+    }
+
+    let (mut results, written) = join(join_all(stages), join_all(files)).await;
+    let result = results.pop().unwrap_or(Ok(()));
+    for e in results.into_iter().filter_map(Result::err) {
+        handle_error(e);
+    }
+    written.into_iter().collect::<Result<(), Error>>()?;
+    result
+}
+
+/// runs one command to completion, every command gets its own task
+async fn run(cmd: String, args: Vec<String>) -> Result<(), Error> {
+    // `NAME=value` on its own sets a variable, the same as `export` does
+    if let Some((name, value)) = cmd.split_once('=') {
+        if args.is_empty() && env::is_valid_name(name) {
+            env::set_var(name, value);
+            return Ok(());
+        }
+    }
+    match commands::find(&cmd) {
+        Some(command) => (command.run)(args).await,
+        None => Err(Error::UnknownCommand(cmd)),
+    }
+}
+
+pub struct CommandHandler {
+    current: String,
+    history: CmdHistory,
+}
+
+impl CommandHandler {
+    pub fn new() -> Self {
+        let handler = Self {
+            current: String::new(),
+            history: CmdHistory {
+                history: Vec::new(),
+            },
+        };
+        handler
+    }
+
+    // this function is activated every time the user presses a key on the keyboard
+    // it accesses the queue of keys (a static ref in src/tasks/keyboard.rs)
+
+    // displays a text prompt for the user to type into.
+    // this is a separate function so that it can be developed as necessary later on
+    // TODO: coloured prompt
+
+    pub fn prompt(&self) {
+        write(format_args!("\n Crystal> "), (Color::Cyan, Color::Black));
+    }
+}
+
+struct CmdHistory {
+    history: Vec<String>,
+}
+
+impl CmdHistory {
+    /// blank lines and repeats of the last line aren't worth going back to
+    fn add(&mut self, line: &str) {
+        let line = line.trim();
+        if !line.is_empty() && self.history.last().map(|l| l.as_str()) != Some(line) {
+            self.history.push(line.to_string());
+        }
+    }
+}
+
+async fn setup_ui() {
+    let exit = |x: KeyStroke| match x {
+        KeyStroke::Char('`') => (KeyStroke::None, Exit::Exit),
+        _ => (x, Exit::None),
+    };
+
+    let options = vec![
+        String::from("Nerd Detected"),
+        String::from("Ok Boomer"),
+        String::from("Idefksda"),
+    ];
+
+    let mut dialog = CgDialog::new(
+        String::from("i'd just like to interject for a moment"),
+        String::from("The kernel is an essential part of an operating system, but useless by itself; it can only function in the context of a complete operating system. Linux is normally used in combination with the GNU operating system: the whole system is basically GNU with Linux added, or GNU/Linux. All the so-called Linux distributions are really distributions of GNU/Linux!"),
+        CgDialog::Type::Selection(options.clone()),
+        // CgDialogType::Information
+        // CgDialogType::Confirmation
+    );
+
+    if let Ok(frame) = dialog.render() {
+        frame.write_to_screen().unwrap();
+    }
+    let (_, x) = dialog.keyboard_capture(exit, None).await.unwrap();
+
+    let mut dialog = CgDialog::new(
+        String::from("i'd just like to interject for a moment"),
+        format!("Your response was [{}]", options[x]),
+        CgDialog::Type::Information,
+    );
+
+    if let Ok(frame) = dialog.render() {
+        frame.write_to_screen().unwrap();
+    }
+    dialog.keyboard_capture(exit, None).await.unwrap();
+
+    // let label= Widget::insert(CgLabel::new(
+    //     String::from("test label"),
+    //     Position::new(1, 1),
+    //     40,
+    //     false,
+    // ));
+    //
+    // let textbox = Widget::insert(CgTextBox::new(
+    //     String::from("i'd just like to interject for a moment"),
+    //     String::from("I'd just like to interject for a moment. What you're referring to as Linux, is in fact, GNU/Linux, or as I've recently taken to calling it, GNU plus Linux. Linux is not an operating system unto itself, but rather another free component of a fully functioning GNU system made useful by the GNU corelibs, shell utilities and vital system components comprising a full OS as defined by POSIX. Many computer users run a modified version of the GNU system every day, without realizing it. Through a peculiar turn of events, the version of GNU which is widely used today is often called Linux, and many of its users are not aware that it is basically the GNU system, developed by the GNU Project. There really is a Linux, and these people are using it, but it is just a part of the system they use. Linux is the kernel: the program in the system that allocates the machine's resources to the other programs that you run. The kernel is an essential part of an operating system, but useless by itself; it can only function in the context of a complete operating system. Linux is normally used in combination with the GNU operating system: the whole system is basically GNU with Linux added, or GNU/Linux. All the so-called Linux distributions are really distributions of GNU/Linux!"),
+    //     Position::new(2, 5),
+    //     Dimensions::new(40, 12),
+    //     true,
+    // ));
+    //
+    // let textedit = Widget::insert(CgLineEdit::new(
+    //     Position::new(10, 20),
+    //     60,
+    //     String::from("enter text here >"),
+    // ));
+    //
+    // let statusbar = Widget::insert(CgStatusBar::new(Position::new(0, 0), Dimensions::new(80, 1)));
+    // let container = Widget::insert({
+    //     let mut container = CgContainer::new(
+    //         Position::new(0, 0),
+    //         Dimensions::new(80, 25),
+    //         true,
+    //     );
+    //     container.insert("textbox", textbox);
+    //     container.insert("label", label);
+    //     container.insert("textedit", textedit);
+    //     container.insert("statusbar", statusbar);
+    //     container
+    // });
+    //
+    // if let Ok(frame) = container.render() {
+    //     frame.write_to_screen().unwrap();
+    // } else {
+    //     serial_println!("failed to write to screen");
+    // }
+    //
+    //
+    //
+    //
+    // let container_copy = container.fetch::<CgContainer>().unwrap();
+    // let entry_ref = container_copy.fetch("textedit").unwrap();
+    // let mut entry = entry_ref.fetch::<CgLineEdit>().unwrap();
+    //
+    // while let Ok((string, false)) = entry.input(exit, &entry_ref, &container).await {
+    //     let textbox_ref = container_copy.fetch("textbox").unwrap();
+    //     let mut textbox = textbox_ref.fetch::<CgTextBox>().unwrap();
+    //     textbox.content = string;
+    //     textbox_ref.update(textbox);
+    //     if let Ok(frame) = container.render() {
+    //         frame.write_to_screen().unwrap();
+    //     }
+    // }
+}
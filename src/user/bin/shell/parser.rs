@@ -0,0 +1,491 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+use core::ops::Range;
+
+use super::lexer::{self, Token, Word};
+use crate::std::{application::Error, env};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    /// an operator where a command or file name should be
+    Unexpected(String),
+    /// the line stopped where something else was needed
+    UnexpectedEnd,
+    /// a quote or `${` that was never closed
+    Unterminated(char),
+    /// a `${...}` without a variable name in it
+    BadSubstitution(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Unexpected(token) => write!(f, "syntax error near '{}'", token),
+            ParseError::UnexpectedEnd => write!(f, "syntax error: unexpected end of line"),
+            ParseError::Unterminated(c) => write!(f, "syntax error: unterminated {}", c),
+            ParseError::BadSubstitution(name) => write!(f, "bad substitution: ${{{}}}", name),
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        Error::CommandFailed(e.to_string())
+    }
+}
+
+/// where `>` or `>>` sends a command's output
+#[derive(Debug, Clone, PartialEq)]
+pub struct Output {
+    pub path: Word,
+    pub append: bool,
+}
+
+/// one command with its arguments and redirections
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    pub name: Word,
+    pub args: Vec<Word>,
+    /// the file given with `<`
+    pub stdin: Option<Word>,
+    pub stdout: Option<Output>,
+}
+
+/// commands joined with `|`, each one's output is the next one's input
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Connector {
+    /// `&&`, only runs if the last pipeline succeeded
+    And,
+    /// `||`, only runs if the last pipeline failed
+    Or,
+}
+
+/// pipelines joined with `&&` and `||`
+#[derive(Debug, Clone, PartialEq)]
+pub struct List {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+}
+
+impl List {
+    /// the command if this is just one command on its own
+    pub fn single(&self) -> Option<&Command> {
+        match (self.first.commands.as_slice(), self.rest.is_empty()) {
+            ([command], true) => Some(command),
+            _ => None,
+        }
+    }
+}
+
+/// one thing for the shell to run, separated from the next by `;` or `&`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub list: List,
+    /// ended with `&`
+    pub background: bool,
+    /// the statement as it was typed, near enough, for `jobs` to show
+    pub text: String,
+}
+
+/// one thing in a script, a statement or a block of them
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Statement(Statement),
+    /// `if ...; then ...; elif ...; then ...; else ...; fi`, the body of the first condition that
+    /// succeeds runs
+    If {
+        branches: Vec<(Vec<Node>, Vec<Node>)>,
+        otherwise: Vec<Node>,
+    },
+    /// `while ...; do ...; done`
+    While {
+        condition: Vec<Node>,
+        body: Vec<Node>,
+    },
+    /// `for name in words...; do ...; done`
+    For {
+        name: String,
+        words: Vec<Word>,
+        body: Vec<Node>,
+    },
+    /// `name() { ...; }` or `function name { ...; }`, defines a function rather than running it
+    Function {
+        name: String,
+        body: Vec<Node>,
+    },
+}
+
+/// words that start or end a block, they only count where a command's name would be
+const KEYWORDS: &[&str] = &[
+    "if", "then", "elif", "else", "fi", "while", "for", "in", "do", "done", "function", "{", "}",
+];
+
+struct Parser<'a> {
+    line: &'a str,
+    tokens: Vec<(Token, Range<usize>)>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.position += 1;
+        token
+    }
+
+    /// the text of the tokens from `start` up to where the parser is
+    fn text(&self, start: usize) -> &'a str {
+        match (self.tokens.get(start), self.tokens.get(self.position - 1)) {
+            (Some((_, first)), Some((_, last))) => &self.line[first.start..last.end],
+            _ => "",
+        }
+    }
+
+    fn unexpected(&self) -> ParseError {
+        match self.tokens.get(self.position) {
+            Some((Token::Newline, _)) => ParseError::Unexpected(String::from("newline")),
+            Some((_, span)) => ParseError::Unexpected(self.line[span.clone()].to_string()),
+            None => ParseError::UnexpectedEnd,
+        }
+    }
+
+    fn word(&mut self) -> Result<Word, ParseError> {
+        match self.peek() {
+            Some(Token::Word(_)) => match self.next() {
+                Some(Token::Word(word)) => Ok(word),
+                _ => unreachable!(),
+            },
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn command(&mut self) -> Result<Command, ParseError> {
+        let mut words = Vec::new();
+        let mut stdin = None;
+        let mut stdout = None;
+        loop {
+            match self.peek() {
+                Some(Token::Word(_)) => words.push(self.word()?),
+                Some(Token::Read) => {
+                    self.next();
+                    stdin = Some(self.word()?);
+                }
+                Some(Token::Write) | Some(Token::Append) => {
+                    let append = self.next() == Some(Token::Append);
+                    stdout = Some(Output {
+                        path: self.word()?,
+                        append,
+                    });
+                }
+                _ => break,
+            }
+        }
+        if words.is_empty() {
+            return Err(self.unexpected());
+        }
+        let name = words.remove(0);
+        Ok(Command {
+            name,
+            args: words,
+            stdin,
+            stdout,
+        })
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let mut commands = Vec::from([self.command()?]);
+        while self.peek() == Some(&Token::Pipe) {
+            self.next();
+            commands.push(self.command()?);
+        }
+        Ok(Pipeline { commands })
+    }
+
+    fn list(&mut self) -> Result<List, ParseError> {
+        let first = self.pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let connector = match self.peek() {
+                Some(Token::And) => Connector::And,
+                Some(Token::Or) => Connector::Or,
+                _ => break,
+            };
+            self.next();
+            rest.push((connector, self.pipeline()?));
+        }
+        Ok(List { first, rest })
+    }
+
+    /// a list with the `&` after it, if there is one
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        let start = self.position;
+        let list = self.list()?;
+        let text = self.text(start).to_string();
+        let background = self.peek() == Some(&Token::Background);
+        if background {
+            self.next();
+        }
+        Ok(Statement {
+            list,
+            background,
+            text,
+        })
+    }
+
+    fn keyword(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Word(word)) => KEYWORDS.iter().copied().find(|k| word.literal() == Some(k)),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, keyword: &str) -> Result<(), ParseError> {
+        match self.keyword() == Some(keyword) {
+            true => {
+                self.next();
+                Ok(())
+            }
+            false => Err(self.unexpected()),
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&Token::Newline) {
+            self.next();
+        }
+    }
+
+    /// statements and blocks up to one of the keywords in `end`, which is left for the caller to
+    /// take. with no `end` it goes on to the end of the input and can be empty, a block can't.
+    fn program(&mut self, end: &[&str]) -> Result<Vec<Node>, ParseError> {
+        let mut nodes = Vec::new();
+        loop {
+            self.skip_newlines();
+            match self.keyword() {
+                Some(keyword) if end.contains(&keyword) => break,
+                _ if end.is_empty() && self.peek().is_none() => break,
+                _ => {}
+            }
+            let node = self.node()?;
+            // `&` already ends a statement, anything else needs a `;` or a new line after it
+            let ended = matches!(&node, Node::Statement(statement) if statement.background);
+            nodes.push(node);
+            match self.peek() {
+                Some(Token::Semicolon) | Some(Token::Newline) => {
+                    self.next();
+                }
+                None => {}
+                Some(_) if ended => {}
+                Some(_) => return Err(self.unexpected()),
+            }
+        }
+        if nodes.is_empty() && !end.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(nodes)
+    }
+
+    fn node(&mut self) -> Result<Node, ParseError> {
+        match self.keyword() {
+            Some("if") => self.if_block(),
+            Some("while") => self.while_loop(),
+            Some("for") => self.for_loop(),
+            Some("function") => {
+                self.next();
+                match self.function_name(false) {
+                    Some(name) => self.function(name),
+                    None => Err(self.unexpected()),
+                }
+            }
+            // a keyword that ends a block where it doesn't belong
+            Some(_) => Err(self.unexpected()),
+            None => match self.function_name(true) {
+                Some(name) => self.function(name),
+                None => Ok(Node::Statement(self.statement()?)),
+            },
+        }
+    }
+
+    fn if_block(&mut self) -> Result<Node, ParseError> {
+        let mut branches = Vec::new();
+        let mut otherwise = Vec::new();
+        // `if` the first time round and `elif` after that
+        while branches.is_empty() || self.keyword() == Some("elif") {
+            self.next();
+            let condition = self.program(&["then"])?;
+            self.expect("then")?;
+            let body = self.program(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+        }
+        if self.keyword() == Some("else") {
+            self.next();
+            otherwise = self.program(&["fi"])?;
+        }
+        self.expect("fi")?;
+        Ok(Node::If {
+            branches,
+            otherwise,
+        })
+    }
+
+    fn while_loop(&mut self) -> Result<Node, ParseError> {
+        self.next();
+        let condition = self.program(&["do"])?;
+        self.expect("do")?;
+        let body = self.program(&["done"])?;
+        self.expect("done")?;
+        Ok(Node::While { condition, body })
+    }
+
+    fn for_loop(&mut self) -> Result<Node, ParseError> {
+        self.next();
+        let name = match self.peek() {
+            Some(Token::Word(word)) => word.literal().filter(|name| env::is_valid_name(name)),
+            _ => None,
+        }
+        .map(|name| name.to_string())
+        .ok_or_else(|| self.unexpected())?;
+        self.next();
+        self.expect("in")?;
+        let mut words = Vec::new();
+        while let Some(Token::Word(_)) = self.peek() {
+            words.push(self.word()?);
+        }
+        match self.peek() {
+            Some(Token::Semicolon) | Some(Token::Newline) => self.next(),
+            _ => return Err(self.unexpected()),
+        };
+        self.skip_newlines();
+        self.expect("do")?;
+        let body = self.program(&["done"])?;
+        self.expect("done")?;
+        Ok(Node::For { name, words, body })
+    }
+
+    /// takes the name at the start of `name() {`, after `function` the `()` can be left off
+    fn function_name(&mut self, needs_brackets: bool) -> Option<String> {
+        let literal = |i: usize| match self.tokens.get(i) {
+            Some((Token::Word(word), _)) => word.literal(),
+            _ => None,
+        };
+        let word = literal(self.position)?;
+        let (name, length) = match word.strip_suffix("()") {
+            Some(name) => (name, 1),
+            None if literal(self.position + 1) == Some("()") => (word, 2),
+            None if !needs_brackets => (word, 1),
+            None => return None,
+        };
+        if !env::is_valid_name(name) {
+            return None;
+        }
+        let name = name.to_string();
+        self.position += length;
+        Some(name)
+    }
+
+    /// the body of a function, after its name
+    fn function(&mut self, name: String) -> Result<Node, ParseError> {
+        self.skip_newlines();
+        self.expect("{")?;
+        let body = self.program(&["}"])?;
+        self.expect("}")?;
+        Ok(Node::Function { name, body })
+    }
+}
+
+/// parses a line of input or a whole script, an empty one gives nothing to run
+pub fn parse(line: &str) -> Result<Vec<Node>, ParseError> {
+    Parser {
+        line,
+        tokens: lexer::tokenise(line)?,
+        position: 0,
+    }
+    .program(&[])
+}
+
+#[test_case]
+fn lines_are_split_into_pipelines_and_lists() {
+    let statements = parse("cat<in.txt|echo>>out.txt && ls; calc 2+2 &")
+        .unwrap()
+        .into_iter()
+        .map(|node| match node {
+            Node::Statement(statement) => statement,
+            node => panic!("{:?} is not a statement", node),
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(statements.len(), 2);
+    let first = &statements[0];
+    assert_eq!(first.text, "cat<in.txt|echo>>out.txt && ls");
+    assert!(!first.background);
+    let commands = &first.list.first.commands;
+    assert_eq!(
+        commands[0].stdin.as_ref().and_then(Word::literal),
+        Some("in.txt")
+    );
+    let stdout = commands[1].stdout.as_ref().unwrap();
+    assert_eq!(
+        (stdout.path.literal(), stdout.append),
+        (Some("out.txt"), true)
+    );
+    assert_eq!(first.list.rest[0].0, Connector::And);
+    assert!(statements[1].background);
+    assert_eq!(
+        statements[1]
+            .list
+            .single()
+            .map(|c| lexer::expand_words(&c.args, &[]).unwrap()),
+        Some(Vec::from([String::from("2+2")]))
+    );
+    assert_eq!(parse("ls |"), Err(ParseError::UnexpectedEnd));
+    assert_eq!(
+        parse("; ls"),
+        Err(ParseError::Unexpected(String::from(";")))
+    );
+}
+
+#[test_case]
+fn scripts_are_parsed_into_blocks() {
+    let nodes = parse(
+        "greet() {\n  echo hi $1\n}\n\
+         for x in a b; do\n  if [ $x = a ]; then greet; elif false; then true; else echo; fi\ndone\n\
+         while false\ndo echo; done # never",
+    )
+    .unwrap();
+    assert_eq!(nodes.len(), 3);
+    match &nodes[0] {
+        Node::Function { name, body } => assert_eq!((name.as_str(), body.len()), ("greet", 1)),
+        node => panic!("{:?} is not a function", node),
+    }
+    match &nodes[1] {
+        Node::For { name, words, body } => {
+            assert_eq!((name.as_str(), words.len()), ("x", 2));
+            match &body[..] {
+                [Node::If {
+                    branches,
+                    otherwise,
+                }] => assert_eq!((branches.len(), otherwise.len()), (2, 1)),
+                body => panic!("{:?} is not an if", body),
+            }
+        }
+        node => panic!("{:?} is not a for loop", node),
+    }
+    assert!(matches!(nodes[2], Node::While { .. }));
+    assert_eq!(parse("if true; then echo"), Err(ParseError::UnexpectedEnd));
+    assert_eq!(
+        parse("while true; do done"),
+        Err(ParseError::Unexpected(String::from("done")))
+    );
+    assert_eq!(parse("fi"), Err(ParseError::Unexpected(String::from("fi"))));
+}
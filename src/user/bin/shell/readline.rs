@@ -0,0 +1,282 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::print;
+use crate::std::fs::{self, FileType};
+use crate::std::io::{KeyStroke, Screen, Stdin};
+
+const CTRL_A: char = '\x01';
+const CTRL_E: char = '\x05';
+const CTRL_R: char = '\x12';
+const ESCAPE: char = '\x1b';
+const DELETE: char = '\x7f';
+
+/// the line being edited at the prompt and how much of it is on the screen
+struct Editor<'a> {
+    history: &'a [String],
+    prompt: fn(),
+    line: Vec<char>,
+    cursor: usize,
+    /// how many characters have been printed since the prompt
+    shown: usize,
+    /// the history entry being shown, `history.len()` is the line being typed
+    entry: usize,
+    /// the line that was being typed before going up through the history
+    draft: Vec<char>,
+}
+
+impl<'a> Editor<'a> {
+    /// replaces whatever is after the prompt with `text`, with the cursor `back` from its end
+    fn show(&mut self, text: &str, back: usize) {
+        Screen::backspace(self.shown);
+        print!("{}", text);
+        self.shown = text.chars().count();
+        Screen::set_cursor_offset(back);
+    }
+
+    fn redraw(&mut self) {
+        let text: String = self.line.iter().collect();
+        self.show(&text, self.line.len() - self.cursor);
+    }
+
+    fn set_line(&mut self, line: Vec<char>) {
+        self.cursor = line.len();
+        self.line = line;
+        self.redraw();
+    }
+
+    fn move_to(&mut self, cursor: usize) {
+        self.cursor = cursor.min(self.line.len());
+        Screen::set_cursor_offset(self.line.len() - self.cursor);
+    }
+
+    fn insert(&mut self, text: &str) {
+        for c in text.chars() {
+            self.line.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+        // typing at the end of the line is most of it, so it doesn't need a redraw
+        if self.cursor == self.line.len() && self.shown + text.chars().count() == self.cursor {
+            print!("{}", text);
+            self.shown = self.cursor;
+        } else {
+            self.redraw();
+        }
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.line.remove(self.cursor);
+            self.redraw();
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.line.len() {
+            self.line.remove(self.cursor);
+            self.redraw();
+        }
+    }
+
+    /// shows another history entry, the line being typed is kept to come back down to
+    fn recall(&mut self, entry: usize) {
+        if entry > self.history.len() || entry == self.entry {
+            return;
+        }
+        if self.entry == self.history.len() {
+            self.draft = self.line.clone();
+        }
+        self.entry = entry;
+        let line = match self.history.get(entry) {
+            Some(line) => line.chars().collect(),
+            None => self.draft.clone(),
+        };
+        self.set_line(line);
+    }
+
+    /// ctrl+r, searches back through the history for a line containing what is typed. ctrl+r
+    /// again finds an older one and escape gives up. any other key picks the match and is
+    /// handed back to be handled as normal, so enter runs it straight away.
+    async fn search(&mut self) -> Option<KeyStroke> {
+        let history = self.history;
+        let mut query = String::new();
+        let mut found: Option<usize> = None;
+        let find = |query: &str, before: usize| {
+            history[..before]
+                .iter()
+                .rposition(|line| line.contains(query))
+        };
+        loop {
+            let matched = found.map_or("", |i| history[i].as_str());
+            self.show(&format!("(reverse-i-search)`{}': {}", query, matched), 0);
+            match Stdin::keystroke().await {
+                KeyStroke::Char(CTRL_R) => {
+                    let before = found.unwrap_or(history.len());
+                    found = find(&query, before).or(found);
+                }
+                KeyStroke::Char(Stdin::BACKSPACE) | KeyStroke::Backspace => {
+                    query.pop();
+                    found = find(&query, history.len());
+                }
+                KeyStroke::Char(ESCAPE) | KeyStroke::Escape => {
+                    self.redraw();
+                    return None;
+                }
+                KeyStroke::Char(c) if !c.is_control() => {
+                    query.push(c);
+                    // the current match is kept for as long as it still matches
+                    let before = found.map_or(history.len(), |i| i + 1);
+                    found = find(&query, before);
+                }
+                key => {
+                    match found {
+                        Some(i) => {
+                            if self.entry == history.len() {
+                                self.draft = self.line.clone();
+                            }
+                            self.entry = i;
+                            self.set_line(history[i].chars().collect());
+                        }
+                        None => self.redraw(),
+                    }
+                    return Some(key);
+                }
+            }
+        }
+    }
+
+    /// tab, completes the word before the cursor as a command name or a path. when there is more
+    /// than one way to go the choices are listed under the line.
+    fn complete(&mut self) {
+        let before: String = self.line[..self.cursor].iter().collect();
+        let start = before
+            .rfind(|c: char| c.is_whitespace() || "|&;<>".contains(c))
+            .map_or(0, |i| i + 1);
+        let word = &before[start..];
+        let is_command = before[..start]
+            .trim_end()
+            .chars()
+            .last()
+            .map_or(true, |c| "|&;".contains(c));
+        let candidates = match is_command {
+            true => complete_command(word),
+            false => complete_path(word),
+        };
+
+        let common = candidates.iter().skip(1).fold(
+            candidates.first().map_or("", |c| c.as_str()),
+            |common, c| {
+                let len = common
+                    .char_indices()
+                    .zip(c.chars())
+                    .take_while(|((_, a), b)| a == b)
+                    .last()
+                    .map_or(0, |((i, a), _)| i + a.len_utf8());
+                &common[..len]
+            },
+        );
+        let mut insert = common.get(word.len()..).unwrap_or("").replace(' ', "\\ ");
+        if let [only] = candidates.as_slice() {
+            if !only.ends_with('/') {
+                insert.push(' ');
+            }
+        }
+        if !insert.is_empty() {
+            self.insert(&insert);
+            return;
+        }
+        if candidates.len() > 1 {
+            // paths are listed without the directory that was typed
+            let dir = match is_command {
+                true => 0,
+                false => word.rfind('/').map_or(0, |i| i + 1),
+            };
+            let names = candidates.iter().map(|c| &c[dir..]).collect::<Vec<_>>();
+            Screen::set_cursor_offset(0);
+            print!("\n{}\n", names.join("  "));
+            (self.prompt)();
+            self.shown = 0;
+            self.redraw();
+        }
+    }
+}
+
+/// every command that starts with `word`
+fn complete_command(word: &str) -> Vec<String> {
+    let mut names = super::commands::names()
+        .filter(|name| name.starts_with(word))
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// every file in the directory `word` points into whose name starts with the rest of it,
+/// directories end with `/`. files starting with `.` only show up when asked for.
+fn complete_path(word: &str) -> Vec<String> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => word.split_at(i + 1),
+        None => ("", word),
+    };
+    let path = match dir {
+        "" => fs::current_dir(),
+        dir => String::from(dir),
+    };
+    let mut paths = fs::read_dir(&path)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|entry| entry.name.starts_with(prefix))
+        .filter(|entry| !entry.name.starts_with('.') || prefix.starts_with('.'))
+        .map(|entry| match entry.file_type {
+            FileType::Directory => format!("{}{}/", dir, entry.name),
+            FileType::File => format!("{}{}", dir, entry.name),
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+/// reads a line at the prompt with the cursor keys, home and end (or ctrl+a and ctrl+e), up and
+/// down through `history`, ctrl+r to search it and tab to complete. `prompt` is printed again
+/// when completions are listed.
+pub async fn read_line(history: &[String], prompt: fn()) -> String {
+    let mut editor = Editor {
+        history,
+        prompt,
+        line: Vec::new(),
+        cursor: 0,
+        shown: 0,
+        entry: history.len(),
+        draft: Vec::new(),
+    };
+    let mut pending = None;
+    loop {
+        let key = match pending.take() {
+            Some(key) => key,
+            None => Stdin::keystroke().await,
+        };
+        match key {
+            KeyStroke::Char('\n') | KeyStroke::Enter => {
+                print!("\n");
+                return editor.line.into_iter().collect();
+            }
+            KeyStroke::Char(Stdin::BACKSPACE) | KeyStroke::Backspace => editor.backspace(),
+            KeyStroke::Char(DELETE) | KeyStroke::Del => editor.delete(),
+            KeyStroke::Left => editor.move_to(editor.cursor.saturating_sub(1)),
+            KeyStroke::Right => editor.move_to(editor.cursor + 1),
+            KeyStroke::Home | KeyStroke::Char(CTRL_A) => editor.move_to(0),
+            KeyStroke::End | KeyStroke::Char(CTRL_E) => editor.move_to(editor.line.len()),
+            KeyStroke::Up => editor.recall(editor.entry.saturating_sub(1)),
+            KeyStroke::Down => editor.recall(editor.entry + 1),
+            KeyStroke::Char(CTRL_R) => pending = editor.search().await,
+            KeyStroke::Char('\t') => editor.complete(),
+            KeyStroke::Char(c) if !c.is_control() => editor.insert(c.encode_utf8(&mut [0; 4])),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::Ordering;
+use spin::Mutex;
+
+use super::commands::CommandFuture;
+use super::parser::{self, Node};
+use super::{handle_error, jobs, lexer, LAST_STATUS};
+use crate::{
+    std::{
+        application::{self, Application, Error},
+        env, fs, tasks,
+    },
+    user::bin::apps::calc::Calculator,
+};
+
+/// functions are shared by every script and the prompt, like the environment variables are
+static FUNCTIONS: Mutex<BTreeMap<String, Vec<Node>>> = Mutex::new(BTreeMap::new());
+
+/// what a block of commands runs with
+pub struct Scope {
+    /// what the script or function was called with, `$1` onwards
+    pub args: Vec<String>,
+    /// whether `$?` follows along, it doesn't for jobs in the background
+    pub foreground: bool,
+}
+
+/// the body of the function called `name`, if there is one
+pub fn function(name: &str) -> Option<Vec<Node>> {
+    FUNCTIONS.lock().get(name).cloned()
+}
+
+/// runs nodes one after another. like in a unix shell a failure is reported and the rest carry
+/// on, the result is that of the last one.
+pub fn run_nodes(nodes: Vec<Node>, scope: Arc<Scope>) -> CommandFuture {
+    Box::pin(async move {
+        let mut result = Ok(());
+        for node in nodes {
+            if let Err(e) = result {
+                handle_error(e);
+            }
+            result = run_node(node, scope.clone()).await;
+            if scope.foreground {
+                LAST_STATUS.store(application::status(&result), Ordering::Relaxed);
+            }
+        }
+        result
+    })
+}
+
+async fn run_node(node: Node, scope: Arc<Scope>) -> Result<(), Error> {
+    match node {
+        Node::Statement(statement) if statement.background => {
+            let scope = Arc::new(Scope {
+                args: scope.args.clone(),
+                foreground: false,
+            });
+            let job = jobs::spawn(&statement.text, super::run_list(statement.list, scope));
+            jobs::background(job);
+            Ok(())
+        }
+        Node::Statement(statement) => super::run_list(statement.list, scope).await,
+        Node::If {
+            branches,
+            otherwise,
+        } => {
+            for (condition, body) in branches {
+                if holds(condition, &scope).await {
+                    return run_nodes(body, scope).await;
+                }
+            }
+            run_nodes(otherwise, scope).await
+        }
+        Node::While { condition, body } => {
+            while holds(condition.clone(), &scope).await {
+                if let Err(e) = run_nodes(body.clone(), scope.clone()).await {
+                    handle_error(e);
+                }
+                // a loop of commands that never wait would keep everything else, ctrl+c
+                // included, from running
+                tasks::yield_now().await;
+            }
+            Ok(())
+        }
+        Node::For { name, words, body } => {
+            for value in lexer::expand_words(&words, &scope.args)? {
+                env::set_var(&name, &value);
+                if let Err(e) = run_nodes(body.clone(), scope.clone()).await {
+                    handle_error(e);
+                }
+                tasks::yield_now().await;
+            }
+            Ok(())
+        }
+        Node::Function { name, body } => {
+            FUNCTIONS.lock().insert(name, body);
+            Ok(())
+        }
+    }
+}
+
+/// runs the condition of an `if` or `while`, which holds if the last command in it succeeded
+async fn holds(condition: Vec<Node>, scope: &Arc<Scope>) -> bool {
+    match run_nodes(condition, scope.clone()).await {
+        Ok(()) => true,
+        Err(e) => {
+            handle_error(e);
+            false
+        }
+    }
+}
+
+/// runs a script file, `args` are its `$1` onwards
+pub async fn run_file(path: &str, args: Vec<String>) -> Result<(), Error> {
+    let script = fs::read_to_string(path)?;
+    let nodes =
+        parser::parse(&script).map_err(|e| Error::CommandFailed(format!("{}: {}", path, e)))?;
+    run_nodes(
+        nodes,
+        Arc::new(Scope {
+            args,
+            foreground: true,
+        }),
+    )
+    .await
+}
+
+/// works out the sum in a `$((...))` with the calculator, so it isn't limited to whole numbers and
+/// `//` is the division that rounds down. variables can be used with or without their `$`, and
+/// one that isn't set counts as 0.
+pub fn arithmetic(sum: &str, args: &[String]) -> Result<String, Error> {
+    let mut expr = String::new();
+    let mut chars = sum.chars().peekable();
+    let is_name = |c: &char| c.is_ascii_alphanumeric() || *c == '_';
+    while let Some(c) = chars.next() {
+        let mut name = String::new();
+        match c {
+            '$' => match chars.next_if(|c| "?#".contains(*c) || c.is_ascii_digit()) {
+                Some(c) => name.push(c),
+                None => {
+                    let braced = chars.next_if_eq(&'{').is_some();
+                    while let Some(c) = chars.next_if(|c| is_name(c) || (braced && *c != '}')) {
+                        name.push(c);
+                    }
+                    if braced {
+                        chars.next();
+                    }
+                }
+            },
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                name.push(c);
+                while let Some(c) = chars.next_if(is_name) {
+                    name.push(c);
+                }
+                // `sqrt(2)` is one of the calculator's functions rather than a variable
+                if chars.peek() == Some(&'(') {
+                    expr.push_str(&name);
+                    continue;
+                }
+            }
+            c => {
+                expr.push(c);
+                continue;
+            }
+        }
+        let value = lexer::var(&name, args);
+        let number = match value.trim() {
+            "" => 0.0,
+            value => value.parse::<f64>().map_err(|_| {
+                Error::CommandFailed(format!("${}: '{}' is not a number", name, value))
+            })?,
+        };
+        expr.push_str(&format!("({})", number));
+    }
+
+    Calculator::new()
+        .calculate(expr)
+        .map(|result| result.to_string())
+        .map_err(|e| Error::CommandFailed(format!("{} in $(({}))", e, sum)))
+}
+
+#[test_case]
+fn sums_use_variables_and_arguments() {
+    env::set_var("SCRIPT_TEST_N", "4");
+    let args = Vec::from([String::from("3")]);
+    assert_eq!(arithmetic("SCRIPT_TEST_N * $1 - 2", &args).unwrap(), "10");
+    assert_eq!(arithmetic("${SCRIPT_TEST_N} / 8", &[]).unwrap(), "0.5");
+    assert_eq!(arithmetic("SCRIPT_TEST_UNSET + 1", &[]).unwrap(), "1");
+    assert!(arithmetic("1 / 0", &[]).is_err());
+    env::set_var("SCRIPT_TEST_N", "four");
+    assert!(arithmetic("SCRIPT_TEST_N + 1", &[]).is_err());
+    env::remove_var("SCRIPT_TEST_N");
+}
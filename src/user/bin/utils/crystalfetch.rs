@@ -5,7 +5,7 @@ use crate::println;
 use crate::std::{
     application::{Application, Error},
     io::{write, Color, Screen},
-    os::OS,
+    os::{self, OS},
 };
 
 const _CRYSTAL_LOGO: &str = "\n  
@@ -51,6 +51,10 @@ pub struct CrystalFetch {}
 
 #[async_trait]
 impl Application for CrystalFetch {
+    const NAME: &'static str = "crystalfetch";
+    const DESCRIPTION: &'static str = "shows the logo and some system information";
+    const USAGE: &'static str = "crystalfetch";
+
     fn new() -> Self {
         Self {}
     }
@@ -61,14 +65,32 @@ impl Application for CrystalFetch {
 
         Screen::clear();
 
+        let memory = match os::memory() {
+            Some(m) => format!(
+                "{} MiB / {} MiB",
+                m.used_bytes() / (1024 * 1024),
+                m.total_bytes() / (1024 * 1024)
+            ),
+            None => String::from("unknown"),
+        };
+        let heap = os::heap();
+        let heap = format!("{} KiB / {} KiB", heap.used / 1024, heap.size / 1024);
+        let clock = match os::tsc_frequency() {
+            Some(hz) => format!("TSC at {} MHz", hz / 1_000_000),
+            None => String::from("PIT ticks"),
+        };
+
         let logo_string = ZXQ5_LOGO;
         let info_string = format!(
             " [   OS      »  {}
  [   BUILD   »  {}
  [   Shell   »  CrySH
+ [   Memory  »  {}
+ [   Heap    »  {}
+ [   Clock   »  {}
  [   Github  »  https://github.com/FantasyPvP/CrystalOS
  [   Author  »  ZXQ5",
-            os, version
+            os, version, memory, heap, clock
         );
 
         // write to output
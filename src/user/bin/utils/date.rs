@@ -0,0 +1,104 @@
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use async_trait::async_trait;
+
+use crate::println;
+use crate::std::application::{Application, Error};
+use crate::std::time::{self, DateTime, Instant, SystemTime};
+
+/// parses a timezone offset like `+01:00`, `-0530` or `2` into minutes east of UTC
+fn parse_offset(s: &str) -> Option<i32> {
+    let (sign, rest) = match s.as_bytes().first()? {
+        b'-' => (-1, &s[1..]),
+        b'+' => (1, &s[1..]),
+        _ => (1, s),
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((h, m)) => (h, m),
+        None if rest.len() > 2 => rest.split_at(rest.len() - 2),
+        None => (rest, "0"),
+    };
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 14 || minutes >= 60 {
+        return None;
+    }
+    Some(sign * (hours * 60 + minutes))
+}
+
+/// prints the date and time, or sets the timezone it is shown in
+pub struct Date {}
+
+#[async_trait]
+impl Application for Date {
+    const NAME: &'static str = "date";
+    const DESCRIPTION: &'static str = "prints the date and time or sets the timezone";
+    const USAGE: &'static str = "date [-u | --unix | -z <offset>]";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, args: Vec<String>) -> Result<(), Error> {
+        let now = SystemTime::now();
+        match args.get(0).map(|s| s.as_str()) {
+            None => {}
+            Some("-u") => {
+                let date = DateTime::utc(now);
+                println!("{} {}", date.weekday(), date);
+                return Ok(());
+            }
+            Some("--unix") => {
+                println!("{}", now.unix());
+                return Ok(());
+            }
+            Some("-z") => {
+                let offset =
+                    args.get(1)
+                        .and_then(|s| parse_offset(s))
+                        .ok_or(Error::CommandFailed(format!(
+                            "usage: {}, e.g. date -z +01:00",
+                            Self::USAGE
+                        )))?;
+                time::set_timezone_offset(offset);
+            }
+            Some(other) => {
+                return Err(Error::CommandFailed(format!(
+                    "unknown option '{}', try -u, --unix or -z <offset>",
+                    other
+                )))
+            }
+        }
+        let date = DateTime::local(now);
+        println!("{} {}", date.weekday(), date);
+        Ok(())
+    }
+}
+
+/// prints how long it has been since boot
+pub struct Uptime {}
+
+#[async_trait]
+impl Application for Uptime {
+    const NAME: &'static str = "uptime";
+    const DESCRIPTION: &'static str = "prints how long it has been since boot";
+    const USAGE: &'static str = "uptime";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, _args: Vec<String>) -> Result<(), Error> {
+        let uptime = Instant::now().since_boot();
+        let secs = uptime.as_secs();
+        let booted = DateTime::local(SystemTime::now() - uptime);
+        println!(
+            "up {}d {:02}h {:02}m {:02}s, since {}",
+            secs / 86_400,
+            secs / 3600 % 24,
+            secs / 60 % 60,
+            secs % 60,
+            booted
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use async_trait::async_trait;
+
+use crate::println;
+use crate::std::application::{Application, Error};
+use crate::std::os;
+
+/// lists the disks attached to the machine, or dumps a sector with `disks read <name> <lba>`
+pub struct Disks {}
+
+#[async_trait]
+impl Application for Disks {
+    const NAME: &'static str = "disks";
+    const DESCRIPTION: &'static str = "lists disks or dumps a sector";
+    const USAGE: &'static str = "disks [read <name> <lba>]";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, args: Vec<String>) -> Result<(), Error> {
+        match args.get(0).map(|s| s.as_str()) {
+            None => {
+                let disks = os::check_ahci();
+                if disks.is_empty() {
+                    println!("no disks found");
+                }
+                for disk in disks {
+                    println!(
+                        "{}  port {}  {:>8} MiB  {} ({})",
+                        disk.name,
+                        disk.port,
+                        disk.sectors * disk.sector_size as u64 / (1024 * 1024),
+                        disk.model,
+                        disk.serial
+                    );
+                }
+                Ok(())
+            }
+            Some("read") => {
+                let disk = args.get(1).ok_or_else(Self::usage)?;
+                let lba = args
+                    .get(2)
+                    .and_then(|s| s.parse::<u64>().ok())
+                    .ok_or_else(Self::usage)?;
+
+                let mut buf = [0u8; 512];
+                os::read_disk(disk, lba, &mut buf)
+                    .map_err(|e| Error::CommandFailed(format!("{}", e)))?;
+
+                // 16 bytes per row so the sector fits in 32 rows of the terminal
+                for (i, row) in buf.chunks(16).enumerate() {
+                    let hex = row
+                        .iter()
+                        .map(|b| format!("{:02x} ", b))
+                        .collect::<String>();
+                    let text = row
+                        .iter()
+                        .map(|b| match b {
+                            0x20..=0x7e => *b as char,
+                            _ => '.',
+                        })
+                        .collect::<String>();
+                    println!("{:04x}  {} {}", i * 16, hex, text);
+                }
+                Ok(())
+            }
+            Some(x) => Err(Error::UnknownCommand(format!("disks {}", x))),
+        }
+    }
+}
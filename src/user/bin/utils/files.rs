@@ -0,0 +1,256 @@
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use async_trait::async_trait;
+
+use crate::std::application::{Application, Error};
+use crate::std::fs::{self, FileType};
+use crate::std::io::{write, Color, Stdin};
+use crate::std::time::DateTime;
+use crate::{print, println};
+
+/// lists the contents of a directory, defaulting to the current directory
+pub struct Ls {}
+
+#[async_trait]
+impl Application for Ls {
+    const NAME: &'static str = "ls";
+    const DESCRIPTION: &'static str = "lists the contents of a directory";
+    const USAGE: &'static str = "ls [dir]";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, args: Vec<String>) -> Result<(), Error> {
+        let path = args.get(0).cloned().unwrap_or(fs::current_dir());
+
+        if fs::metadata(&path)?.is_file() {
+            println!("{}", path);
+            return Ok(());
+        }
+
+        for entry in fs::read_dir(&path)? {
+            match entry.file_type {
+                FileType::Directory => write(
+                    format_args!("{}/\n", entry.name),
+                    (Color::Cyan, Color::Black),
+                ),
+                FileType::File => {
+                    let meta = fs::metadata(&format!("{}/{}", path, entry.name))?;
+                    let modified = match meta.modified.map(DateTime::local) {
+                        Some(t) => format!(
+                            "{:04}-{:02}-{:02} {:02}:{:02}",
+                            t.year, t.month, t.day, t.hour, t.minute
+                        ),
+                        None => String::new(),
+                    };
+                    println!("{:<40} {:>8} B  {}", entry.name, meta.size, modified);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// prints the contents of one or more files, or of stdin when it is piped and no file is given
+pub struct Cat {}
+
+#[async_trait]
+impl Application for Cat {
+    const NAME: &'static str = "cat";
+    const DESCRIPTION: &'static str = "prints files, or stdin when it is piped";
+    const USAGE: &'static str = "cat <file>...";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, args: Vec<String>) -> Result<(), Error> {
+        if args.is_empty() {
+            if !Stdin::is_piped() {
+                return Err(Self::usage());
+            }
+            print!("{}", Stdin::read_to_string().await);
+        }
+        for path in args {
+            println!("{}", fs::read_to_string(&path)?);
+        }
+        Ok(())
+    }
+}
+
+/// creates directories, use -p to create any missing parents
+pub struct Mkdir {}
+
+#[async_trait]
+impl Application for Mkdir {
+    const NAME: &'static str = "mkdir";
+    const DESCRIPTION: &'static str = "creates directories";
+    const USAGE: &'static str = "mkdir [-p] <dir>...";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, args: Vec<String>) -> Result<(), Error> {
+        let parents = args.iter().any(|a| a == "-p");
+        let paths = args.iter().filter(|a| *a != "-p").collect::<Vec<&String>>();
+        if paths.is_empty() {
+            return Err(Self::usage());
+        }
+        for path in paths {
+            if parents {
+                fs::create_dir_all(path)?;
+            } else {
+                fs::create_dir(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// removes files and empty directories
+pub struct Rm {}
+
+#[async_trait]
+impl Application for Rm {
+    const NAME: &'static str = "rm";
+    const DESCRIPTION: &'static str = "removes files and empty directories";
+    const USAGE: &'static str = "rm <path>...";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, args: Vec<String>) -> Result<(), Error> {
+        if args.is_empty() {
+            return Err(Self::usage());
+        }
+        for path in args {
+            fs::remove(&path)?;
+        }
+        Ok(())
+    }
+}
+
+/// moves or renames a file or directory
+pub struct Mv {}
+
+#[async_trait]
+impl Application for Mv {
+    const NAME: &'static str = "mv";
+    const DESCRIPTION: &'static str = "moves or renames a file or directory";
+    const USAGE: &'static str = "mv <from> <to>";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, args: Vec<String>) -> Result<(), Error> {
+        if args.len() != 2 {
+            return Err(Self::usage());
+        }
+
+        // moving onto a directory places the file inside it
+        let mut to = args[1].clone();
+        if let Ok(meta) = fs::metadata(&to) {
+            if meta.is_dir() {
+                let name = args[0]
+                    .trim_end_matches('/')
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or("");
+                to = format!("{}/{}", to, name);
+            }
+        }
+        fs::rename(&args[0], &to)?;
+        Ok(())
+    }
+}
+
+/// creates empty files if they do not already exist
+pub struct Touch {}
+
+#[async_trait]
+impl Application for Touch {
+    const NAME: &'static str = "touch";
+    const DESCRIPTION: &'static str = "creates empty files";
+    const USAGE: &'static str = "touch <file>...";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, args: Vec<String>) -> Result<(), Error> {
+        if args.is_empty() {
+            return Err(Self::usage());
+        }
+        for path in args {
+            fs::File::append(&path)?;
+        }
+        Ok(())
+    }
+}
+
+/// lists mounted filesystems
+pub struct Mounts {}
+
+#[async_trait]
+impl Application for Mounts {
+    const NAME: &'static str = "mounts";
+    const DESCRIPTION: &'static str = "lists mounted filesystems";
+    const USAGE: &'static str = "mounts";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, _args: Vec<String>) -> Result<(), Error> {
+        for (path, name) in fs::mounts() {
+            println!("{:<20} {}", path, name);
+        }
+        Ok(())
+    }
+}
+
+/// mounts a block device onto a directory, `mount` on its own lists mounted filesystems
+pub struct Mount {}
+
+#[async_trait]
+impl Application for Mount {
+    const NAME: &'static str = "mount";
+    const DESCRIPTION: &'static str = "mounts a block device onto a directory";
+    const USAGE: &'static str = "mount [<device> <dir>]";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, args: Vec<String>) -> Result<(), Error> {
+        match args.as_slice() {
+            [] => Mounts::new().run(args).await,
+            [device, path] => Ok(fs::mount(device, path)?),
+            _ => Err(Self::usage()),
+        }
+    }
+}
+
+/// writes back and detaches the filesystem mounted at a directory
+pub struct Umount {}
+
+#[async_trait]
+impl Application for Umount {
+    const NAME: &'static str = "umount";
+    const DESCRIPTION: &'static str = "writes back and detaches a mounted filesystem";
+    const USAGE: &'static str = "umount <dir>";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, args: Vec<String>) -> Result<(), Error> {
+        match args.as_slice() {
+            [path] => Ok(fs::unmount(path)?),
+            _ => Err(Self::usage()),
+        }
+    }
+}
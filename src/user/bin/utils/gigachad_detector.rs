@@ -12,6 +12,10 @@ pub struct GigachadDetector {}
 
 #[async_trait]
 impl Application for GigachadDetector {
+    const NAME: &'static str = "gigachad?";
+    const DESCRIPTION: &'static str = "checks whether users are gigachads";
+    const USAGE: &'static str = "gigachad? <username>...";
+
     fn new() -> Self {
         Self {}
     }
@@ -0,0 +1,81 @@
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use async_trait::async_trait;
+
+use crate::println;
+use crate::std::application::{Application, Error};
+use crate::std::os::{self, PartitionKind};
+
+/// lists disks and the partitions found on them
+pub struct Lsblk {}
+
+#[async_trait]
+impl Application for Lsblk {
+    const NAME: &'static str = "lsblk";
+    const DESCRIPTION: &'static str = "lists disks and their partitions";
+    const USAGE: &'static str = "lsblk";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, _args: Vec<String>) -> Result<(), Error> {
+        let devices = os::block_devices();
+        if devices.is_empty() {
+            println!("no block devices found");
+            return Ok(());
+        }
+
+        let disks = os::check_ahci();
+        println!(
+            "{:<10} {:>10}  {:<4}  {:>10}  {}",
+            "NAME", "SIZE", "TYPE", "START", "DESCRIPTION"
+        );
+        for dev in devices {
+            match dev.partition {
+                None => {
+                    let model = disks
+                        .iter()
+                        .find(|d| d.name == dev.name)
+                        .map(|d| d.model.clone())
+                        .unwrap_or_default();
+                    println!(
+                        "{:<10} {:>10}  {:<4}  {:>10}  {}",
+                        dev.name,
+                        human_size(dev.size),
+                        "disk",
+                        "",
+                        model
+                    );
+                }
+                Some(part) => {
+                    let description = match &part.kind {
+                        PartitionKind::Gpt { name, .. } if !name.is_empty() => {
+                            format!("{} \"{}\"", part.kind.type_name(), name)
+                        }
+                        _ => part.kind.type_name(),
+                    };
+                    println!(
+                        "  {:<8} {:>10}  {:<4}  {:>10}  {}",
+                        dev.name,
+                        human_size(dev.size),
+                        "part",
+                        part.start,
+                        description
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes;
+    let mut unit = 0;
+    while size >= 1024 * 10 && unit < UNITS.len() - 1 {
+        size /= 1024;
+        unit += 1;
+    }
+    format!("{} {}", size, UNITS[unit])
+}
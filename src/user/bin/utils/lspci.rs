@@ -0,0 +1,78 @@
+use alloc::{boxed::Box, string::String, vec::Vec};
+use async_trait::async_trait;
+
+use crate::println;
+use crate::std::application::{Application, Error};
+use crate::std::os::{self, Bar};
+
+/// lists the devices found on the PCI bus, `-v` also shows their BARs and interrupt line
+pub struct Lspci {}
+
+#[async_trait]
+impl Application for Lspci {
+    const NAME: &'static str = "lspci";
+    const DESCRIPTION: &'static str = "lists the devices on the PCI bus";
+    const USAGE: &'static str = "lspci [-v]";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, args: Vec<String>) -> Result<(), Error> {
+        let verbose = args.iter().any(|a| a == "-v");
+
+        for dev in os::pci_devices() {
+            println!(
+                "{:02x}:{:02x}.{} {} [{:02x}{:02x}]: {} {:04x}:{:04x} (rev {:02x})",
+                dev.bus,
+                dev.device,
+                dev.function,
+                dev.class_name(),
+                dev.class,
+                dev.subclass,
+                dev.vendor_name(),
+                dev.vendor_id,
+                dev.device_id,
+                dev.revision,
+            );
+
+            if !verbose {
+                continue;
+            }
+
+            if dev.interrupt_pin != 0 {
+                println!(
+                    "        interrupt: pin {} irq {}",
+                    (b'A' + dev.interrupt_pin - 1) as char,
+                    dev.interrupt_line
+                );
+            }
+            for (i, bar) in dev.bars.iter().enumerate() {
+                match bar {
+                    Some(Bar::Memory {
+                        address,
+                        size,
+                        prefetchable,
+                    }) => println!(
+                        "        BAR{}: memory at {:#x} [size={:#x}]{}",
+                        i,
+                        address,
+                        size,
+                        if *prefetchable { " prefetchable" } else { "" }
+                    ),
+                    Some(Bar::Io { port, size }) => {
+                        println!(
+                            "        BAR{}: io ports at {:#x} [size={:#x}]",
+                            i, port, size
+                        )
+                    }
+                    None => {}
+                }
+            }
+            if let Some(driver) = dev.driver {
+                println!("        driver: {}", driver);
+            }
+        }
+        Ok(())
+    }
+}
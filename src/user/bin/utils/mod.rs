@@ -1,3 +1,11 @@
 pub mod crystalfetch;
+pub mod date;
+pub mod disks;
+pub mod files;
 pub mod gigachad_detector;
+pub mod lsblk;
+pub mod lspci;
+pub mod ps;
 pub mod rickroll;
+pub mod run;
+pub mod threads;
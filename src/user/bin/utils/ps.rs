@@ -0,0 +1,33 @@
+use alloc::{boxed::Box, string::String, vec::Vec};
+use async_trait::async_trait;
+
+use crate::println;
+use crate::std::application::{Application, Error};
+use crate::std::tasks;
+
+/// lists the async tasks on the executor and how often each has been polled
+pub struct Ps {}
+
+#[async_trait]
+impl Application for Ps {
+    const NAME: &'static str = "ps";
+    const DESCRIPTION: &'static str = "lists the tasks on the executor";
+    const USAGE: &'static str = "ps";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, _args: Vec<String>) -> Result<(), Error> {
+        println!("{:>4}  {:<20} {:<10} {:>8}", "ID", "NAME", "STATE", "POLLS");
+        for t in tasks::list() {
+            let state = if t.cancelled {
+                "cancelled"
+            } else {
+                t.state.name()
+            };
+            println!("{:>4}  {:<20} {:<10} {:>8}", t.id, t.name, state, t.polls);
+        }
+        Ok(())
+    }
+}
@@ -47,6 +47,10 @@ pub struct Rickroll {}
 
 #[async_trait]
 impl Application for Rickroll {
+    const NAME: &'static str = "rickroll";
+    const DESCRIPTION: &'static str = "never gonna give you up";
+    const USAGE: &'static str = "rickroll";
+
     fn new() -> Self {
         Self {}
     }
@@ -0,0 +1,49 @@
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use async_trait::async_trait;
+
+use crate::println;
+use crate::std::application::{Application, Error};
+use crate::std::{
+    env, fs,
+    process::{self, Child},
+};
+
+/// kills the program if the job is cancelled, with ctrl+c say, before it has exited
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        // once it has been waited on there is nothing left to kill
+        self.0.kill().ok();
+    }
+}
+
+/// runs a program from the filesystem as its own process and waits for it to exit. static ELF
+/// executables get the rest of the arguments, anything else is treated as a flat binary that
+/// starts at its first byte.
+pub struct Run {}
+
+#[async_trait]
+impl Application for Run {
+    const NAME: &'static str = "run";
+    const DESCRIPTION: &'static str = "runs a program from the filesystem";
+    const USAGE: &'static str = "run <file> [args...]";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, args: Vec<String>) -> Result<(), Error> {
+        let path = args.first().ok_or_else(Self::usage)?;
+
+        let mut env = env::vars_os();
+        env.push(format!("PWD={}", fs::current_dir()));
+        let child = KillOnDrop(process::exec(path, &args, &env)?);
+        let code = child.0.wait().await?;
+        if code != 0 {
+            println!("{} exited with status {}", path, code);
+            return Err(Error::Exit(code as i32));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use async_trait::async_trait;
+
+use crate::println;
+use crate::std::application::{Application, Error};
+use crate::std::thread::{self, ThreadState};
+
+/// lists the kernel threads and what each of them is doing
+pub struct Threads {}
+
+#[async_trait]
+impl Application for Threads {
+    const NAME: &'static str = "threads";
+    const DESCRIPTION: &'static str = "lists the kernel threads";
+    const USAGE: &'static str = "threads";
+
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn run(&mut self, _args: Vec<String>) -> Result<(), Error> {
+        let current = thread::current();
+        println!(
+            "{:>4}  {:>4}  {:<16} {:<10} {:<16} {}",
+            "ID", "PID", "NAME", "STATE", "STACK", "WAITING ON"
+        );
+        for t in thread::list() {
+            let waiting = match t.state {
+                ThreadState::Sleeping(tick) => format!("tick {}", tick),
+                ThreadState::Joining(id) => format!("thread {}", id.as_u64()),
+                _ => String::new(),
+            };
+            let stack = match t.stack {
+                Some((start, end)) => format!("{:#x} {}K", start, (end - start) / 1024),
+                None => String::from("boot"),
+            };
+            let marker = if Some(t.id) == current { "*" } else { " " };
+            println!(
+                "{:>3}{} {:>5}  {:<16} {:<10} {:<16} {}",
+                t.id,
+                marker,
+                t.pid,
+                t.name,
+                t.state.name(),
+                stack,
+                waiting
+            );
+        }
+        Ok(())
+    }
+}